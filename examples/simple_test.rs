@@ -16,7 +16,7 @@ static HID_DEVICE_EVENT_HANDLER: LazyLock<SafeCallback2<u128, Vec<u8>, ()>> = La
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     hid_rs::Hid::init_hid().await?;
     println!("HID initialized.");
-    sayo_api_rs::device::init_sayo_device().await;
+    sayo_api_rs::device::init_sayo_device().await?;
     println!("Sayo device initialized.");
 
     let runtime_handle = tokio::runtime::Handle::current();
@@ -32,14 +32,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     );
                     let device = SayoDeviceApi::from(uuid);
                     match device.get_system_info().await {
-                        Some(sys_info) => println!("System Info: {:?}", sys_info),
-                        None => println!("Failed to fetch system info for {:?}", uuid),
+                        Ok(sys_info) => println!("System Info: {:?}", sys_info),
+                        Err(e) => println!("Failed to fetch system info for {:?}: {}", uuid, e),
                     }
                 });
             }) as Pin<Box<dyn Future<Output = ()> + Send + 'static>>
         },
     ))
-    .await;
+    .await?;
 
     sleep(Duration::from_secs(100)).await;
     Ok(())
@@ -0,0 +1,434 @@
+// 设备配置备份：读出所有可配置项，写成一个带版本号的归档文件，刷固件后用来恢复调校。
+// 归档格式（小端）：
+//   magic "SAYOCFG\0"，格式版本 u16，model_code u16，固件版本 u16，保留 u16，
+//   创建时间 u64（Unix 毫秒），条目数 u32，随后每个条目为 cmd u8、index u8、长度 u32、数据。
+// 条目数据是结构体的原始字节，按读取顺序保存；模拟按键统一保存为 AnalogKeyInfo2（微米），
+// 跨固件版本恢复时不需要换算。字符串（device-name、string、script-name）条目先存一个编码字节
+// （0x02 GB18030、0x03 UTF-16LE，同应答状态），其后为文本字节；版本 1 的归档没有这个字节，
+// 读取时按 UTF-16LE 补上。设备不支持的部分跳过，不计入归档。
+
+use std::fmt;
+use std::io::{Read, Write};
+
+use log::{debug, info};
+
+use crate::byte_converter::Encoding;
+use crate::cross_platform_utils::now_millis;
+use crate::device::{SayoDeviceApi, ScreenLayer};
+use crate::device_constants::*;
+use crate::device_error_handling::DeviceResult;
+use crate::report_codec::ResponseStatus;
+use crate::structures::*;
+use crate::structures_codec::CodecableHidPackage;
+use crate::utility::{invalid_data, le_u16, le_u32, le_u64};
+
+pub const ARCHIVE_MAGIC: [u8; 8] = *b"SAYOCFG\0";
+pub const ARCHIVE_VERSION: u16 = 2;
+// 从该版本起字符串条目带编码字节
+const STRING_ENCODING_VERSION: u16 = 2;
+const HEADER_LEN: usize = 28;
+const ENTRY_HEADER_LEN: usize = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Section {
+    DeviceName,
+    DeviceConfig,
+    RfConfig,
+    KeyInfo,
+    LedInfo,
+    ColorTable,
+    AnalogKey,
+    AdvancedKey,
+    LedEffect,
+    GamePadCfg,
+    AmbientLed,
+    String,
+    ScriptName,
+    Script,
+    DisplayAssets,
+    LcdDrawData(ScreenLayer),
+}
+
+impl Section {
+    // 备份顺序，也是恢复时的写入顺序
+    pub const ALL: [Section; 18] = [
+        Section::DeviceName,
+        Section::DeviceConfig,
+        Section::RfConfig,
+        Section::KeyInfo,
+        Section::LedInfo,
+        Section::ColorTable,
+        Section::AnalogKey,
+        Section::AdvancedKey,
+        Section::LedEffect,
+        Section::GamePadCfg,
+        Section::AmbientLed,
+        Section::String,
+        Section::ScriptName,
+        Section::Script,
+        Section::DisplayAssets,
+        Section::LcdDrawData(ScreenLayer::Bootup),
+        Section::LcdDrawData(ScreenLayer::Main),
+        Section::LcdDrawData(ScreenLayer::Sleep),
+    ];
+
+    // 读写该部分使用的 cmd，同时作为归档中的类型标记
+    pub fn cmd(&self) -> u8 {
+        match self {
+            Section::DeviceName => CMD_DEVICE_NAME,
+            Section::DeviceConfig => CMD_DEVICE_CONFIG,
+            Section::RfConfig => CMD_RF_CONFIG,
+            Section::KeyInfo => CMD_KEY_INFO,
+            Section::LedInfo => CMD_LED_INFO,
+            Section::ColorTable => CMD_COLOR_TABLE,
+            Section::AnalogKey => AnalogKeyInfo2::CMD.expect("No CMD found for AnalogKeyInfo2"),
+            Section::AdvancedKey => {
+                AdvancedKeyBinding::CMD.expect("No CMD found for AdvancedKeyBinding")
+            }
+            Section::LedEffect => CMD_LED_EFFECT,
+            Section::GamePadCfg => GamePadCfg::CMD.expect("No CMD found for GamePadCfg"),
+            Section::AmbientLed => AmbientLED::CMD.expect("No CMD found for AmbientLED"),
+            Section::String => CMD_STRING,
+            Section::ScriptName => CMD_SCRIPT_NAME,
+            Section::Script => SayoScriptContent::CMD.expect("No CMD found for SayoScriptContent"),
+            Section::DisplayAssets => DisplayAssets::CMD.expect("No CMD found for DisplayAssets"),
+            Section::LcdDrawData(layer) => *layer as u8,
+        }
+    }
+
+    pub fn from_cmd(cmd: u8) -> Option<Section> {
+        Section::ALL
+            .into_iter()
+            .find(|section| section.cmd() == cmd)
+    }
+
+    // 比较配置时使用的字节：AnalogKeyInfo2 开头的 raw_data、raw_um、zero_pos 是实时测量值，不参与比较
+    pub fn config_bytes<'a>(&self, data: &'a [u8]) -> &'a [u8] {
+        match self {
+            Section::AnalogKey => data.get(6..).unwrap_or_default(),
+            _ => data,
+        }
+    }
+
+    // 条目以编码字节开头的部分
+    pub fn is_string(&self) -> bool {
+        matches!(
+            self,
+            Section::DeviceName | Section::String | Section::ScriptName
+        )
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Section::DeviceName => "device-name",
+            Section::DeviceConfig => "device-config",
+            Section::RfConfig => "rf-config",
+            Section::KeyInfo => "key-info",
+            Section::LedInfo => "led-info",
+            Section::ColorTable => "color-table",
+            Section::AnalogKey => "analog-key",
+            Section::AdvancedKey => "advanced-key",
+            Section::LedEffect => "led-effect",
+            Section::GamePadCfg => "gamepad-cfg",
+            Section::AmbientLed => "ambient-led",
+            Section::String => "string",
+            Section::ScriptName => "script-name",
+            Section::Script => "script",
+            Section::DisplayAssets => "display-assets",
+            Section::LcdDrawData(ScreenLayer::Bootup) => "lcd-bootup",
+            Section::LcdDrawData(ScreenLayer::Main) => "lcd-main",
+            Section::LcdDrawData(ScreenLayer::Sleep) => "lcd-sleep",
+        }
+    }
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    pub section: Section,
+    pub index: u8,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigArchive {
+    pub model_code: u16,
+    pub firmware_version: u16,
+    // Unix 毫秒
+    pub created_ms: u64,
+    pub entries: Vec<ArchiveEntry>,
+}
+
+impl ConfigArchive {
+    pub fn new(model_code: u16, firmware_version: u16) -> Self {
+        ConfigArchive {
+            model_code,
+            firmware_version,
+            created_ms: now_millis(),
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn entry(&self, section: Section, index: u8) -> Option<&ArchiveEntry> {
+        self.entries
+            .iter()
+            .find(|entry| entry.section == section && entry.index == index)
+    }
+
+    pub fn section(&self, section: Section) -> impl Iterator<Item = &ArchiveEntry> + '_ {
+        self.entries
+            .iter()
+            .filter(move |entry| entry.section == section)
+    }
+
+    pub fn sections(&self) -> Vec<Section> {
+        let mut sections: Vec<Section> = self.entries.iter().map(|entry| entry.section).collect();
+        sections.dedup();
+        sections
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN);
+        bytes.extend_from_slice(&ARCHIVE_MAGIC);
+        bytes.extend_from_slice(&ARCHIVE_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.model_code.to_le_bytes());
+        bytes.extend_from_slice(&self.firmware_version.to_le_bytes());
+        bytes.extend_from_slice(&0u16.to_le_bytes());
+        bytes.extend_from_slice(&self.created_ms.to_le_bytes());
+        bytes.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        for entry in &self.entries {
+            bytes.push(entry.section.cmd());
+            bytes.push(entry.index);
+            bytes.extend_from_slice(&(entry.data.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&entry.data);
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> std::io::Result<Self> {
+        if bytes.get(..ARCHIVE_MAGIC.len()) != Some(&ARCHIVE_MAGIC[..]) {
+            return Err(invalid_data("not a sayo config archive"));
+        }
+        let truncated = || invalid_data("truncated config archive");
+        let version = le_u16(bytes, 8).ok_or_else(truncated)?;
+        if version > ARCHIVE_VERSION {
+            return Err(invalid_data(format!(
+                "config archive version {} is newer than supported {}",
+                version, ARCHIVE_VERSION
+            )));
+        }
+        let mut archive = ConfigArchive {
+            model_code: le_u16(bytes, 10).ok_or_else(truncated)?,
+            firmware_version: le_u16(bytes, 12).ok_or_else(truncated)?,
+            created_ms: le_u64(bytes, 16).ok_or_else(truncated)?,
+            entries: Vec::new(),
+        };
+        let count = le_u32(bytes, 24).ok_or_else(truncated)?;
+        let mut offset = HEADER_LEN;
+        for _ in 0..count {
+            let cmd = *bytes.get(offset).ok_or_else(truncated)?;
+            let section = Section::from_cmd(cmd)
+                .ok_or_else(|| invalid_data(format!("unknown archive section {:#04X}", cmd)))?;
+            let index = *bytes.get(offset + 1).ok_or_else(truncated)?;
+            let len = le_u32(bytes, offset + 2).ok_or_else(truncated)? as usize;
+            let start = offset + ENTRY_HEADER_LEN;
+            let data = bytes.get(start..start + len).ok_or_else(truncated)?;
+            let mut data = data.to_vec();
+            if version < STRING_ENCODING_VERSION && section.is_string() {
+                data.insert(0, u8::from(Encoding::UTF16LE));
+            }
+            archive.entries.push(ArchiveEntry {
+                section,
+                index,
+                data,
+            });
+            offset = start + len;
+        }
+        Ok(archive)
+    }
+
+    pub fn write_to(&self, mut writer: impl Write) -> std::io::Result<()> {
+        writer.write_all(&self.to_bytes())
+    }
+
+    pub fn read_from(mut reader: impl Read) -> std::io::Result<Self> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Self::from_bytes(&bytes)
+    }
+}
+
+// request_all_index、get_all_scripts 从 index 0 连续读到第一个不存在的 index，序号即设备上的 index
+fn indexed(items: impl IntoIterator<Item = Vec<u8>>) -> Vec<(u8, Vec<u8>)> {
+    (0..=u8::MAX).zip(items).collect()
+}
+
+fn packages<T: CodecableHidPackage>(items: Vec<T>) -> Vec<(u8, Vec<u8>)> {
+    indexed(items.iter().map(|item| item.into_vec()))
+}
+
+// 编码字节 + 文本字节，与 StringContent::create 的输入格式相同
+fn string_bytes(item: &StringContent) -> Vec<u8> {
+    let encoding = item
+        .encoding_byte
+        .get()
+        .unwrap_or(u8::from(Encoding::UTF16LE));
+    let mut bytes = vec![encoding];
+    bytes.extend(item.into_vec());
+    bytes
+}
+
+fn strings(items: Vec<StringContent>) -> Vec<(u8, Vec<u8>)> {
+    indexed(items.iter().map(string_bytes))
+}
+
+// 读出一部分配置，按 index 顺序，每项带设备上的 index
+pub(crate) async fn read_section(
+    device: &SayoDeviceApi,
+    section: Section,
+) -> DeviceResult<Vec<(u8, Vec<u8>)>> {
+    Ok(match section {
+        Section::DeviceName => vec![(0, string_bytes(&device.get_device_name_content().await?))],
+        Section::DeviceConfig => vec![(0, device.get_optional_bytes().await?.into_vec())],
+        Section::RfConfig => vec![(0, device.get_rf_config().await?.into_vec())],
+        Section::KeyInfo => packages(device.get_key_infos().await?),
+        Section::LedInfo => packages(device.get_led_infos().await?),
+        Section::ColorTable => packages(device.get_color_tables().await?),
+        Section::AnalogKey => packages(device.get_analog_keys().await?),
+        Section::AdvancedKey => packages(device.get_advanced_keys().await?),
+        Section::LedEffect => vec![(0, device.get_led_effect().await?.into_vec())],
+        Section::GamePadCfg => vec![(0, device.get_gamepad_cfg().await?.into_vec())],
+        Section::AmbientLed => packages(device.get_ambient_leds().await?),
+        Section::String => strings(device.get_strings().await?),
+        Section::ScriptName => strings(device.get_script_names().await?),
+        Section::Script => indexed(
+            device
+                .get_all_scripts()
+                .await?
+                .into_iter()
+                .map(|(_, script)| script.into_vec()),
+        ),
+        Section::DisplayAssets => {
+            // 资源区从 0 连续编号，第一个不存在或长度为 0 的资源区即为末尾
+            let mut res = Vec::new();
+            for index in 0..u8::MAX {
+                match device.get_display_assets(index).await {
+                    Ok((0, _)) => break,
+                    Ok((_, assets)) => res.push((index, assets.into_vec())),
+                    Err(e) if e.status() == Some(ResponseStatus::IndexMissing) => break,
+                    Err(e) => return Err(e),
+                }
+            }
+            res
+        }
+        Section::LcdDrawData(layer) => packages(device.get_lcd_draw_datas(layer).await?),
+    })
+}
+
+// 读出设备的全部配置；不支持的部分跳过，其他错误中止备份
+pub async fn snapshot(device: &SayoDeviceApi) -> DeviceResult<ConfigArchive> {
+    let capabilities = device.capabilities().await?;
+    let mut archive = ConfigArchive::new(capabilities.model_code, capabilities.ver);
+    for section in Section::ALL {
+        let items = match read_section(device, section).await {
+            Ok(items) => items,
+            Err(e) if e.is_unsupported() => {
+                debug!(section = section.name(); "section not supported, skipped: {}", e);
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        for (index, data) in items {
+            archive.entries.push(ArchiveEntry {
+                section,
+                index,
+                data,
+            });
+        }
+    }
+    info!(
+        uuid:% = uuid::Uuid::from_u128(device.get_uuid()),
+        entries = archive.entries.len();
+        "config snapshot done"
+    );
+    Ok(archive)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::byte_converter::RwBytes;
+    use crate::context::SayoContext;
+    use crate::simulator::{VirtualBus, VirtualDevice};
+    use pollster::block_on;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_snapshot_archive() {
+        let uuid = 0x5A10_0000_0000_0000_0000_0000_0000_0022;
+        let bus = Arc::new(VirtualBus::new());
+        let context = SayoContext::new(bus.clone());
+        block_on(context.init()).expect("init");
+        let virtual_device = VirtualDevice::new(uuid);
+        block_on(bus.attach(virtual_device.clone()));
+        let device = context.device(uuid);
+        // 长度为 0 的资源区之后不再读取
+        let assets_cmd = Section::DisplayAssets.cmd();
+        virtual_device.set_region(assets_cmd, 1, 0, Vec::new());
+        virtual_device.set_region(assets_cmd, 2, 64, vec![0x5A; 64]);
+
+        let archive = block_on(snapshot(&device)).expect("snapshot");
+        assert_eq!(
+            (archive.model_code, archive.firmware_version),
+            (0x0106, 130)
+        );
+        assert_eq!(
+            archive.section(Section::KeyInfo).count(),
+            VirtualDevice::KEY_COUNT as usize
+        );
+        let analog = AnalogKeyInfo2::new(RwBytes::new(
+            archive.entry(Section::AnalogKey, 0).unwrap().data.clone(),
+        ));
+        assert_eq!(analog.trigger_level(None), Some(1200));
+        assert!(archive.entry(Section::Script, 0).is_some());
+        // 字符串条目带编码字节
+        for (section, index, encoding) in [
+            (Section::DeviceName, 0, 0x03),
+            (Section::String, 0, 0x03),
+            (Section::String, 1, 0x02),
+            (Section::ScriptName, 0, 0x03),
+        ] {
+            let data = &archive.entry(section, index).unwrap().data;
+            assert_eq!(data[0], encoding);
+            assert_eq!(
+                virtual_device.entry(section.cmd(), index).as_deref(),
+                Some(&data[1..])
+            );
+        }
+        let assets: Vec<u8> = archive
+            .section(Section::DisplayAssets)
+            .map(|entry| entry.index)
+            .collect();
+        assert_eq!(assets, vec![0]);
+        for layer in [ScreenLayer::Bootup, ScreenLayer::Main, ScreenLayer::Sleep] {
+            let section = Section::LcdDrawData(layer);
+            assert_eq!(archive.section(section).count(), 2);
+            assert_eq!(
+                Some(archive.entry(section, 1).unwrap().data.clone()),
+                virtual_device.entry(layer as u8, 1)
+            );
+        }
+
+        let mut file = Vec::new();
+        archive.write_to(&mut file).unwrap();
+        assert_eq!(ConfigArchive::read_from(&file[..]).unwrap(), archive);
+        assert!(ConfigArchive::from_bytes(&file[..file.len() - 1]).is_err());
+        file[8] = 0xFF;
+        assert!(ConfigArchive::from_bytes(&file).is_err());
+    }
+}
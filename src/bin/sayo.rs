@@ -0,0 +1,381 @@
+// sayo 命令行工具：不写 GUI 也能查看和修改客户设备。
+// 结构体以 dissector 的 JSON 形式导出，改写后可按字段写回。
+
+use std::error::Error;
+use std::pin::Pin;
+use std::process::ExitCode;
+
+use futures::StreamExt;
+use sayo_api_rs::backup::{ConfigArchive, Section};
+use sayo_api_rs::byte_converter::RwBytes;
+use sayo_api_rs::device::{self, SayoDeviceApi};
+use sayo_api_rs::diff;
+use sayo_api_rs::dissector::{ApplyJson, Dissect, ToFieldValue};
+use sayo_api_rs::echo;
+use sayo_api_rs::json::{self, JsonValue};
+use sayo_api_rs::report_codec::RequestOptions;
+use sayo_api_rs::restore::RestoreOptions;
+use sayo_api_rs::structures::{DisplayAssets, SayoScriptContent};
+use sayo_api_rs::structures_codec::CodecableHidPackage;
+
+type CliResult<T> = Result<T, Box<dyn Error>>;
+
+const USAGE: &str = "\
+用法: sayo [-e echo] <命令> [参数]
+
+  list                                  列出设备
+  dump  [-d 设备] <结构> [-o 文件]      导出结构体为 JSON
+  write [-d 设备] <结构> <JSON 文件>    按 JSON 字段写回结构体
+  reboot [-d 设备]                      重启
+  bootloader [-d 设备]                  进入 bootloader
+  log [-d 设备]                         持续输出固件日志，Ctrl+C 退出
+  backup [-d 设备] <文件>               备份全部配置到归档文件
+  restore [-d 设备] [-n] [-f] <文件> [部分...]
+                                        从归档恢复有变化的部分，-n 只显示变化，
+                                        -f 型号不同也恢复
+  diff <配置> <配置>                    逐字段比较两份配置，配置为归档文件或 @设备
+  script get [-d 设备] <index> <文件>   下载脚本
+  script put [-d 设备] <index> <文件>   上传脚本
+  assets get [-d 设备] <index> <文件>   下载显示资源
+  assets put [-d 设备] <index> <文件>   上传显示资源
+
+结构: device-info, system-info, key-infos, led-effect, rf-config, gamepad-cfg
+设备: list 输出的序号或 uuid，只连接一个设备时可省略
+部分: backup 模块的部分名，如 key-info、analog-key、script，省略时恢复全部
+echo: 请求使用的 echo（如 0x41），与其他上位机同时连接设备时指定不同的值";
+
+const STRUCTURES: [&str; 6] = [
+    "device-info",
+    "system-info",
+    "key-infos",
+    "led-effect",
+    "rf-config",
+    "gamepad-cfg",
+];
+
+// 已连接设备在订阅连接事件后异步上报，最多等待这么久让其完成注册
+const ATTACH_TIMEOUT_MS: u32 = 1000;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.is_empty() || args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    match pollster::block_on(run(args)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("错误: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+struct Args {
+    device: Option<String>,
+    echo: Option<u8>,
+    output: Option<String>,
+    dry_run: bool,
+    force: bool,
+    positional: Vec<String>,
+}
+
+fn parse_args(args: Vec<String>) -> CliResult<Args> {
+    let mut parsed = Args {
+        device: None,
+        echo: None,
+        output: None,
+        dry_run: false,
+        force: false,
+        positional: Vec::new(),
+    };
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-d" | "--device" => parsed.device = Some(args.next().ok_or("-d 需要设备参数")?),
+            "-e" | "--echo" => {
+                let echo = args.next().ok_or("-e 需要 echo 参数")?;
+                let echo = match echo.strip_prefix("0x").or_else(|| echo.strip_prefix("0X")) {
+                    Some(hex) => u8::from_str_radix(hex, 16)?,
+                    None => echo.parse()?,
+                };
+                if echo == echo::BROADCAST_ECHO {
+                    return Err("echo 0x00 保留给广播".into());
+                }
+                parsed.echo = Some(echo);
+            }
+            "-o" | "--output" => parsed.output = Some(args.next().ok_or("-o 需要文件参数")?),
+            "-n" | "--dry-run" => parsed.dry_run = true,
+            "-f" | "--force" => parsed.force = true,
+            _ => parsed.positional.push(arg),
+        }
+    }
+    Ok(parsed)
+}
+
+fn positional<'a>(args: &'a Args, i: usize, name: &str) -> CliResult<&'a str> {
+    args.positional
+        .get(i)
+        .map(|arg| arg.as_str())
+        .ok_or_else(|| format!("缺少参数 <{}>\n\n{}", name, USAGE).into())
+}
+
+async fn run(args: Vec<String>) -> CliResult<()> {
+    let args = parse_args(args)?;
+    let command = positional(&args, 0, "命令")?.to_string();
+
+    device::init_sayo_device().await?;
+    device::wait_device_list(ATTACH_TIMEOUT_MS).await?;
+
+    match command.as_str() {
+        "list" => list().await,
+        "dump" => {
+            let device = select_device(&args).await?;
+            let json = dump(&device, positional(&args, 1, "结构")?).await?;
+            write_output(&args, json.as_bytes())
+        }
+        "write" => {
+            let device = select_device(&args).await?;
+            let text = std::fs::read_to_string(positional(&args, 2, "JSON 文件")?)?;
+            write(&device, positional(&args, 1, "结构")?, &json::parse(&text)?).await
+        }
+        "reboot" => Ok(select_device(&args).await?.reboot().await?),
+        "bootloader" => Ok(select_device(&args).await?.into_bootloader().await?),
+        "log" => follow_log(&select_device(&args).await?).await,
+        "backup" => {
+            let device = select_device(&args).await?;
+            let path = positional(&args, 1, "文件")?;
+            let archive = device.snapshot().await?;
+            archive.write_to(std::fs::File::create(path)?)?;
+            println!("已备份 {} 项到 {}", archive.entries.len(), path);
+            Ok(())
+        }
+        "restore" => {
+            let device = select_device(&args).await?;
+            let file = std::fs::File::open(positional(&args, 1, "文件")?)?;
+            let archive = ConfigArchive::read_from(file)?;
+            restore(&device, &archive, &args).await
+        }
+        "diff" => {
+            let old = load_config(&args, positional(&args, 1, "配置")?).await?;
+            let new = load_config(&args, positional(&args, 2, "配置")?).await?;
+            print!("{}", diff::diff(&old, &new));
+            Ok(())
+        }
+        "script" | "assets" => {
+            let device = select_device(&args).await?;
+            let index: u8 = positional(&args, 2, "index")?.parse()?;
+            let path = positional(&args, 3, "文件")?;
+            match (command.as_str(), positional(&args, 1, "get|put")?) {
+                ("script", "get") => {
+                    let (_, script) = device.get_script(index).await?;
+                    Ok(std::fs::write(path, script.into_vec())?)
+                }
+                ("script", "put") => {
+                    let script = SayoScriptContent::new(RwBytes::new(std::fs::read(path)?));
+                    device.set_script(index, &script, 0, progress).await?;
+                    Ok(())
+                }
+                ("assets", "get") => {
+                    let (_, assets) = device.get_display_assets(index).await?;
+                    Ok(std::fs::write(path, assets.into_vec())?)
+                }
+                ("assets", "put") => {
+                    let assets = DisplayAssets::new(RwBytes::new(std::fs::read(path)?));
+                    device.set_display_assets(index, &assets, 0, progress).await?;
+                    Ok(())
+                }
+                (_, action) => Err(format!("未知操作: {}", action).into()),
+            }
+        }
+        _ => Err(format!("未知命令: {}\n\n{}", command, USAGE).into()),
+    }
+}
+
+async fn restore(device: &SayoDeviceApi, archive: &ConfigArchive, args: &Args) -> CliResult<()> {
+    let sections = args.positional[2..]
+        .iter()
+        .map(|name| {
+            Section::ALL
+                .into_iter()
+                .find(|section| section.name() == name)
+                .ok_or_else(|| format!("未知部分: {}", name))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let preview = device.preview_restore(archive).await?;
+    print!("{}", preview);
+    if args.dry_run {
+        return Ok(());
+    }
+    let options = RestoreOptions {
+        allow_model_mismatch: args.force,
+        ..Default::default()
+    };
+    let report = device.restore(archive, &sections, options).await?;
+    for (section, index) in &report.skipped {
+        eprintln!("跳过 {}[{}]：设备上不存在", section, index);
+    }
+    let saved = if report.saved { "并保存" } else { "" };
+    println!("已恢复 {} 项{}", report.written.len(), saved);
+    Ok(())
+}
+
+fn progress(value: f32) -> Pin<Box<dyn Future<Output = bool> + Send + 'static>> {
+    eprint!("\r{:5.1}%", value * 100.0);
+    if value >= 1.0 {
+        eprintln!();
+    }
+    Box::pin(async { true })
+}
+
+fn write_output(args: &Args, bytes: &[u8]) -> CliResult<()> {
+    match &args.output {
+        Some(path) => Ok(std::fs::write(path, bytes)?),
+        None => {
+            println!("{}", String::from_utf8_lossy(bytes));
+            Ok(())
+        }
+    }
+}
+
+async fn follow_log(device: &SayoDeviceApi) -> CliResult<()> {
+    let mut logs = device.logs();
+    for entry in device.log_history() {
+        println!("{}", entry);
+    }
+    while let Some(entry) = logs.next().await {
+        match entry {
+            Ok(entry) => println!("{}", entry),
+            Err(lagged) => eprintln!("{}", lagged),
+        }
+    }
+    eprintln!("设备已断开");
+    Ok(())
+}
+
+async fn list() -> CliResult<()> {
+    let devices = device::get_device_list().await?;
+    if devices.is_empty() {
+        println!("未发现设备");
+    }
+    for (i, device) in devices.iter().enumerate() {
+        let id = |value: Result<u16, _>| match value {
+            Ok(value) => format!("{:04X}", value),
+            Err(_) => "????".to_string(),
+        };
+        println!(
+            "{}: {} {}:{} report {:#04X} {}",
+            i,
+            uuid::Uuid::from_u128(device.get_uuid()),
+            id(device.vid()),
+            id(device.pid()),
+            device.get_report_id(),
+            device.get_product_name().unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
+// @设备 读取设备当前配置，否则读取归档文件
+async fn load_config(args: &Args, operand: &str) -> CliResult<ConfigArchive> {
+    match operand.strip_prefix('@') {
+        Some(selector) => {
+            let device = find_device(Some(selector), args.echo).await?;
+            Ok(device.snapshot().await?)
+        }
+        None => Ok(ConfigArchive::read_from(std::fs::File::open(operand)?)?),
+    }
+}
+
+async fn select_device(args: &Args) -> CliResult<SayoDeviceApi> {
+    find_device(args.device.as_deref(), args.echo).await
+}
+
+// echo 只作用于选中设备的句柄
+async fn find_device(selector: Option<&str>, echo: Option<u8>) -> CliResult<SayoDeviceApi> {
+    let options = RequestOptions {
+        echo,
+        ..Default::default()
+    };
+    let devices: Vec<SayoDeviceApi> = device::get_device_list()
+        .await?
+        .into_iter()
+        .map(|device| device.with_options(options))
+        .collect();
+    let Some(selector) = selector else {
+        return match devices.len() {
+            1 => Ok(devices[0].clone()),
+            0 => Err("未发现设备".into()),
+            n => Err(format!("连接了 {} 个设备，请用 -d 指定", n).into()),
+        };
+    };
+    let found = match selector.parse::<usize>() {
+        Ok(i) => devices.get(i).cloned(),
+        Err(_) => {
+            let uuid = uuid::Uuid::parse_str(selector)?.as_u128();
+            devices.into_iter().find(|device| device.get_uuid() == uuid)
+        }
+    };
+    found.ok_or_else(|| format!("设备未找到: {}", selector).into())
+}
+
+fn unknown_structure(name: &str) -> Box<dyn Error> {
+    format!("未知结构: {}，可选 {}", name, STRUCTURES.join(", ")).into()
+}
+
+async fn dump(device: &SayoDeviceApi, name: &str) -> CliResult<String> {
+    let value = match name {
+        "device-info" => device.get_device_info().await?.to_field_value(),
+        "system-info" => device.get_system_info().await?.to_field_value(),
+        "key-infos" => device.get_key_infos().await?.to_field_value(),
+        "led-effect" => device.get_led_effect().await?.to_field_value(),
+        "rf-config" => device.get_rf_config().await?.to_field_value(),
+        "gamepad-cfg" => device.get_gamepad_cfg().await?.to_field_value(),
+        _ => return Err(unknown_structure(name)),
+    };
+    Ok(value.to_json())
+}
+
+// 先读出当前值，只覆盖 JSON 中出现的字段，再整体写回
+async fn write(device: &SayoDeviceApi, name: &str, value: &JsonValue) -> CliResult<()> {
+    match name {
+        "device-info" => {
+            let current = device.get_device_info().await?;
+            current.apply_json(value)?;
+            device.set_device_info(&current).await?;
+        }
+        "system-info" => {
+            let current = device.get_system_info().await?;
+            current.apply_json(value)?;
+            device.set_system_info(&current).await?;
+        }
+        "key-infos" => {
+            let mut key_infos = device.get_key_infos().await?;
+            let before: Vec<Vec<u8>> = key_infos.iter().map(|key_info| key_info.into_vec()).collect();
+            key_infos.apply_json(value)?;
+            // 只写回有改动的按键
+            for (index, (key_info, before)) in key_infos.iter().zip(before).enumerate() {
+                if key_info.into_vec() != before {
+                    device.set_key_info(index as u8, key_info).await?;
+                }
+            }
+        }
+        "led-effect" => {
+            let current = device.get_led_effect().await?;
+            current.apply_json(value)?;
+            device.set_led_effect(&current).await?;
+        }
+        "rf-config" => {
+            let current = device.get_rf_config().await?;
+            current.apply_json(value)?;
+            device.set_rf_config(&current).await?;
+        }
+        "gamepad-cfg" => {
+            let current = device.get_gamepad_cfg().await?;
+            current.apply_json(value)?;
+            device.set_gamepad_cfg(&current).await?;
+        }
+        _ => return Err(unknown_structure(name)),
+    }
+    Ok(())
+}
@@ -0,0 +1,572 @@
+// 广播记录的多订阅分发：每个订阅者有独立的有界队列，
+// 队列满时丢弃最旧的记录并累计丢失数，下次读取时先报告 Lagged。
+// 发布只做入队和唤醒，不在 HID 回调线程里等待订阅者。
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll, Waker};
+
+use futures::Stream;
+
+use crate::cross_platform_utils::now_micros;
+use crate::device_constants::*;
+use crate::device_log;
+use crate::levels::{KeyTravel, decode_levels};
+use crate::structures::{BroadCast, BroadCastData};
+use crate::utility::lock;
+
+pub const DEFAULT_BROADCAST_CAPACITY: usize = 256;
+
+// 订阅者来不及读取而丢弃的记录数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lagged(pub u64);
+
+impl fmt::Display for Lagged {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "广播订阅落后，丢弃了 {} 条记录", self.0)
+    }
+}
+
+impl std::error::Error for Lagged {}
+
+// 发布时的上下文：主机收到报告的时间，以及同一报告中此前出现的设备时间
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordContext {
+    pub received_us: u64,
+    pub device_time_ms: Option<u16>,
+}
+
+struct Queue<T> {
+    items: VecDeque<T>,
+    capacity: usize,
+    lagged: u64,
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+// 每个订阅者在发布时把记录转换成自己的条目，不关心的记录返回 None
+struct Subscriber<T> {
+    queue: Mutex<Queue<T>>,
+    map: fn(&RecordContext, &BroadCastData) -> Option<T>,
+}
+
+trait Deliver: Send + Sync {
+    fn deliver(&self, records: &[(RecordContext, BroadCastData)]);
+
+    fn close(&self);
+}
+
+impl<T> Queue<T> {
+    fn new(capacity: usize) -> Self {
+        Queue {
+            items: VecDeque::new(),
+            capacity: capacity.max(1),
+            lagged: 0,
+            closed: false,
+            waker: None,
+        }
+    }
+
+    fn push(&mut self, item: T) {
+        if self.items.len() >= self.capacity {
+            self.items.pop_front();
+            self.lagged += 1;
+        }
+        self.items.push_back(item);
+    }
+
+    fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T: Send> Deliver for Subscriber<T> {
+    fn deliver(&self, records: &[(RecordContext, BroadCastData)]) {
+        let mut queue = lock(&self.queue);
+        let mut pushed = false;
+        for (context, record) in records {
+            if let Some(item) = (self.map)(context, record) {
+                queue.push(item);
+                pushed = true;
+            }
+        }
+        if pushed {
+            queue.wake();
+        }
+    }
+
+    fn close(&self) {
+        let mut queue = lock(&self.queue);
+        queue.closed = true;
+        queue.wake();
+    }
+}
+
+type Subscribers = HashMap<u128, Vec<Weak<dyn Deliver>>>;
+
+// 一个上下文内各设备的广播订阅者，由 SayoContext 持有；
+// 上下文释放时其下所有流结束，不影响其他上下文的同一 uuid
+#[derive(Default)]
+pub(crate) struct Broadcasts {
+    subscribers: Mutex<Subscribers>,
+}
+
+impl Broadcasts {
+    pub(crate) fn subscribe_with<T: Send + 'static>(
+        &self,
+        uuid: u128,
+        capacity: usize,
+        map: fn(&RecordContext, &BroadCastData) -> Option<T>,
+    ) -> BroadcastStream<T> {
+        let subscriber = Arc::new(Subscriber {
+            queue: Mutex::new(Queue::new(capacity)),
+            map,
+        });
+        let weak: Weak<dyn Deliver> = Arc::downgrade(&subscriber) as Weak<dyn Deliver>;
+        lock(&self.subscribers).entry(uuid).or_default().push(weak);
+        BroadcastStream { subscriber }
+    }
+
+    pub(crate) fn subscribe(&self, uuid: u128, capacity: usize) -> BroadcastStream {
+        // 记录是广播报告的视图，复制后再交给订阅者
+        self.subscribe_with(uuid, capacity, |_, data| {
+            Some(BroadCastData {
+                bytes: data.bytes.deep_clone(),
+            })
+        })
+    }
+
+    // 类型化的广播事件流
+    pub(crate) fn subscribe_events(
+        &self,
+        uuid: u128,
+        capacity: usize,
+    ) -> BroadcastStream<BroadcastEvent> {
+        self.subscribe_with(uuid, capacity, |_, data| Some(BroadcastEvent::from(data)))
+    }
+
+    pub(crate) fn subscriber_count(&self, uuid: u128) -> usize {
+        lock(&self.subscribers)
+            .get(&uuid)
+            .map(|subscribers| subscribers.iter().filter(|s| s.strong_count() > 0).count())
+            .unwrap_or(0)
+    }
+
+    // 把一个广播报告的所有记录分发给该设备的订阅者，顺带清理已释放的订阅
+    pub(crate) fn publish(&self, uuid: u128, records: &[(RecordContext, BroadCastData)]) {
+        let subscribers: Vec<Arc<dyn Deliver>> = {
+            let mut all = lock(&self.subscribers);
+            match all.get_mut(&uuid) {
+                Some(subscribers) => {
+                    subscribers.retain(|s| s.strong_count() > 0);
+                    subscribers.iter().filter_map(|s| s.upgrade()).collect()
+                }
+                None => Vec::new(),
+            }
+        };
+        for subscriber in subscribers {
+            subscriber.deliver(records);
+        }
+    }
+
+    // 设备断开：已排队的条目读完后流结束
+    pub(crate) fn close(&self, uuid: u128) {
+        let Some(subscribers) = lock(&self.subscribers).remove(&uuid) else {
+            return;
+        };
+        for subscriber in subscribers.iter().filter_map(|s| s.upgrade()) {
+            subscriber.close();
+        }
+    }
+
+    pub(crate) fn close_all(&self) {
+        let all: Vec<Weak<dyn Deliver>> = lock(&self.subscribers)
+            .drain()
+            .flat_map(|(_, subscribers)| subscribers)
+            .collect();
+        for subscriber in all.iter().filter_map(|s| s.upgrade()) {
+            subscriber.close();
+        }
+    }
+}
+
+impl Drop for Broadcasts {
+    fn drop(&mut self) {
+        self.close_all();
+    }
+}
+
+// 把广播报告拆成记录，并附上主机收到的时间和报告中此前出现的设备时间
+pub(crate) fn records(broadcast: &BroadCast) -> Vec<(RecordContext, BroadCastData)> {
+    let mut context = RecordContext {
+        received_us: now_micros(),
+        device_time_ms: None,
+    };
+    let mut records = Vec::new();
+    for record in broadcast.data().unwrap_or_default() {
+        if record.data_type(None) == Some(BROADCAST_TYPE_SYS_TIME_MS)
+            && let Some([lo, hi]) = record.data(None).as_deref()
+        {
+            context.device_time_ms = Some(u16::from_le_bytes([*lo, *hi]));
+        }
+        records.push((context, record));
+    }
+    records
+}
+
+// 条目不由广播记录映射、而由其他模块直接推入的流，队列与 Lagged 语义相同
+pub(crate) struct Sender<T> {
+    subscriber: Weak<Subscriber<T>>,
+}
+
+impl<T> Sender<T> {
+    // 接收端已释放时返回 false
+    pub(crate) fn send(&self, item: T) -> bool {
+        let Some(subscriber) = self.subscriber.upgrade() else {
+            return false;
+        };
+        let mut queue = lock(&subscriber.queue);
+        queue.push(item);
+        queue.wake();
+        true
+    }
+
+    pub(crate) fn close(&self) {
+        if let Some(subscriber) = self.subscriber.upgrade() {
+            let mut queue = lock(&subscriber.queue);
+            queue.closed = true;
+            queue.wake();
+        }
+    }
+}
+
+pub(crate) fn channel<T>(capacity: usize) -> (Sender<T>, BroadcastStream<T>) {
+    let subscriber = Arc::new(Subscriber {
+        queue: Mutex::new(Queue::new(capacity)),
+        map: |_, _| None,
+    });
+    let sender = Sender {
+        subscriber: Arc::downgrade(&subscriber),
+    };
+    (sender, BroadcastStream { subscriber })
+}
+
+// 广播流；Err(Lagged) 表示此前有条目因队列满被丢弃，流仍可继续读取
+pub struct BroadcastStream<T = BroadCastData> {
+    subscriber: Arc<Subscriber<T>>,
+}
+
+impl<T> BroadcastStream<T> {
+    // 已结束的流，上下文关闭后订阅时返回
+    pub(crate) fn closed() -> Self {
+        let (sender, stream) = channel(1);
+        sender.close();
+        stream
+    }
+}
+
+impl<T> Stream for BroadcastStream<T> {
+    type Item = Result<T, Lagged>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut queue = lock(&self.subscriber.queue);
+        if queue.lagged > 0 {
+            let lagged = std::mem::take(&mut queue.lagged);
+            return Poll::Ready(Some(Err(Lagged(lagged))));
+        }
+        if let Some(item) = queue.items.pop_front() {
+            return Poll::Ready(Some(Ok(item)));
+        }
+        if queue.closed {
+            return Poll::Ready(None);
+        }
+        queue.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+// BROADCAST_TYPE_SYS_CMD 携带的系统命令
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SysCmd {
+    Reboot,
+    LedOn,
+    LedOff,
+    ToggleLed,
+    BluetoothOn,
+    BluetoothOff,
+    ToggleBluetooth,
+    LedEffectBrightnessDown,
+    LedEffectBrightnessUp,
+    LedEffectSpeedDown,
+    LedEffectSpeedUp,
+    LedEffectRollSubmode,
+    LedEffectToggleEnabled,
+    LedEffectGrayModeToggle,
+    LedEffectRollMode,
+    // 配置文件序号 0-7
+    ProfileSelect(u8),
+    ToggleSocd,
+    LedTest,
+    LockKey,
+    Other(u8),
+}
+
+impl From<u8> for SysCmd {
+    fn from(value: u8) -> Self {
+        match value {
+            SYS_CMD_REBOOT => SysCmd::Reboot,
+            SYS_CMD_LED_ON => SysCmd::LedOn,
+            SYS_CMD_LED_OFF => SysCmd::LedOff,
+            SYS_CMD_TOGGLE_LED => SysCmd::ToggleLed,
+            SYS_CMD_BLUETOOTH_ON => SysCmd::BluetoothOn,
+            SYS_CMD_BLUETOOTH_OFF => SysCmd::BluetoothOff,
+            SYS_CMD_TOGGLE_BLUETOOTH => SysCmd::ToggleBluetooth,
+            SYS_CMD_LED_EFFECT_BRIGHTNESS_DOWN => SysCmd::LedEffectBrightnessDown,
+            SYS_CMD_LED_EFFECT_BRIGHTNESS_UP => SysCmd::LedEffectBrightnessUp,
+            SYS_CMD_LED_EFFECT_SPEED_DOWN => SysCmd::LedEffectSpeedDown,
+            SYS_CMD_LED_EFFECT_SPEED_UP => SysCmd::LedEffectSpeedUp,
+            SYS_CMD_LED_EFFECT_ROLL_SUBMODE => SysCmd::LedEffectRollSubmode,
+            SYS_CMD_LED_EFFECT_TOGGLE_ENABLED => SysCmd::LedEffectToggleEnabled,
+            SYS_CMD_LED_EFFECT_GRAY_MODE_TOGGLE => SysCmd::LedEffectGrayModeToggle,
+            SYS_CMD_LED_EEFECT_ROLL_MODE => SysCmd::LedEffectRollMode,
+            SYS_CMD_PROFILE_SELECT_BASE..=SYS_CMD_PROFILE_SELECT_MAX => {
+                SysCmd::ProfileSelect(value - SYS_CMD_PROFILE_SELECT_BASE)
+            }
+            SYS_CMD_TOGGLE_SOCD => SysCmd::ToggleSocd,
+            SYS_CMD_LED_TEST => SysCmd::LedTest,
+            SYS_CMD_LOCK_KEY => SysCmd::LockKey,
+            other => SysCmd::Other(other),
+        }
+    }
+}
+
+impl From<SysCmd> for u8 {
+    fn from(value: SysCmd) -> Self {
+        match value {
+            SysCmd::Reboot => SYS_CMD_REBOOT,
+            SysCmd::LedOn => SYS_CMD_LED_ON,
+            SysCmd::LedOff => SYS_CMD_LED_OFF,
+            SysCmd::ToggleLed => SYS_CMD_TOGGLE_LED,
+            SysCmd::BluetoothOn => SYS_CMD_BLUETOOTH_ON,
+            SysCmd::BluetoothOff => SYS_CMD_BLUETOOTH_OFF,
+            SysCmd::ToggleBluetooth => SYS_CMD_TOGGLE_BLUETOOTH,
+            SysCmd::LedEffectBrightnessDown => SYS_CMD_LED_EFFECT_BRIGHTNESS_DOWN,
+            SysCmd::LedEffectBrightnessUp => SYS_CMD_LED_EFFECT_BRIGHTNESS_UP,
+            SysCmd::LedEffectSpeedDown => SYS_CMD_LED_EFFECT_SPEED_DOWN,
+            SysCmd::LedEffectSpeedUp => SYS_CMD_LED_EFFECT_SPEED_UP,
+            SysCmd::LedEffectRollSubmode => SYS_CMD_LED_EFFECT_ROLL_SUBMODE,
+            SysCmd::LedEffectToggleEnabled => SYS_CMD_LED_EFFECT_TOGGLE_ENABLED,
+            SysCmd::LedEffectGrayModeToggle => SYS_CMD_LED_EFFECT_GRAY_MODE_TOGGLE,
+            SysCmd::LedEffectRollMode => SYS_CMD_LED_EEFECT_ROLL_MODE,
+            SysCmd::ProfileSelect(profile) => SYS_CMD_PROFILE_SELECT_BASE + profile,
+            SysCmd::ToggleSocd => SYS_CMD_TOGGLE_SOCD,
+            SysCmd::LedTest => SYS_CMD_LED_TEST,
+            SysCmd::LockKey => SYS_CMD_LOCK_KEY,
+            SysCmd::Other(other) => other,
+        }
+    }
+}
+
+// 主机键盘指示灯，位定义同 HID LED 输出报告
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KbLed {
+    pub num_lock: bool,
+    pub caps_lock: bool,
+    pub scroll_lock: bool,
+    pub raw: u8,
+}
+
+impl From<u8> for KbLed {
+    fn from(raw: u8) -> Self {
+        KbLed {
+            num_lock: raw & 0x01 != 0,
+            caps_lock: raw & 0x02 != 0,
+            scroll_lock: raw & 0x04 != 0,
+            raw,
+        }
+    }
+}
+
+// 单条广播记录的类型化视图，未识别或长度不符的记录落到 Unknown
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BroadcastEvent {
+    SysCmd(SysCmd),
+    KbLed(KbLed),
+    FnLayer(u8),
+    // 百分比
+    CpuLoad(u8),
+    Profile(u8),
+    // dBm
+    Rssi(i8),
+    KeyPress { key: u8 },
+    KeyRelease { key: u8 },
+    // 标准键盘键码（HID usage）加入/移出报告
+    StandardKeyAdd(u8),
+    StandardKeyDel(u8),
+    GeneralKeyAdd(u8),
+    GeneralKeyDel(u8),
+    JoystickButtonAdd(u8),
+    JoystickButtonDel(u8),
+    // 0-7 为八个方向，其余为回中
+    JoystickHat(u8),
+    // 多媒体键 usage，0 表示松开
+    Media(u16),
+    Mouse { buttons: u8, dx: i8, dy: i8, wheel: i8 },
+    // 摇杆轴的绝对位置
+    Point { x: u16, y: u16 },
+    HallKeyReload(u8),
+    SysTimeMs(u16),
+    SysTime(u32),
+    // 各键行程，见 levels 模块
+    Levels(Vec<KeyTravel>),
+    Log(String),
+    Error(String),
+    Unknown { data_type: u8, bytes: Vec<u8> },
+}
+
+// 日志去掉末尾的 0 填充，与设备日志相同按 UTF-8、GB18030 解码
+fn message(bytes: &[u8]) -> String {
+    let end = bytes.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+    device_log::decode_text(&bytes[..end])
+}
+
+impl BroadcastEvent {
+    pub fn decode(data_type: u8, bytes: &[u8]) -> BroadcastEvent {
+        match (data_type, bytes) {
+            (BROADCAST_TYPE_SYS_CMD, [cmd]) => BroadcastEvent::SysCmd(SysCmd::from(*cmd)),
+            (BROADCAST_TYPE_KB_LED, [raw]) => BroadcastEvent::KbLed(KbLed::from(*raw)),
+            (BROADCAST_TYPE_FN_LAYER, [layer]) => BroadcastEvent::FnLayer(*layer),
+            (BROADCAST_TYPE_CPU_LOAD, [load]) => BroadcastEvent::CpuLoad(*load),
+            (BROADCAST_TYPE_PROFILE, [profile]) => BroadcastEvent::Profile(*profile),
+            (BROADCAST_TYPE_RSSI, [rssi]) => BroadcastEvent::Rssi(*rssi as i8),
+            (BROADCAST_TYPE_KEY_PRESS, [key]) => BroadcastEvent::KeyPress { key: *key },
+            (BROADCAST_TYPE_KEY_RELEASE, [key]) => BroadcastEvent::KeyRelease { key: *key },
+            (BROADCAST_TYPE_SK_ADD, [code]) => BroadcastEvent::StandardKeyAdd(*code),
+            (BROADCAST_TYPE_SK_DEL, [code]) => BroadcastEvent::StandardKeyDel(*code),
+            (BROADCAST_TYPE_GK_ADD, [code]) => BroadcastEvent::GeneralKeyAdd(*code),
+            (BROADCAST_TYPE_GK_DEL, [code]) => BroadcastEvent::GeneralKeyDel(*code),
+            (BROADCAST_TYPE_JOYSTICK_ADD, [button]) => BroadcastEvent::JoystickButtonAdd(*button),
+            (BROADCAST_TYPE_JOYSTICK_DEL, [button]) => BroadcastEvent::JoystickButtonDel(*button),
+            (BROADCAST_TYPE_JOYSTICK_HAT, [hat]) => BroadcastEvent::JoystickHat(*hat),
+            (BROADCAST_TYPE_HALL_KEY_RELOAD, [key]) => BroadcastEvent::HallKeyReload(*key),
+            (BROADCAST_TYPE_MU_DATA, [lo, hi]) => BroadcastEvent::Media(u16::from_le_bytes([*lo, *hi])),
+            (BROADCAST_TYPE_MO_DATA, [buttons, dx, dy, wheel]) => BroadcastEvent::Mouse {
+                buttons: *buttons,
+                dx: *dx as i8,
+                dy: *dy as i8,
+                wheel: *wheel as i8,
+            },
+            (BROADCAST_TYPE_POINT, [x0, x1, y0, y1]) => BroadcastEvent::Point {
+                x: u16::from_le_bytes([*x0, *x1]),
+                y: u16::from_le_bytes([*y0, *y1]),
+            },
+            (BROADCAST_TYPE_SYS_TIME_MS, [lo, hi]) => {
+                BroadcastEvent::SysTimeMs(u16::from_le_bytes([*lo, *hi]))
+            }
+            (BROADCAST_TYPE_SYS_TIME, [b0, b1, b2, b3]) => {
+                BroadcastEvent::SysTime(u32::from_le_bytes([*b0, *b1, *b2, *b3]))
+            }
+            (BROADCAST_TYPE_LEVELS, bytes) => BroadcastEvent::Levels(decode_levels(bytes)),
+            (BROADCAST_TYPE_LOG_MSG, bytes) => BroadcastEvent::Log(message(bytes)),
+            (BROADCAST_TYPE_ERROR_MSG, bytes) => BroadcastEvent::Error(message(bytes)),
+            (data_type, bytes) => BroadcastEvent::Unknown {
+                data_type,
+                bytes: bytes.to_vec(),
+            },
+        }
+    }
+}
+
+impl From<&BroadCastData> for BroadcastEvent {
+    fn from(data: &BroadCastData) -> Self {
+        BroadcastEvent::decode(
+            data.data_type(None).unwrap_or_default(),
+            &data.data(None).unwrap_or_default(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::byte_converter::RwBytes;
+    use futures::StreamExt;
+    use pollster::block_on;
+
+    fn broadcast(bytes: &[u8]) -> Vec<(RecordContext, BroadCastData)> {
+        records(&BroadCast {
+            bytes: RwBytes::new(bytes.to_vec()),
+        })
+    }
+
+    #[test]
+    fn test_fan_out_with_lag() {
+        let uuid = 0xB40A_0013;
+        let broadcasts = Broadcasts::default();
+        let mut fast = broadcasts.subscribe(uuid, 8);
+        let mut slow = broadcasts.subscribe(uuid, 2);
+        assert_eq!(broadcasts.subscriber_count(uuid), 2);
+
+        // 按下 1、2、3，每条 2 字节
+        broadcasts.publish(uuid, &broadcast(&[0x10, 0x01, 0x10, 0x02, 0x10, 0x03, 0x00]));
+
+        for key in 1..=3 {
+            let record = block_on(fast.next()).unwrap().unwrap();
+            assert_eq!(record.data(None), Some(vec![key]));
+        }
+        assert!(matches!(block_on(slow.next()), Some(Err(Lagged(1)))));
+        assert_eq!(block_on(slow.next()).unwrap().unwrap().data(None), Some(vec![2]));
+        assert_eq!(block_on(slow.next()).unwrap().unwrap().data(None), Some(vec![3]));
+
+        drop(fast);
+        assert_eq!(broadcasts.subscriber_count(uuid), 1);
+        broadcasts.close(uuid);
+        assert!(block_on(slow.next()).is_none());
+        assert_eq!(broadcasts.subscriber_count(uuid), 0);
+    }
+
+    #[test]
+    fn test_event_stream() {
+        let uuid = 0xB40A_0014;
+        let broadcasts = Broadcasts::default();
+        let mut events = broadcasts.subscribe_events(uuid, 8);
+        broadcasts.publish(
+            uuid,
+            &broadcast(&[
+                0x01, 0xF2, 0x02, 0x03, 0x06, 0xC4, 0x80, 0x34, 0x12, 0xFF, 0x04, b'o', b'k', 0x00,
+                0x07, 0x09, 0x00,
+            ]),
+        );
+        let expected = [
+            BroadcastEvent::SysCmd(SysCmd::ProfileSelect(2)),
+            BroadcastEvent::KbLed(KbLed {
+                num_lock: true,
+                caps_lock: true,
+                scroll_lock: false,
+                raw: 0x03,
+            }),
+            BroadcastEvent::Rssi(-60),
+            BroadcastEvent::SysTimeMs(0x1234),
+            BroadcastEvent::Log("ok".to_string()),
+            BroadcastEvent::Unknown {
+                data_type: 0x07,
+                bytes: vec![0x09],
+            },
+        ];
+        for event in expected {
+            assert_eq!(block_on(events.next()), Some(Ok(event)));
+        }
+        assert_eq!(u8::from(SysCmd::from(0xF2)), 0xF2);
+        assert_eq!(
+            BroadcastEvent::decode(BROADCAST_TYPE_ERROR_MSG, &[0xD6, 0xD0, 0x00]),
+            BroadcastEvent::Error("中".to_string())
+        );
+        // 注册表释放时流同样结束
+        drop(broadcasts);
+        assert_eq!(block_on(events.next()), None);
+    }
+}
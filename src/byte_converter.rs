@@ -102,7 +102,7 @@ impl RwBytes {
     pub fn from_str(encoding: Encoding, value: &str) -> Self {
         let bytes = Self::encode_string(encoding, value);
         let len = bytes.len();
-        println!("from_str: {:02X?}", bytes);
+        log::trace!("from_str: {:02X?}", bytes);
         RwBytes {
             bytes: Arc::new(Mutex::new(bytes)),
             offset: 0,
@@ -138,12 +138,7 @@ impl RwBytes {
         let data = self.lock_bytes();
 
         if offset + len > data.len() {
-            println!(
-                "Index out of bounds for bytes: {} + {} > {}",
-                offset,
-                len,
-                data.len()
-            );
+            log::debug!(offset, len, bytes_len = data.len(); "ref_at index out of bounds");
             return None;
         }
 
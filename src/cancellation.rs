@@ -0,0 +1,124 @@
+// 取消令牌：克隆共享同一状态，任一克隆调用 cancel 后所有持有者可见，
+// 等待中的 cancelled() future 会被唤醒。
+// child_token() 派生的子令牌随父令牌一起取消，取消子令牌不影响父令牌。
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll, Waker};
+
+use crate::utility::lock;
+
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    next_id: AtomicU64,
+    wakers: Mutex<HashMap<u64, Waker>>,
+    children: Mutex<Vec<Weak<Inner>>>,
+}
+
+impl Inner {
+    fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        let wakers: Vec<Waker> = lock(&self.wakers).drain().map(|(_, waker)| waker).collect();
+        for waker in wakers {
+            waker.wake();
+        }
+        let children: Vec<Weak<Inner>> = lock(&self.children).drain(..).collect();
+        for child in children.iter().filter_map(Weak::upgrade) {
+            child.cancel();
+        }
+    }
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.inner.cancel();
+    }
+
+    pub fn child_token(&self) -> CancellationToken {
+        let child = CancellationToken::new();
+        let mut children = lock(&self.inner.children);
+        // 持锁检查，cancel 先置位再取子令牌列表，不会漏掉刚派生的子令牌
+        if self.is_cancelled() {
+            drop(children);
+            child.cancel();
+            return child;
+        }
+        children.retain(|weak| weak.strong_count() > 0);
+        children.push(Arc::downgrade(&child.inner));
+        child
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    // 取消时完成的 future
+    pub fn cancelled(&self) -> Cancelled {
+        Cancelled {
+            inner: self.inner.clone(),
+            id: self.inner.next_id.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+}
+
+pub struct Cancelled {
+    inner: Arc<Inner>,
+    id: u64,
+}
+
+impl Future for Cancelled {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.inner.cancelled.load(Ordering::SeqCst) {
+            return Poll::Ready(());
+        }
+        let mut wakers = lock(&self.inner.wakers);
+        // 持锁后再检查一次，避免与 cancel 竞争丢失唤醒
+        if self.inner.cancelled.load(Ordering::SeqCst) {
+            return Poll::Ready(());
+        }
+        wakers.insert(self.id, cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for Cancelled {
+    fn drop(&mut self) {
+        lock(&self.inner.wakers).remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_child_token() {
+        let parent = CancellationToken::new();
+        let child = parent.child_token();
+        let sibling = parent.child_token();
+        child.cancel();
+        assert!(child.is_cancelled());
+        assert!(!parent.is_cancelled() && !sibling.is_cancelled());
+
+        let grandchild = sibling.child_token();
+        parent.cancel();
+        assert!(sibling.is_cancelled() && grandchild.is_cancelled());
+        assert!(parent.child_token().is_cancelled());
+        pollster::block_on(grandchild.cancelled());
+    }
+}
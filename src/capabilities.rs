@@ -0,0 +1,98 @@
+// 设备能力：DeviceInfo::api_list 列出固件实现的 cmd，每字节一个，未用部分以 0x00 / 0xFF 填充。
+// 0x00 本身是 DeviceInfo，任何固件都支持，因此当作填充跳过。
+// 旧固件的 api_list 全为填充，此时视为未声明，所有命令照常发送，由设备状态码决定。
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::device_constants::{ANALOG_KEY_V2_VERSION, CMD_DEVICE_INFO};
+use crate::structures::{AnalogKeyInfo2, DeviceInfo};
+use crate::structures_codec::CodecableHidPackage;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    pub model_code: u16,
+    // 固件版本
+    pub ver: u16,
+    // None 表示固件未声明 api_list
+    commands: Option<BTreeSet<u8>>,
+}
+
+impl Capabilities {
+    pub fn from_device_info(info: &DeviceInfo) -> Self {
+        let commands: BTreeSet<u8> = info
+            .api_list(None)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|cmd| *cmd != 0x00 && *cmd != 0xFF)
+            .collect();
+        Capabilities {
+            model_code: info.model_code(None).unwrap_or(0),
+            ver: info.ver(None).unwrap_or(0),
+            commands: (!commands.is_empty()).then_some(commands),
+        }
+    }
+
+    pub fn is_advertised(&self) -> bool {
+        self.commands.is_some()
+    }
+
+    // 未声明 api_list 时总是返回 true
+    pub fn supports(&self, cmd: u8) -> bool {
+        match &self.commands {
+            Some(commands) => cmd == CMD_DEVICE_INFO || commands.contains(&cmd),
+            None => true,
+        }
+    }
+
+    pub fn commands(&self) -> impl Iterator<Item = u8> + '_ {
+        self.commands.iter().flatten().copied()
+    }
+
+    pub fn firmware_at_least(&self, ver: u16) -> bool {
+        self.ver >= ver
+    }
+
+    // 模拟按键使用 AnalogKeyInfo2（0x1C）还是 AnalogKeyInfo（0x14）；未声明 api_list 时按固件版本判断
+    pub fn uses_analog_key_info2(&self) -> bool {
+        match &self.commands {
+            Some(commands) => AnalogKeyInfo2::CMD.is_some_and(|cmd| commands.contains(&cmd)),
+            None => self.ver >= ANALOG_KEY_V2_VERSION,
+        }
+    }
+}
+
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "model {:#06X} ver {}", self.model_code, self.ver)?;
+        match &self.commands {
+            Some(commands) => write!(f, " cmds {:02X?}", commands),
+            None => write!(f, " cmds 未声明"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::byte_converter::RwBytes;
+
+    #[test]
+    fn test_capabilities() {
+        let info = DeviceInfo::new(RwBytes::new(vec![0; 20]));
+        info.ver(Some(130));
+        let caps = Capabilities::from_device_info(&info);
+        assert!(!caps.is_advertised());
+        assert!(caps.supports(0x28));
+        assert!(caps.uses_analog_key_info2());
+
+        info.api_list(Some(vec![0x10, 0x1C, 0x26, 0xFF, 0x00, 0x00, 0x00, 0x00]));
+        let caps = Capabilities::from_device_info(&info);
+        assert!(caps.is_advertised());
+        assert!(caps.supports(0x1C) && caps.supports(CMD_DEVICE_INFO));
+        assert!(!caps.supports(0x28));
+        assert_eq!(caps.commands().collect::<Vec<_>>(), vec![0x10, 0x1C, 0x26]);
+        assert!(caps.firmware_at_least(120) && !caps.firmware_at_least(131));
+        assert!(caps.uses_analog_key_info2());
+    }
+}
@@ -0,0 +1,520 @@
+// 原始 HID 报告抓包：按设备开关，记录 send_hid_report 发出和 on_report_arrived 收到的每个报告。
+// pcapng 使用 USBPcap 链路类型，可直接用 Wireshark 打开；JSON-lines 每行一条记录，便于脚本处理；
+// Text 为经 dissector 解码的可读文本。
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use log::warn;
+
+use crate::cross_platform_utils::now_micros;
+use crate::device_error_handling::{DeviceError, DeviceResult};
+use crate::dissector;
+use crate::json::{self, JsonValue};
+use crate::utility::{hex, invalid_data, le_u16, le_u32, lock, parse_hex};
+
+// LINKTYPE_USBPCAP
+pub const LINKTYPE_USBPCAP: u16 = 249;
+const USBPCAP_HEADER_LEN: u16 = 27;
+const URB_FUNCTION_BULK_OR_INTERRUPT_TRANSFER: u16 = 0x0009;
+const USBPCAP_TRANSFER_INTERRUPT: u8 = 0x01;
+
+const BLOCK_SHB: u32 = 0x0A0D_0D0A;
+const BLOCK_IDB: u32 = 0x0000_0001;
+const BLOCK_EPB: u32 = 0x0000_0006;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+const OPT_END: u16 = 0;
+const OPT_IF_NAME: u16 = 2;
+const OPT_IF_DESCRIPTION: u16 = 3;
+const OPT_IF_TSRESOL: u16 = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CaptureFormat {
+    Pcapng,
+    JsonLines,
+    Text,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    // 主机 -> 设备
+    Out,
+    // 设备 -> 主机
+    In,
+}
+
+impl Direction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Out => "out",
+            Direction::In => "in",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureRecord {
+    // 微秒级 Unix 时间戳
+    pub timestamp_us: u64,
+    pub direction: Direction,
+    pub uuid: u128,
+    pub data: Vec<u8>,
+}
+
+// 可读视图：一行摘要，随后是解码后的字段；无法解析的报告输出原始字节
+impl fmt::Display for CaptureRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}.{:06} {:<3} {} ",
+            self.timestamp_us / 1_000_000,
+            self.timestamp_us % 1_000_000,
+            self.direction.as_str(),
+            uuid::Uuid::from_u128(self.uuid)
+        )?;
+        match dissector::dissect_report(&self.data) {
+            Ok(dissection) => write!(f, "{}", dissection),
+            Err(e) => write!(f, "{}: {:02X?}", e, self.data),
+        }
+    }
+}
+
+pub struct CaptureWriter {
+    format: CaptureFormat,
+    sink: Box<dyn Write + Send>,
+    records: usize,
+}
+
+impl CaptureWriter {
+    // pcapng 在创建时写入节头和接口描述块，每个 writer 对应一个设备接口
+    pub fn new(
+        uuid: u128,
+        format: CaptureFormat,
+        mut sink: Box<dyn Write + Send>,
+    ) -> std::io::Result<Self> {
+        if format == CaptureFormat::Pcapng {
+            sink.write_all(&pcapng_section_header())?;
+            sink.write_all(&pcapng_interface_description(uuid))?;
+        }
+        Ok(Self {
+            format,
+            sink,
+            records: 0,
+        })
+    }
+
+    pub fn write(&mut self, record: &CaptureRecord) -> std::io::Result<()> {
+        let bytes = match self.format {
+            CaptureFormat::Pcapng => pcapng_enhanced_packet(record),
+            CaptureFormat::JsonLines => json_line(record).into_bytes(),
+            CaptureFormat::Text => format!("{}\n", record).into_bytes(),
+        };
+        self.sink.write_all(&bytes)?;
+        self.records += 1;
+        Ok(())
+    }
+
+    pub fn records(&self) -> usize {
+        self.records
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.sink.flush()
+    }
+}
+
+// 一个上下文内各设备的抓包，由 SayoContext 持有
+#[derive(Default)]
+pub(crate) struct Captures {
+    writers: Mutex<HashMap<u128, CaptureWriter>>,
+    // 无抓包时跳过加锁
+    active: AtomicUsize,
+}
+
+impl Captures {
+    // 开始抓包，已有抓包时先结束旧的
+    pub(crate) fn start(
+        &self,
+        uuid: u128,
+        format: CaptureFormat,
+        sink: Box<dyn Write + Send>,
+    ) -> DeviceResult<()> {
+        let writer = CaptureWriter::new(uuid, format, sink)
+            .map_err(|e| DeviceError::InvalidData(format!("抓包写入失败: {}", e)))?;
+        let mut writers = lock(&self.writers);
+        match writers.insert(uuid, writer) {
+            Some(mut old) => _ = old.flush(),
+            None => _ = self.active.fetch_add(1, Ordering::SeqCst),
+        }
+        Ok(())
+    }
+
+    // 结束抓包并返回记录条数，未在抓包时返回 0
+    pub(crate) fn stop(&self, uuid: u128) -> DeviceResult<usize> {
+        let Some(mut writer) = lock(&self.writers).remove(&uuid) else {
+            return Ok(0);
+        };
+        self.active.fetch_sub(1, Ordering::SeqCst);
+        writer
+            .flush()
+            .map_err(|e| DeviceError::InvalidData(format!("抓包写入失败: {}", e)))?;
+        Ok(writer.records())
+    }
+
+    pub(crate) fn is_capturing(&self, uuid: u128) -> bool {
+        self.active.load(Ordering::SeqCst) > 0 && lock(&self.writers).contains_key(&uuid)
+    }
+
+    // 写入失败时自动结束该设备的抓包，不影响正常通信
+    pub(crate) fn record(&self, uuid: u128, direction: Direction, data: &[u8]) {
+        if self.active.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let mut writers = lock(&self.writers);
+        let Some(writer) = writers.get_mut(&uuid) else {
+            return;
+        };
+        let record = CaptureRecord {
+            timestamp_us: now_micros(),
+            direction,
+            uuid,
+            data: data.to_vec(),
+        };
+        if let Err(e) = writer.write(&record) {
+            warn!(uuid:% = uuid::Uuid::from_u128(uuid); "capture write failed, capture stopped: {}", e);
+            writers.remove(&uuid);
+            self.active.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+// 上下文释放时写出缓冲中的记录
+impl Drop for Captures {
+    fn drop(&mut self) {
+        for writer in lock(&self.writers).values_mut() {
+            let _ = writer.flush();
+        }
+    }
+}
+
+fn push_option(block: &mut Vec<u8>, code: u16, value: &[u8]) {
+    block.extend_from_slice(&code.to_le_bytes());
+    block.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    block.extend_from_slice(value);
+    block.resize(block.len().next_multiple_of(4), 0);
+}
+
+// 补齐块头的类型和长度，以及块尾的长度
+fn finish_block(block_type: u32, body: Vec<u8>) -> Vec<u8> {
+    let total_len = (body.len() + 12) as u32;
+    let mut block = Vec::with_capacity(total_len as usize);
+    block.extend_from_slice(&block_type.to_le_bytes());
+    block.extend_from_slice(&total_len.to_le_bytes());
+    block.extend(body);
+    block.extend_from_slice(&total_len.to_le_bytes());
+    block
+}
+
+pub fn pcapng_section_header() -> Vec<u8> {
+    let mut body = Vec::new();
+    body.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+    body.extend_from_slice(&1u16.to_le_bytes());
+    body.extend_from_slice(&0u16.to_le_bytes());
+    // 节长度未知
+    body.extend_from_slice(&(-1i64).to_le_bytes());
+    finish_block(BLOCK_SHB, body)
+}
+
+pub fn pcapng_interface_description(uuid: u128) -> Vec<u8> {
+    let mut body = Vec::new();
+    body.extend_from_slice(&LINKTYPE_USBPCAP.to_le_bytes());
+    body.extend_from_slice(&0u16.to_le_bytes());
+    // snaplen 0 表示不截断
+    body.extend_from_slice(&0u32.to_le_bytes());
+    push_option(
+        &mut body,
+        OPT_IF_NAME,
+        uuid::Uuid::from_u128(uuid).to_string().as_bytes(),
+    );
+    push_option(&mut body, OPT_IF_DESCRIPTION, b"Sayo HID");
+    // 时间戳精度 10^-6 秒
+    push_option(&mut body, OPT_IF_TSRESOL, &[6]);
+    push_option(&mut body, OPT_END, &[]);
+    finish_block(BLOCK_IDB, body)
+}
+
+// USBPcap 伪头部：中断传输，IN 方向 info 置位且端点带 0x80
+fn usbpcap_header(direction: Direction, data_len: usize) -> Vec<u8> {
+    let (info, endpoint) = match direction {
+        Direction::Out => (0u8, 0x01u8),
+        Direction::In => (1u8, 0x81u8),
+    };
+    let mut header = Vec::with_capacity(USBPCAP_HEADER_LEN as usize);
+    header.extend_from_slice(&USBPCAP_HEADER_LEN.to_le_bytes());
+    header.extend_from_slice(&0u64.to_le_bytes()); // irpId
+    header.extend_from_slice(&0u32.to_le_bytes()); // USBD_STATUS_SUCCESS
+    header.extend_from_slice(&URB_FUNCTION_BULK_OR_INTERRUPT_TRANSFER.to_le_bytes());
+    header.push(info);
+    header.extend_from_slice(&0u16.to_le_bytes()); // bus
+    header.extend_from_slice(&0u16.to_le_bytes()); // device
+    header.push(endpoint);
+    header.push(USBPCAP_TRANSFER_INTERRUPT);
+    header.extend_from_slice(&(data_len as u32).to_le_bytes());
+    header
+}
+
+pub fn pcapng_enhanced_packet(record: &CaptureRecord) -> Vec<u8> {
+    let mut packet = usbpcap_header(record.direction, record.data.len());
+    packet.extend_from_slice(&record.data);
+    let packet_len = packet.len() as u32;
+
+    let mut body = Vec::new();
+    body.extend_from_slice(&0u32.to_le_bytes()); // interface id
+    body.extend_from_slice(&((record.timestamp_us >> 32) as u32).to_le_bytes());
+    body.extend_from_slice(&(record.timestamp_us as u32).to_le_bytes());
+    body.extend_from_slice(&packet_len.to_le_bytes());
+    body.extend_from_slice(&packet_len.to_le_bytes());
+    body.extend(packet);
+    body.resize(body.len().next_multiple_of(4), 0);
+    finish_block(BLOCK_EPB, body)
+}
+
+pub fn json_line(record: &CaptureRecord) -> String {
+    format!(
+        "{{\"ts_us\":{},\"dir\":\"{}\",\"uuid\":\"{}\",\"data\":\"{}\"}}\n",
+        record.timestamp_us,
+        record.direction.as_str(),
+        uuid::Uuid::from_u128(record.uuid),
+        hex(&record.data, "")
+    )
+}
+
+// 读取 JSON-lines 抓包，空行跳过
+pub fn read_json_lines(reader: impl std::io::BufRead) -> std::io::Result<Vec<CaptureRecord>> {
+    let mut records = Vec::new();
+    for (line_no, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let bad_line = || invalid_data(format!("line {}: bad capture record", line_no + 1));
+        let value = json::parse(&line)
+            .map_err(|e| invalid_data(format!("line {}: {}", line_no + 1, e)))?;
+        let text = |key| match value.get(key) {
+            Some(JsonValue::String(text)) => Some(text.as_str()),
+            _ => None,
+        };
+        let direction = match text("dir") {
+            Some("out") => Direction::Out,
+            Some("in") => Direction::In,
+            _ => return Err(bad_line()),
+        };
+        records.push(CaptureRecord {
+            timestamp_us: match value.get("ts_us") {
+                Some(JsonValue::Number(ts)) => u64::try_from(*ts).ok(),
+                _ => None,
+            }
+            .ok_or_else(bad_line)?,
+            direction,
+            uuid: text("uuid")
+                .and_then(|uuid| uuid::Uuid::parse_str(uuid).ok())
+                .ok_or_else(bad_line)?
+                .as_u128(),
+            data: text("data").and_then(parse_hex).ok_or_else(bad_line)?,
+        });
+    }
+    Ok(records)
+}
+
+struct PcapngInterface {
+    uuid: u128,
+    // 每个时间戳单位对应的纳秒数
+    ns_per_tick: u64,
+}
+
+fn parse_interface(body: &[u8]) -> std::io::Result<PcapngInterface> {
+    if le_u16(body, 0) != Some(LINKTYPE_USBPCAP) {
+        return Err(invalid_data("pcapng interface is not USBPcap"));
+    }
+    let mut interface = PcapngInterface {
+        uuid: 0,
+        ns_per_tick: 1000,
+    };
+    let mut offset = 8;
+    while let (Some(code), Some(len)) = (le_u16(body, offset), le_u16(body, offset + 2)) {
+        let value = body
+            .get(offset + 4..offset + 4 + len as usize)
+            .ok_or_else(|| invalid_data("truncated pcapng option"))?;
+        match code {
+            OPT_END => break,
+            OPT_IF_NAME => {
+                if let Some(uuid) = std::str::from_utf8(value)
+                    .ok()
+                    .and_then(|name| uuid::Uuid::parse_str(name).ok())
+                {
+                    interface.uuid = uuid.as_u128();
+                }
+            }
+            // 仅支持 10 的负幂
+            OPT_IF_TSRESOL => match value.first() {
+                Some(&resol) if resol <= 9 => interface.ns_per_tick = 10u64.pow(9 - resol as u32),
+                _ => return Err(invalid_data("unsupported pcapng timestamp resolution")),
+            },
+            _ => {}
+        }
+        offset += (4 + len as usize).next_multiple_of(4);
+    }
+    Ok(interface)
+}
+
+// 读取 pcapng 抓包，只接受小端、USBPcap 链路类型；设备 uuid 取自接口名
+pub fn read_pcapng(mut reader: impl std::io::Read) -> std::io::Result<Vec<CaptureRecord>> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+
+    let mut records = Vec::new();
+    let mut interfaces: Vec<PcapngInterface> = Vec::new();
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let (Some(block_type), Some(total_len)) = (le_u32(&bytes, offset), le_u32(&bytes, offset + 4))
+        else {
+            return Err(invalid_data("truncated pcapng block header"));
+        };
+        let total_len = total_len as usize;
+        if total_len < 12 || offset + total_len > bytes.len() {
+            return Err(invalid_data("bad pcapng block length"));
+        }
+        let body = &bytes[offset + 8..offset + total_len - 4];
+        match block_type {
+            BLOCK_SHB => {
+                if le_u32(body, 0) != Some(BYTE_ORDER_MAGIC) {
+                    return Err(invalid_data("only little-endian pcapng is supported"));
+                }
+                // 新的节重新编号接口
+                interfaces.clear();
+            }
+            BLOCK_IDB => interfaces.push(parse_interface(body)?),
+            BLOCK_EPB => {
+                let bad_packet = || invalid_data("bad pcapng packet block");
+                let interface = le_u32(body, 0)
+                    .and_then(|id| interfaces.get(id as usize))
+                    .ok_or_else(bad_packet)?;
+                let ticks = ((le_u32(body, 4).ok_or_else(bad_packet)? as u64) << 32)
+                    | le_u32(body, 8).ok_or_else(bad_packet)? as u64;
+                let captured = le_u32(body, 12).ok_or_else(bad_packet)? as usize;
+                let packet = body.get(20..20 + captured).ok_or_else(bad_packet)?;
+                let header_len = le_u16(packet, 0).ok_or_else(bad_packet)? as usize;
+                let info = *packet.get(16).ok_or_else(bad_packet)?;
+                records.push(CaptureRecord {
+                    timestamp_us: ticks * interface.ns_per_tick / 1000,
+                    direction: match info & 0x01 {
+                        0 => Direction::Out,
+                        _ => Direction::In,
+                    },
+                    uuid: interface.uuid,
+                    data: packet.get(header_len..).ok_or_else(bad_packet)?.to_vec(),
+                });
+            }
+            _ => {}
+        }
+        offset += total_len;
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_pcapng_blocks() {
+        let shb = pcapng_section_header();
+        assert_eq!(u32_at(&shb, 0), BLOCK_SHB);
+        assert_eq!(shb.len(), 28);
+        assert_eq!(u32_at(&shb, 8), BYTE_ORDER_MAGIC);
+
+        let idb = pcapng_interface_description(0x1234);
+        assert_eq!(u32_at(&idb, 0), BLOCK_IDB);
+        assert_eq!(idb.len() % 4, 0);
+        assert_eq!(u32_at(&idb, 4) as usize, idb.len());
+        assert_eq!(u32_at(&idb, idb.len() - 4) as usize, idb.len());
+        assert_eq!(u16::from_le_bytes([idb[8], idb[9]]), LINKTYPE_USBPCAP);
+
+        let record = CaptureRecord {
+            timestamp_us: 0x1_0000_0002,
+            direction: Direction::In,
+            uuid: 0x1234,
+            data: vec![0x22, 0x13, 0, 0, 5, 0, 0x00, 0x00, 0xAA],
+        };
+        let epb = pcapng_enhanced_packet(&record);
+        assert_eq!(u32_at(&epb, 0), BLOCK_EPB);
+        assert_eq!(epb.len() % 4, 0);
+        assert_eq!(u32_at(&epb, 4) as usize, epb.len());
+        assert_eq!(u32_at(&epb, 12), 1);
+        assert_eq!(u32_at(&epb, 16), 2);
+        let captured = u32_at(&epb, 20) as usize;
+        assert_eq!(captured, USBPCAP_HEADER_LEN as usize + record.data.len());
+        let packet = &epb[28..28 + captured];
+        assert_eq!(packet[16], 1);
+        assert_eq!(packet[21], 0x81);
+        assert_eq!(&packet[USBPCAP_HEADER_LEN as usize..], &record.data[..]);
+
+        let mut file = shb.clone();
+        file.extend(idb);
+        file.extend(epb);
+        assert_eq!(read_pcapng(&file[..]).unwrap(), vec![record.clone()]);
+        assert_eq!(read_json_lines(json_line(&record).as_bytes()).unwrap(), vec![record.clone()]);
+        // 带空白的 JSON 照常读取，损坏的行返回错误而不是 panic
+        let spaced = json_line(&record).replace(":", ": ").replace(",", ", ");
+        assert_eq!(read_json_lines(spaced.as_bytes()).unwrap(), vec![record.clone()]);
+        let bad = json_line(&record).replace("2213", "2é1");
+        assert!(read_json_lines(bad.as_bytes()).is_err());
+
+        let text = record.to_string();
+        assert!(text.starts_with("4294.967298 in "));
+        assert!(text.contains("DeviceInfo"));
+    }
+
+    #[test]
+    fn test_json_line_and_toggle() {
+        #[derive(Clone, Default)]
+        struct Shared(std::sync::Arc<Mutex<Vec<u8>>>);
+        impl Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let uuid = 0xCA97_0001;
+        let sink = Shared::default();
+        let captures = Captures::default();
+        captures.record(uuid, Direction::Out, &[0x01]);
+        captures.start(uuid, CaptureFormat::JsonLines, Box::new(sink.clone())).unwrap();
+        assert!(captures.is_capturing(uuid));
+        captures.record(uuid, Direction::Out, &[0x22, 0x13]);
+        captures.record(uuid, Direction::In, &[0x22, 0x00]);
+        captures.record(uuid + 1, Direction::In, &[0x21]);
+        assert_eq!(captures.stop(uuid).unwrap(), 2);
+        assert!(!captures.is_capturing(uuid));
+        captures.record(uuid, Direction::Out, &[0x02]);
+
+        let text = String::from_utf8(sink.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("\"dir\":\"out\""));
+        assert!(lines[0].contains("\"data\":\"2213\""));
+        assert!(lines[1].contains("\"dir\":\"in\""));
+        assert!(lines[1].contains(&uuid::Uuid::from_u128(uuid).to_string()));
+    }
+}
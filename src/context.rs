@@ -0,0 +1,593 @@
+// SayoContext 持有传输层、每个设备的报告解码器和回调注册表，可以创建多个互相隔离的上下文
+// （例如并行测试各用一条 VirtualBus）。用完后调用 shutdown 关闭：取消连接订阅和进行中的能力预读、移除所有报告监听器，
+// 该上下文下的广播流随之结束。未 shutdown 就释放时只标记关闭、结束各流，监听器在后台移除，不阻塞调用方。
+// SayoDeviceApi 句柄只弱引用所属上下文，上下文关闭后句柄上的调用返回 ContextClosed。
+// 旧的全局函数（init_sayo_device、get_device_list 等）使用进程级的默认上下文 SayoContext::global()，
+// 其传输层跟随 transport::set_transport。
+// 广播订阅、设备日志、抓包和 echo 池也属于上下文，同一 uuid 在不同上下文中互不可见。
+
+use futures::Future;
+use futures::channel::oneshot;
+use futures::lock::Mutex;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{Duration, Instant};
+
+use hid_rs::SafeCallback2;
+use log::{debug, info, trace, warn};
+
+use crate::broadcast::{self, Broadcasts};
+use crate::cancellation::CancellationToken;
+use crate::capabilities::Capabilities;
+use crate::capture::{Captures, Direction};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::device::block_in_thread;
+use crate::device::{ReportIdCache, SayoDeviceApi};
+use crate::device_error_handling::{DeviceError, DeviceResult};
+use crate::device_log::DeviceLogs;
+use crate::echo::{self, DEFAULT_ECHO_POOL, EchoPool};
+use crate::report_codec::ReportDecoder;
+use crate::structures::{BroadCast, HidReportHeader};
+use crate::transport::{self, ConnectionListener, ReportListener, Transport};
+use crate::utility::{future_delay, lock};
+
+enum TransportSource {
+    // 跟随 transport::set_transport
+    Global,
+    Owned(Arc<dyn Transport>),
+}
+
+// 回调在短暂持锁时取出副本，调用时不持锁
+// 连接时的能力预读：断开时取消，shutdown 时取消并等待结束
+struct Prefetch {
+    cancel: CancellationToken,
+    done: oneshot::Receiver<()>,
+}
+
+type Callbacks<T> = std::sync::Mutex<HashMap<u128, SafeCallback2<u128, T, ()>>>;
+
+pub(crate) struct ContextInner {
+    transport: TransportSource,
+    // 只在查找、插入时短暂持有，请求路径上不会因争用失败
+    codecs: std::sync::Mutex<HashMap<u128, Arc<Mutex<ReportDecoder>>>>,
+    connection_listener: std::sync::Mutex<Option<ConnectionListener>>,
+    report_callbacks: Callbacks<Vec<u8>>,
+    broadcast_callbacks: Callbacks<BroadCast>,
+    cmd_response_callbacks: Callbacks<(HidReportHeader, Vec<u8>)>,
+    // 每个设备的 report id 探测缓存
+    pub(crate) report_id_cache: std::sync::Mutex<HashMap<u128, ReportIdCache>>,
+    // 每个设备的能力，连接时预读，断开时清除；None 表示本次连接探测失败
+    pub(crate) capabilities: std::sync::Mutex<HashMap<u128, Option<Capabilities>>>,
+    prefetches: std::sync::Mutex<HashMap<u128, Prefetch>>,
+    pub(crate) broadcasts: Broadcasts,
+    pub(crate) logs: DeviceLogs,
+    pub(crate) captures: Captures,
+    pub(crate) echo_pool: Arc<std::sync::Mutex<EchoPool>>,
+    closed: AtomicBool,
+}
+
+impl ContextInner {
+    fn new(transport: TransportSource) -> Self {
+        ContextInner {
+            transport,
+            codecs: std::sync::Mutex::new(HashMap::new()),
+            connection_listener: std::sync::Mutex::new(None),
+            report_callbacks: std::sync::Mutex::new(HashMap::new()),
+            broadcast_callbacks: std::sync::Mutex::new(HashMap::new()),
+            cmd_response_callbacks: std::sync::Mutex::new(HashMap::new()),
+            report_id_cache: std::sync::Mutex::new(HashMap::new()),
+            capabilities: std::sync::Mutex::new(HashMap::new()),
+            prefetches: std::sync::Mutex::new(HashMap::new()),
+            broadcasts: Broadcasts::default(),
+            logs: DeviceLogs::default(),
+            captures: Captures::default(),
+            echo_pool: Arc::new(std::sync::Mutex::new(EchoPool::new(DEFAULT_ECHO_POOL))),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    pub(crate) fn transport(&self) -> Arc<dyn Transport> {
+        match &self.transport {
+            TransportSource::Global => transport::transport(),
+            TransportSource::Owned(transport) => transport.clone(),
+        }
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn require_codec(self: &Arc<Self>, uuid: u128) -> Arc<Mutex<ReportDecoder>> {
+        let mut binding = lock(&self.codecs);
+        if let Some(existing) = binding.get(&uuid) {
+            return existing.clone();
+        }
+        let on_broadcast = Arc::downgrade(self);
+        let on_cmd_response = Arc::downgrade(self);
+        let echo_pool = self.echo_pool.clone();
+        let decoder = Arc::new(Mutex::new(ReportDecoder::new(
+            uuid,
+            Arc::new(move |device, broadcast: &mut BroadCast| {
+                if let Some(context) = on_broadcast.upgrade() {
+                    context.on_broadcast_arrived(device, broadcast);
+                }
+            }),
+            Arc::new(move |device, header, data| {
+                if let Some(context) = on_cmd_response.upgrade() {
+                    context.on_cmd_response_arrived(device, header, data);
+                }
+            }),
+            Arc::new(move |echo| echo::is_local(&echo_pool, echo)),
+        )));
+        binding.insert(uuid, decoder.clone());
+        decoder
+    }
+
+    pub(crate) fn has_codec(&self, uuid: u128) -> bool {
+        lock(&self.codecs).contains_key(&uuid)
+    }
+
+    pub(crate) async fn on_connection_changed(self: &Arc<Self>, uuid: u128, connected: bool) -> DeviceResult<()> {
+        info!(uuid:% = uuid::Uuid::from_u128(uuid), connected; "device connection changed");
+        let transport = self.transport();
+
+        if connected {
+            // 建立解码器
+            self.require_codec(uuid);
+
+            // 添加报告监听器，监听器只弱引用上下文
+            let context = Arc::downgrade(self);
+            let report_callback = SafeCallback2::new(move |uuid, data: Vec<u8>| {
+                if let Some(context) = context.upgrade() {
+                    context.on_report_arrived(uuid, data);
+                }
+                Box::pin(async {}) as Pin<Box<dyn Future<Output = ()> + Send + 'static>>
+            });
+            debug!(uuid:% = uuid::Uuid::from_u128(uuid); "adding report listener");
+            transport.add_report_listener(uuid, &report_callback).await?;
+
+            lock(&self.report_callbacks).insert(uuid, report_callback);
+
+            // 预读设备能力，不阻塞连接回调；失败时由首个请求再读。
+            // 使用独占 echo，避免与调用方同时发出的 DeviceInfo 请求互相取走应答
+            // 每个设备至多一个预读，重复的连接事件取消前一个
+            let cancel = CancellationToken::new();
+            let device = SayoDeviceApi::in_context(uuid, Arc::downgrade(self));
+            let device = device.session().unwrap_or(device).with_cancellation(cancel.clone());
+            let (finished, done) = oneshot::channel();
+            let previous = lock(&self.prefetches).insert(uuid, Prefetch { cancel, done });
+            if let Some(previous) = previous {
+                previous.cancel.cancel();
+            }
+            let prefetch = async move {
+                if let Err(e) = device.capabilities().await {
+                    debug!(uuid:% = uuid::Uuid::from_u128(uuid); "failed to read capabilities: {}", e);
+                }
+                let _ = finished.send(());
+            };
+            #[cfg(not(target_arch = "wasm32"))]
+            std::thread::spawn(move || pollster::block_on(prefetch));
+            #[cfg(target_arch = "wasm32")]
+            wasm_bindgen_futures::spawn_local(prefetch);
+        } else {
+            // 移除报告监听器
+            let callback = lock(&self.report_callbacks).remove(&uuid);
+            if let Some(callback) = callback {
+                // 设备已拔出时移除可能失败，本地状态照常清理
+                if let Err(e) = transport.remove_report_listener(uuid, &callback).await {
+                    debug!(uuid:% = uuid::Uuid::from_u128(uuid); "failed to remove report listener: {}", e);
+                }
+            }
+
+            lock(&self.codecs).remove(&uuid);
+            if let Some(prefetch) = lock(&self.prefetches).remove(&uuid) {
+                prefetch.cancel.cancel();
+            }
+
+            // 结束该设备的广播流和日志流，日志历史保留
+            self.logs.close(uuid);
+            self.broadcasts.close(uuid);
+
+            // 清理报告ID缓存
+            lock(&self.report_id_cache).remove(&uuid);
+            lock(&self.capabilities).remove(&uuid);
+        }
+        Ok(())
+    }
+
+    fn on_broadcast_arrived(self: &Arc<Self>, device: u128, broadcast: &mut BroadCast) {
+        // 日志记录不论有无订阅者都会进入设备日志
+        let records = broadcast::records(broadcast);
+        self.logs.ingest(device, &records);
+        self.broadcasts.publish(device, &records);
+
+        let Some(callback) = lock(&self.broadcast_callbacks).get(&device).cloned() else {
+            return;
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            use pollster::FutureExt;
+
+            callback.call(device, broadcast.clone()).block_on();
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        {
+            let payload = broadcast.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                callback.call(device, payload).await;
+            });
+        }
+    }
+
+    fn on_cmd_response_arrived(self: &Arc<Self>, device: u128, header: HidReportHeader, data: Vec<u8>) {
+        let Some(callback) = lock(&self.cmd_response_callbacks).get(&device).cloned() else {
+            return;
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            use pollster::FutureExt;
+
+            callback.call(device, (header, data)).block_on();
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        wasm_bindgen_futures::spawn_local(async move {
+            callback.call(device, (header, data)).await;
+        });
+    }
+
+    fn on_report_arrived(self: &Arc<Self>, uuid: u128, data: Vec<u8>) {
+        self.captures.record(uuid, Direction::In, &data);
+        let cmd = data.get(6).cloned().unwrap_or(0);
+        // Lazily ensure a ReportDecoder exists to avoid executor re-entry panics when callbacks race.
+        let wrap_codec = self.require_codec(uuid);
+
+        // 如果锁繁忙，短暂等待（最多 20ms）再放弃，避免长时间阻塞或死锁。
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let deadline = Instant::now() + Duration::from_millis(20);
+            loop {
+                if let Some(mut codec) = wrap_codec.try_lock() {
+                    if let Err(e) = codec.join(&mut data.clone()) {
+                        warn!(uuid:% = uuid::Uuid::from_u128(uuid), cmd; "failed to join packet: {}", e);
+                    }
+                    break;
+                }
+
+                if Instant::now() >= deadline {
+                    warn!(uuid:% = uuid::Uuid::from_u128(uuid), cmd; "report decoder lock timeout, dropping packet");
+                    break;
+                }
+
+                // 小睡一会儿再抢锁，减少忙等
+                std::thread::sleep(Duration::from_micros(200));
+            }
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        {
+            if let Some(mut codec) = wrap_codec.try_lock() {
+                if let Err(e) = codec.join(&mut data.clone()) {
+                    warn!(uuid:% = uuid::Uuid::from_u128(uuid), cmd; "failed to join packet: {}", e);
+                }
+            } else {
+                warn!(uuid:% = uuid::Uuid::from_u128(uuid), cmd; "report decoder busy, dropping packet");
+            }
+        }
+    }
+
+    async fn shutdown(&self) {
+        if self.closed.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let listener = lock(&self.connection_listener).take();
+        let report_callbacks: Vec<(u128, ReportListener)> =
+            lock(&self.report_callbacks).drain().collect();
+        detach_listeners(self.transport(), listener, report_callbacks).await;
+
+        let prefetches: Vec<Prefetch> = lock(&self.prefetches).drain().map(|(_, p)| p).collect();
+        for prefetch in prefetches {
+            prefetch.cancel.cancel();
+            let _ = prefetch.done.await;
+        }
+
+        // 释放解码器后等待中的请求立即结束
+        lock(&self.codecs).clear();
+        self.logs.close_all();
+        self.broadcasts.close_all();
+        lock(&self.broadcast_callbacks).clear();
+        lock(&self.cmd_response_callbacks).clear();
+        lock(&self.report_id_cache).clear();
+        lock(&self.capabilities).clear();
+        debug!("context shut down");
+    }
+}
+
+async fn detach_listeners(
+    transport: Arc<dyn Transport>,
+    listener: Option<ConnectionListener>,
+    report_callbacks: Vec<(u128, ReportListener)>,
+) {
+    if let Some(listener) = listener
+        && let Err(e) = transport.unsub_connection_changed(listener).await
+    {
+        debug!("failed to unsubscribe connection changes: {}", e);
+    }
+    for (uuid, callback) in report_callbacks {
+        if let Err(e) = transport.remove_report_listener(uuid, &callback).await {
+            debug!(uuid:% = uuid::Uuid::from_u128(uuid); "failed to remove report listener: {}", e);
+        }
+    }
+}
+
+pub struct SayoContext {
+    inner: Arc<ContextInner>,
+}
+
+static GLOBAL: Lazy<SayoContext> = Lazy::new(|| SayoContext {
+    inner: Arc::new(ContextInner::new(TransportSource::Global)),
+});
+
+impl SayoContext {
+    pub fn new(transport: Arc<dyn Transport>) -> Self {
+        SayoContext {
+            inner: Arc::new(ContextInner::new(TransportSource::Owned(transport))),
+        }
+    }
+
+    // 进程级默认上下文，SayoDeviceApi::from(uuid) 和 device 模块的全局函数使用它
+    pub fn global() -> &'static SayoContext {
+        &GLOBAL
+    }
+
+    pub(crate) fn downgrade(&self) -> Weak<ContextInner> {
+        Arc::downgrade(&self.inner)
+    }
+
+    pub fn transport(&self) -> Arc<dyn Transport> {
+        self.inner.transport()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    // 初始化传输层并订阅连接变化，以便设备插入时建立解码器；shutdown 之后可以再次 init
+    pub async fn init(&self) -> DeviceResult<()> {
+        let transport = self.transport();
+        transport.init().await?;
+        debug!("HID initialized");
+
+        let context = Arc::downgrade(&self.inner);
+        let listener = SafeCallback2::new(move |hid, connected| {
+            trace!(uuid:% = uuid::Uuid::from_u128(hid), connected; "connection callback");
+            let context = context.clone();
+
+            // On some platforms (Android), the caller may not poll the returned future.
+            // To ensure the side effects run reliably, spawn the async body and return
+            // an already-ready future.
+            let body = async move {
+                let Some(context) = context.upgrade() else {
+                    return;
+                };
+                if let Err(e) = context.on_connection_changed(hid, connected).await {
+                    warn!(uuid:% = uuid::Uuid::from_u128(hid), connected; "connection change handling failed: {}", e);
+                }
+            };
+            #[cfg(not(target_arch = "wasm32"))]
+            block_in_thread(body);
+            #[cfg(target_arch = "wasm32")]
+            wasm_bindgen_futures::spawn_local(body);
+
+            // Return a ready future so the signature is satisfied regardless of polling behavior.
+            Box::pin(async {}) as Pin<Box<dyn Future<Output = ()> + Send + 'static>>
+        });
+        transport.sub_connection_changed(listener.clone()).await?;
+        let previous = lock(&self.inner.connection_listener).replace(listener);
+        if let Some(previous) = previous {
+            // 重复 init 时只保留一个订阅
+            let _ = transport.unsub_connection_changed(previous).await;
+        }
+        self.inner.closed.store(false, Ordering::Release);
+        debug!("connection change subscription registered");
+        Ok(())
+    }
+
+    // 关闭上下文的正确方式：取消连接订阅、移除所有报告监听器、结束该上下文下设备的广播流，
+    // 取消并等待连接时启动的能力预读。返回时传输层上已没有本上下文的监听器；
+    // 进行中的请求以错误结束，之后句柄上的调用返回 ContextClosed
+    pub async fn shutdown(&self) {
+        self.inner.shutdown().await;
+    }
+
+    // 之后 session() 从该范围分配 echo，已分配的不受影响
+    pub fn set_echo_pool_range(&self, range: RangeInclusive<u8>) {
+        lock(&self.inner.echo_pool).set_range(range);
+    }
+
+    // 排除其他客户端使用的 echo
+    pub fn reserve_echo(&self, echo: u8) {
+        lock(&self.inner.echo_pool).reserve(echo);
+    }
+
+    // 未指定 RequestOptions::echo 的请求使用的 echo，默认为 SayoDeviceApi::ECHO
+    pub fn default_echo(&self) -> u8 {
+        lock(&self.inner.echo_pool).default_echo()
+    }
+
+    // 立即作用于该上下文下所有未指定 echo 的句柄
+    pub fn set_default_echo(&self, echo: u8) -> DeviceResult<()> {
+        if echo == echo::BROADCAST_ECHO {
+            return Err(DeviceError::InvalidData("echo 0x00 保留给广播".to_string()));
+        }
+        lock(&self.inner.echo_pool).set_default_echo(echo);
+        Ok(())
+    }
+
+    pub fn device(&self, uuid: u128) -> SayoDeviceApi {
+        SayoDeviceApi::in_context(uuid, self.downgrade())
+    }
+
+    pub async fn device_list(&self) -> DeviceResult<Vec<SayoDeviceApi>> {
+        let devices = self.transport().device_list()?;
+        Ok(devices.into_iter().map(|uuid| self.device(uuid)).collect())
+    }
+
+    // 已连接设备在 init 之后经连接事件异步注册；等到列表非空且每个设备都已注册监听器，
+    // 最多 timeout_ms，到期时返回当时的列表
+    pub async fn wait_device_list(&self, timeout_ms: u32) -> DeviceResult<Vec<SayoDeviceApi>> {
+        const POLL_MS: u32 = 10;
+        let mut waited = 0;
+        loop {
+            let devices = self.transport().device_list()?;
+            let ready = !devices.is_empty() && {
+                let callbacks = lock(&self.inner.report_callbacks);
+                devices.iter().all(|uuid| callbacks.contains_key(uuid))
+            };
+            if ready || waited >= timeout_ms {
+                return Ok(devices.into_iter().map(|uuid| self.device(uuid)).collect());
+            }
+            future_delay(POLL_MS).await;
+            waited += POLL_MS;
+        }
+    }
+
+    // vpid 高 16 位为 vid，低 16 位为 pid，pid 为 0 时匹配该 vid 的所有设备
+    pub async fn request_device(&self, vpids: Vec<u32>) -> DeviceResult<()> {
+        let filter = vpids
+            .into_iter()
+            .map(|vpid| {
+                let pid = (vpid & 0xFFFF) as u16;
+                ((vpid >> 16) as u16, (pid != 0).then_some(pid))
+            })
+            .collect();
+        self.transport().request_device(filter).await
+    }
+
+    pub async fn sub_connection_changed(&self, callback: ConnectionListener) -> DeviceResult<()> {
+        self.transport().sub_connection_changed(callback).await
+    }
+
+    pub async fn unsub_connection_changed(&self, callback: ConnectionListener) -> DeviceResult<()> {
+        self.transport().unsub_connection_changed(callback).await
+    }
+
+    pub async fn sub_cmd_response(
+        &self,
+        uuid: u128,
+        callback: &SafeCallback2<u128, (HidReportHeader, Vec<u8>), ()>,
+    ) -> DeviceResult<()> {
+        lock(&self.inner.cmd_response_callbacks).insert(uuid, callback.clone());
+        Ok(())
+    }
+
+    pub async fn unsub_cmd_response(&self, uuid: u128) -> DeviceResult<()> {
+        lock(&self.inner.cmd_response_callbacks).remove(&uuid);
+        Ok(())
+    }
+
+    // 每个设备只保留一个回调，多个订阅者请使用 SayoDeviceApi::broadcasts
+    pub async fn sub_broadcast(&self, uuid: u128, callback: &SafeCallback2<u128, BroadCast, ()>) -> DeviceResult<()> {
+        lock(&self.inner.broadcast_callbacks).insert(uuid, callback.clone());
+        Ok(())
+    }
+
+    pub async fn unsub_broadcast(&self, uuid: u128) -> DeviceResult<()> {
+        lock(&self.inner.broadcast_callbacks).remove(&uuid);
+        Ok(())
+    }
+}
+
+// 未调用 shutdown 就释放时不等待传输层：标记关闭、结束各流，监听器交给后台移除。
+// 监听器只弱引用上下文，移除完成前收到的报告直接丢弃
+impl Drop for SayoContext {
+    fn drop(&mut self) {
+        if self.inner.closed.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let listener = lock(&self.inner.connection_listener).take();
+        let report_callbacks: Vec<(u128, ReportListener)> =
+            lock(&self.inner.report_callbacks).drain().collect();
+        lock(&self.inner.codecs).clear();
+        for (_, prefetch) in lock(&self.inner.prefetches).drain() {
+            prefetch.cancel.cancel();
+        }
+        self.inner.logs.close_all();
+        self.inner.broadcasts.close_all();
+
+        let detach = detach_listeners(self.inner.transport(), listener, report_callbacks);
+        #[cfg(not(target_arch = "wasm32"))]
+        std::thread::spawn(move || pollster::block_on(detach));
+        #[cfg(target_arch = "wasm32")]
+        wasm_bindgen_futures::spawn_local(detach);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device_error_handling::DeviceError;
+    use crate::simulator::{VirtualBus, VirtualDevice};
+    use crate::structures::DeviceInfo;
+    use crate::structures_codec::CodecableHidPackage;
+    use futures::StreamExt;
+    use pollster::block_on;
+
+    #[test]
+    fn test_isolated_contexts() {
+        let uuid = 0x5A10_0000_0000_0000_0000_0000_0000_0019;
+        let bus_a = Arc::new(VirtualBus::new());
+        let bus_b = Arc::new(VirtualBus::new());
+        let context_a = SayoContext::new(bus_a.clone());
+        let context_b = SayoContext::new(bus_b.clone());
+        for (bus, context) in [(&bus_a, &context_a), (&bus_b, &context_b)] {
+            block_on(context.init()).expect("init");
+            block_on(bus.attach(VirtualDevice::new(uuid)));
+        }
+        bus_b.device(uuid).unwrap().remove_entry(DeviceInfo::CMD.unwrap(), 0);
+
+        let device_a = block_on(context_a.wait_device_list(1000)).unwrap().remove(0);
+        let device_b = context_b.device(uuid);
+        assert_ne!(device_a, device_b);
+        // 默认 echo 属于上下文，修改后立即作用于已有句柄
+        context_b.set_default_echo(0x55).unwrap();
+        assert_eq!(device_b.echo(), 0x55);
+        assert_eq!(device_a.echo(), SayoDeviceApi::ECHO);
+        assert!(block_on(device_a.get_device_info()).is_ok());
+        assert!(block_on(device_b.get_device_info()).unwrap_err().is_unsupported());
+
+        // 同一 uuid 的广播只到达所属上下文的订阅者
+        let mut broadcasts = device_a.broadcasts();
+        let mut broadcasts_b = device_b.broadcasts();
+        block_on(bus_a.emit_broadcast(uuid, &[0x10, 0x01, 0x00])).unwrap();
+        let record = block_on(broadcasts.next()).unwrap().unwrap();
+        assert_eq!(record.data(None), Some(vec![0x01]));
+
+        // 释放后句柄失效、广播流结束，另一个上下文不受影响
+        drop(context_a);
+        assert!(block_on(broadcasts.next()).is_none());
+        assert!(block_on(device_a.broadcasts().next()).is_none());
+        let err = block_on(device_a.get_device_info()).unwrap_err();
+        assert!(matches!(err, DeviceError::ContextClosed));
+        assert!(block_on(device_b.get_device_info()).unwrap_err().is_unsupported());
+        block_on(bus_b.emit_broadcast(uuid, &[0x10, 0x02, 0x00])).unwrap();
+        let record = block_on(broadcasts_b.next()).unwrap().unwrap();
+        assert_eq!(record.data(None), Some(vec![0x02]));
+        assert_eq!(device_b.broadcast_subscriber_count(), 1);
+
+        block_on(context_b.shutdown());
+        assert!(context_b.is_closed());
+        assert!(matches!(block_on(device_b.get_device_info()), Err(DeviceError::ContextClosed)));
+
+        // 没有设备时等到期限返回空列表
+        let context = SayoContext::new(Arc::new(VirtualBus::new()));
+        block_on(context.init()).expect("init");
+        assert!(block_on(context.wait_device_list(30)).unwrap().is_empty());
+    }
+}
@@ -52,6 +52,20 @@ pub fn now_millis() -> u64 {
         .as_millis() as u64
 }
 
+// 微秒级 Unix 时间戳，用于抓包记录
+#[cfg(not(target_arch = "wasm32"))]
+pub fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn now_micros() -> u64 {
+    (js_sys::Date::now() * 1000.0) as u64
+}
+
 // 跨平台的性能计时器
 #[derive(Debug)]
 pub struct CrossPlatformTimer {
@@ -77,9 +91,9 @@ impl CrossPlatformTimer {
 // 跨平台的日志记录
 pub fn log_performance(operation: &str, duration_ms: u64) {
     if duration_ms > 1000 {
-        println!("⚠️  Slow operation: {} took {}ms", operation, duration_ms);
+        log::warn!(operation, duration_ms; "slow operation");
     } else if duration_ms > 100 {
-        println!("⏱️  Operation: {} took {}ms", operation, duration_ms);
+        log::debug!(operation, duration_ms; "operation timing");
     }
 }
 
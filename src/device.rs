@@ -1,22 +1,35 @@
 use pollster::block_on;
 use futures::Future;
 use futures::future::Either;
-use futures::lock::Mutex;
-use once_cell::sync::Lazy;
 use std::cell::Cell;
-use std::collections::HashMap;
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
 #[cfg(not(target_arch = "wasm32"))]
 use std::time::{Duration, Instant};
 
 use crate::device_constants::*;
-use crate::utility::future_delay;
+use crate::utility::{future_delay, lock};
 
 use crate::byte_converter::{Encoding, RwBytes};
-use hid_rs::{self, HidDevice, SafeCallback, SafeCallback2};
-
-fn block_in_thread<T: Send + 'static>(future: impl Future<Output = T> + Send + 'static) -> T {
+use crate::device_error_handling::{DeviceError, DeviceResult};
+use crate::backup::{self, ConfigArchive, Section};
+use crate::diff::{self, ConfigDiff};
+use crate::restore::{self, RestoreOptions, RestorePreview, RestoreReport};
+use crate::broadcast::{BroadcastEvent, BroadcastStream, DEFAULT_BROADCAST_CAPACITY};
+use crate::cancellation::CancellationToken;
+use crate::capabilities::Capabilities;
+use crate::device_log::{LogEntry, LogStream};
+use crate::echo::{self, EchoLease};
+use crate::capture::{CaptureFormat, Direction};
+use crate::input_state::InputStream;
+use crate::levels::{self, LevelsFrame};
+use crate::transfer::{self, TransferChunk, TransferReport};
+use crate::context::{ContextInner, SayoContext};
+use crate::transport::Transport;
+use hid_rs::{HidDevice, SafeCallback, SafeCallback2};
+use log::{debug, trace, warn};
+
+pub(crate) fn block_in_thread<T: Send + 'static>(future: impl Future<Output = T> + Send + 'static) -> T {
     std::thread::spawn(move || block_on(future)).join().expect("async worker panicked")
 }
 
@@ -26,351 +39,53 @@ use crate::structures_codec::CodecableHidPackage;
 
 // use crate::utility::*;
 
-#[path = "report_codec.rs"]
-mod report_codec;
-
-fn require_report_codec(uuid: u128) -> Option<Arc<Mutex<report_codec::ReportDecoder>>> {
-    let mut binding = REPORT_BUFFER_CODEC.try_lock()?;
-    if let Some(existing) = binding.get(&uuid) {
-        return Some(existing.clone());
-    }
-    let decoder = Arc::new(Mutex::new(report_codec::ReportDecoder::new(
-        uuid,
-        Arc::new(on_broadcast_arrived),
-        Arc::new(on_cmd_response_arrived),
-    )));
-    binding.insert(uuid, decoder.clone());
-    Some(decoder)
-}
-
-static REPORT_BUFFER_CODEC: Lazy<Mutex<HashMap<u128, Arc<Mutex<report_codec::ReportDecoder>>>>> =
-    Lazy::new(|| Mutex::new(HashMap::new()));
-static CONNECTION_CALLBACK: Lazy<SafeCallback2<u128, bool, ()>> = Lazy::new(|| {
-    SafeCallback2::new(|hid, connected| {
-        println!(
-            "CONNECTION_CALLBACK called: {:?} {:?}",
-            uuid::Uuid::from_u128(hid),
-            connected
-        );
-
-        // On some platforms (Android), the caller may not poll the returned future.
-        // To ensure the side effects run reliably, spawn the async body and return
-        // an already-ready future.
-        #[cfg(not(target_arch = "wasm32"))]
-        {
-            let hid_m = hid;
-            let connected_m = connected;
-            block_in_thread(async move {
-                println!(
-                    "on_connection_changed called from CONNECTION_CALLBACK: {:?} {:?}",
-                    uuid::Uuid::from_u128(hid_m),
-                    connected_m
-                );
-                let _ = on_connection_changed(hid_m, connected_m).await;
-            });
-        }
-
-        #[cfg(target_arch = "wasm32")]
-        {
-            let hid_m = hid;
-            let connected_m = connected;
-            wasm_bindgen_futures::spawn_local(async move {
-                println!(
-                    "on_connection_changed called from CONNECTION_CALLBACK: {:?} {:?}",
-                    uuid::Uuid::from_u128(hid_m),
-                    connected_m
-                );
-                let _ = on_connection_changed(hid_m, connected_m).await;
-            });
-        }
-
-        // Return a ready future so the signature is satisfied regardless of polling behavior.
-        Box::pin(async {})
-    })
-});
-static REPORT_CALLBACKS: Lazy<Mutex<HashMap<u128, SafeCallback2<u128, Vec<u8>, ()>>>> =
-    Lazy::new(|| Mutex::new(HashMap::new()));
-static BROADCAST_CALLBACKS: Lazy<Mutex<HashMap<u128, SafeCallback2<u128, BroadCast, ()>>>> =
-    Lazy::new(|| Mutex::new(HashMap::new()));
-static CMD_RESPONSE_CALLBACKS:
-    Lazy<Mutex<HashMap<u128, SafeCallback2<u128, (HidReportHeader, Vec<u8>), ()>>>> =
-    Lazy::new(|| Mutex::new(HashMap::new()));
-
-// Global per-device cache for report-id presence
-static REPORT_ID_CACHE_MAP: Lazy<std::sync::Mutex<HashMap<u128, ReportIdCache>>> =
-    Lazy::new(|| std::sync::Mutex::new(HashMap::new()));
-
-// pub async fn init_app() {
-//     init_sayo_device().await;
-// }
-
-pub async fn init_sayo_device() {
-    match hid_rs::Hid::init_hid().await {
-        Ok(_) => println!("HID initialized."),
-        Err(e) => println!("HID initialization failed: {:?}", e),
-    }
-
-    // Subscribe to connection changes so we can initialize per-device report decoders on attach.
-    match hid_rs::Hid::sub_connection_changed(CONNECTION_CALLBACK.clone()).await {
-        Ok(_) => println!("Connection change subscription registered."),
-        Err(e) => println!("Connection change subscription failed: {:?}", e),
-    }
-}
-
-async fn on_connection_changed(uuid: u128, connected: bool) -> bool {
-    println!(
-        "Device connection changed {:?} {:?}",
-        uuid::Uuid::from_u128(uuid),
-        connected
-    );
-
-    let hid = HidDevice::from(uuid);
-
-    // if !hid.has_report_id(0x21) && !hid.has_report_id(0x22) {
-    //     println!("Device {:?} has no report id", hid.uuid);
-    //     return false;
-    // }
-
-    if connected {
-        //println!("device name {:?}", hid.get_product_name());
-
-        // 先处理设备状态
-        // 再处理报告缓冲区编解码器
-        {
-            // Ensure decoder exists (best-effort; ignore if lock is busy)
-            let _ = require_report_codec(hid.uuid);
-        } // 释放REPORT_BUFFER_CODEC锁
-
-        // 添加报告监听器
-        let report_callback = SafeCallback2::new(on_report_arrived);
-        println!(
-            "Adding report listener for device {:?}",
-            uuid::Uuid::from_u128(hid.uuid)
-        );
-        if let Err(e) = hid.add_report_listener(&report_callback).await {
-            println!("Failed to add report listener: {:?}", e);
-        }
-
-        // 存储回调
-        {
-            let mut report_callbacks = REPORT_CALLBACKS.lock().await;
-            report_callbacks.insert(hid.uuid, report_callback);
-        } // 释放REPORT_CALLBACKS锁
-    } else {
-        // 移除报告监听器
-        {
-            let mut report_callbacks = REPORT_CALLBACKS.lock().await;
-            if let Some(callback) = report_callbacks.get(&hid.uuid) {
-                if let Err(e) = hid.remove_report_listener(&callback).await {
-                    println!("Failed to remove report listener: {:?}", e);
-                }
-                report_callbacks.remove(&hid.uuid);
-            }
-        } // 释放REPORT_CALLBACKS锁
-
-        // 清理其他资源
-        {
-            let mut binding = REPORT_BUFFER_CODEC.lock().await;
-            binding.remove(&hid.uuid);
-        } // 释放REPORT_BUFFER_CODEC锁
-
-        // 清理报告ID缓存
-        {
-            let mut report_id_map = REPORT_ID_CACHE_MAP.lock().unwrap();
-            report_id_map.remove(&hid.uuid);
-        }
-    }
-    println!("Device connection changed done");
-    return true;
-}
-
-fn on_broadcast_arrived(device: u128, broadcast: &mut BroadCast) {
-    #[cfg(not(target_arch = "wasm32"))]
-    {
-        let callbacks = BROADCAST_CALLBACKS
-            .try_lock()
-            .expect("BROADCAST_CALLBACKS lock poisoned");
-        if let Some(callback) = callbacks.get(&device) {
-            use pollster::FutureExt;
-
-            let callback_clone = callback.clone();
-            drop(callbacks);
-            let _ = callback_clone.call(device, broadcast.clone()).block_on();
-        }
-    }
-
-    #[cfg(target_arch = "wasm32")]
-    {
-        let payload = broadcast.clone();
-        wasm_bindgen_futures::spawn_local(async move {
-            let callbacks = BROADCAST_CALLBACKS.lock().await;
-            if let Some(callback) = callbacks.get(&device) {
-                let callback_clone = callback.clone();
-                drop(callbacks);
-                let _ = callback_clone.call(device, payload).await;
-            }
-        });
-    }
-}
-
-fn on_cmd_response_arrived(device: u128, header: HidReportHeader, data: Vec<u8>) {
-    // println!("on_cmd_response_arrived called for device {:?} cmd {:02X?}", uuid::Uuid::from_u128(device), header.cmd(None));
-    #[cfg(not(target_arch = "wasm32"))]
-    {
-        let callbacks = CMD_RESPONSE_CALLBACKS
-            .try_lock()
-            .expect("CMD_RESPONSE_CALLBACKS lock poisoned");
-        if let Some(callback) = callbacks.get(&device) {
-            use pollster::FutureExt;
-
-            let callback_clone = callback.clone();
-            drop(callbacks);
-            // println!("on_cmd_response_arrived calling callback for device {:?} cmd {:02X?}", uuid::Uuid::from_u128(device), header.cmd(None));
-            let _ = callback_clone.call(device, (header.clone(), data.clone())).block_on();
-        }
-    }
-
-    #[cfg(target_arch = "wasm32")]
-    {
-        let payload_header = header.clone();
-        let payload_data = data.clone();
-        wasm_bindgen_futures::spawn_local(async move {
-            let callbacks = CMD_RESPONSE_CALLBACKS.lock().await;
-            if let Some(callback) = callbacks.get(&device) {
-                let callback_clone = callback.clone();
-                drop(callbacks);
-                let _ = callback_clone.call(device, (payload_header, payload_data)).await;
-            }
-        });
-    }
-}
-
-fn on_report_arrived(
-    uuid: u128,
-    data: Vec<u8>,
-) -> Pin<Box<dyn Future<Output = ()> + Send + 'static>> {
-    let cmd = data.get(6).cloned().unwrap_or(0);
-    let header_bytes = &data[..8.min(data.len())];
-    let body_bytes = &data[8.min(data.len())..];
-    // if cmd != 0xFF && cmd != 0x13 && cmd != 0x25 && cmd != 0x15 && cmd != 0x27 {
-    //     println!("Report arrived: {:02X?} {:02X?}", header_bytes, body_bytes);
-    // }
-    // println!("Report arrived ({:02X?}): {:02X?} {:02X?} end;", data.len(), header_bytes, body_bytes);
-    // Lazily ensure a ReportDecoder exists to avoid executor re-entry panics when callbacks race.
-    let Some(wrap_codec) = require_report_codec(uuid) else {
-        println!("ReportDecoder unavailable (lock busy?) for device {:?}, dropping packet", uuid::Uuid::from_u128(uuid));
-        return Box::pin(async {});
-    };
-
-    // 如果锁繁忙，短暂等待（最多 20ms）再放弃，避免长时间阻塞或死锁。
-    #[cfg(not(target_arch = "wasm32"))]
-    {
-        let deadline = Instant::now() + Duration::from_millis(20);
-        loop {
-            if let Some(mut codec) = wrap_codec.try_lock() {
-                if let Err(e) = codec.join(&mut data.clone()) {
-                    println!("Failed to join packet: {}", e);
-                }
-                break;
-            }
-
-            if Instant::now() >= deadline {
-                println!("ReportDecoder lock timeout for device {:?}, dropping packet", uuid::Uuid::from_u128(uuid));
-                break;
-            }
-
-            // 小睡一会儿再抢锁，减少忙等
-            std::thread::sleep(Duration::from_micros(200));
-        }
-    }
-
-    #[cfg(target_arch = "wasm32")]
-    {
-        if let Some(mut codec) = wrap_codec.try_lock() {
-            if let Err(e) = codec.join(&mut data.clone()) {
-                println!("Failed to join packet: {}", e);
-            }
-        } else {
-            println!("ReportDecoder unavailable (lock busy?) for device {:?}, dropping packet", uuid::Uuid::from_u128(uuid));
-        }
-    }
+use crate::report_codec::{self, ReportError, RequestOptions, ResponseStatus};
 
-    // if data[6] != 0xFF && data[6] != 0x13 && data[6] != 0x25 && data[6] != 0x15 && data[6] != 0x27 {
-    //     let packet_len = (data[4] as u16 | (data[5] as u16) << 8) & 0x03FF;
-    //     println!("Report arrived: {:02X?} {:02X?}", data[..8].to_vec(), data[8..(8 + packet_len as usize)].to_vec());
-    // }
-    return Box::pin(async {});
+pub async fn init_sayo_device() -> DeviceResult<()> {
+    SayoContext::global().init().await
 }
 
-pub async fn sub_connection_changed(callback: SafeCallback2<u128, bool, ()>) {
-    println!("sub_connection_changed called");
-    match hid_rs::Hid::sub_connection_changed(callback).await {
-        Ok(_) => (),
-        Err(_) => {
-            println!("sub_connection_changed failed");
-        }
-    };
+pub async fn sub_connection_changed(callback: SafeCallback2<u128, bool, ()>) -> DeviceResult<()> {
+    SayoContext::global().sub_connection_changed(callback).await
 }
 
-pub async fn unsub_connection_changed(callback: SafeCallback2<u128, bool, ()>) {
-    match hid_rs::Hid::unsub_connection_changed(callback).await {
-        Ok(_) => (),
-        Err(_) => {
-            println!("unsub_connection_changed failed");
-        }
-    };
+pub async fn unsub_connection_changed(callback: SafeCallback2<u128, bool, ()>) -> DeviceResult<()> {
+    SayoContext::global().unsub_connection_changed(callback).await
 }
 
 pub async fn sub_cmd_response(
     uuid: u128,
     callback: &SafeCallback2<u128, (HidReportHeader, Vec<u8>), ()>,
-) -> Result<(), String> {
-    let mut callbacks = CMD_RESPONSE_CALLBACKS.lock().await;
-    callbacks.insert(uuid, callback.clone());
-    Ok(())
+) -> DeviceResult<()> {
+    SayoContext::global().sub_cmd_response(uuid, callback).await
 }
 
-pub async fn unsub_cmd_response(uuid: u128) -> Result<(), String> {
-    let mut callbacks = CMD_RESPONSE_CALLBACKS.lock().await;
-    callbacks.remove(&uuid);
-    Ok(())
+pub async fn unsub_cmd_response(uuid: u128) -> DeviceResult<()> {
+    SayoContext::global().unsub_cmd_response(uuid).await
 }
 
-pub async fn sub_broadcast(uuid: u128, callback: &SafeCallback2<u128, BroadCast, ()>) -> Result<(), String> {
-    let mut callbacks = BROADCAST_CALLBACKS.lock().await;
-    callbacks.insert(uuid, callback.clone());
-    Ok(())
+// 每个设备只保留一个回调，多个订阅者请使用 SayoDeviceApi::broadcasts
+pub async fn sub_broadcast(uuid: u128, callback: &SafeCallback2<u128, BroadCast, ()>) -> DeviceResult<()> {
+    SayoContext::global().sub_broadcast(uuid, callback).await
 }
 
-pub async fn unsub_broadcast(uuid: u128) -> Result<(), String> {
-    let mut callbacks = BROADCAST_CALLBACKS.lock().await;
-    callbacks.remove(&uuid);
-    Ok(())
+pub async fn unsub_broadcast(uuid: u128) -> DeviceResult<()> {
+    SayoContext::global().unsub_broadcast(uuid).await
 }
 
-pub async fn request_device(vpids: Vec<u32>) {
-    let mut filter = vec![];
-    for vpid in vpids {
-        let vid = vpid >> 16;
-        let pid = vpid & 0xFFFF;
-        filter.push((vid as u16, match pid {
-            0x0000 => None,
-            _ => Some(pid as u16),
-        }));
-    }
-    let _ = hid_rs::Hid::request_device(filter).await;
+pub async fn request_device(vpids: Vec<u32>) -> DeviceResult<()> {
+    SayoContext::global().request_device(vpids).await
 }
 
-pub async fn get_device_list() -> Vec<SayoDeviceApi> {
-    let devices = match hid_rs::Hid::get_device_list() {
-        Ok(devices) => devices,
-        Err(_) => {
-            return Vec::new();
-        }
-    };
-    devices.into_iter().map(|device| device.into()).collect()
+pub async fn get_device_list() -> DeviceResult<Vec<SayoDeviceApi>> {
+    SayoContext::global().device_list().await
 }
 
+pub async fn wait_device_list(timeout_ms: u32) -> DeviceResult<Vec<SayoDeviceApi>> {
+    SayoContext::global().wait_device_list(timeout_ms).await
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum ScreenLayer {
     Bootup = 0x21,
     Main = 0x22,
@@ -382,7 +97,7 @@ pub enum ScreenLayer {
 const REPORT_ID_WARMUP_SECS: u64 = 2;
 
 #[cfg(not(target_arch = "wasm32"))]
-struct ReportIdCache {
+pub(crate) struct ReportIdCache {
     created: Instant,
     has_22: Option<bool>,
     has_21: Option<bool>,
@@ -419,7 +134,7 @@ impl ReportIdCache {
 }
 
 #[cfg(target_arch = "wasm32")]
-struct ReportIdCache {
+pub(crate) struct ReportIdCache {
     created_ms: f64,
     has_22: Option<bool>,
     has_21: Option<bool>,
@@ -460,20 +175,37 @@ impl ReportIdCache {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone)]
 pub struct SayoDeviceApi {
     pub uuid: u128,
+    options: RequestOptions,
+    cancel: Option<CancellationToken>,
+    // session() 分配的 echo，所有克隆释放后归还
+    lease: Option<Arc<EchoLease>>,
+    // 所属上下文，不延长其生命周期
+    context: Weak<ContextInner>,
+}
+// 同一上下文中同一设备的不同句柄视为相等，与请求选项无关
+impl PartialEq for SayoDeviceApi {
+    fn eq(&self, other: &Self) -> bool {
+        self.uuid == other.uuid && Weak::ptr_eq(&self.context, &other.context)
+    }
+}
+impl Eq for SayoDeviceApi {}
+impl std::hash::Hash for SayoDeviceApi {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.uuid.hash(state);
+    }
 }
 impl From<HidDevice> for SayoDeviceApi {
     fn from(hid_device: HidDevice) -> Self {
-        SayoDeviceApi {
-            uuid: hid_device.uuid,
-        }
+        SayoDeviceApi::from(hid_device.uuid)
     }
 }
+// 属于默认上下文 SayoContext::global()
 impl From<u128> for SayoDeviceApi {
     fn from(uuid: u128) -> Self {
-        SayoDeviceApi { uuid: uuid }
+        SayoDeviceApi::in_context(uuid, SayoContext::global().downgrade())
     }
 }
 
@@ -490,35 +222,313 @@ impl SayoDeviceApi {
     pub const ECHO: u8 = 0x12;
 
     pub fn from_uuid(uuid: u128) -> Self {
-        SayoDeviceApi { uuid: uuid }
+        SayoDeviceApi::from(uuid)
+    }
+
+    pub(crate) fn in_context(uuid: u128, context: Weak<ContextInner>) -> Self {
+        SayoDeviceApi {
+            uuid,
+            options: RequestOptions::default(),
+            cancel: None,
+            lease: None,
+            context,
+        }
     }
 
-    pub async fn passiv_mode(&self) -> bool {
-        return on_connection_changed(self.uuid, false).await;
+    fn context(&self) -> DeviceResult<Arc<ContextInner>> {
+        match self.context.upgrade() {
+            Some(context) if !context.is_closed() => Ok(context),
+            _ => Err(DeviceError::ContextClosed),
+        }
     }
 
-    pub async fn active_mode(&self) -> bool {
-        let hid = HidDevice::from(self.uuid);
-        if !hid.has_report_id(0x21) && !hid.has_report_id(0x22) && !hid.has_report_id(0x02) {
-            return false;
+    fn transport(&self) -> DeviceResult<Arc<dyn Transport>> {
+        Ok(self.context()?.transport())
+    }
+
+    pub fn options(&self) -> &RequestOptions {
+        &self.options
+    }
+
+    pub fn set_options(&mut self, options: RequestOptions) {
+        self.options = options;
+    }
+
+    // 返回使用指定选项的句柄，用于单次调用覆盖实例选项
+    pub fn with_options(&self, options: RequestOptions) -> SayoDeviceApi {
+        SayoDeviceApi {
+            uuid: self.uuid,
+            options,
+            cancel: self.cancel.clone(),
+            lease: self.lease.clone(),
+            context: self.context.clone(),
+        }
+    }
+
+    // 返回使用独占 echo 的句柄，同一进程内的独立组件各自持有，应答不会被其他组件取走；
+    // 池用完时返回 EchoExhausted。跨进程共存见 echo 模块说明
+    pub fn session(&self) -> DeviceResult<SayoDeviceApi> {
+        let lease = echo::lease(&self.context()?.echo_pool)?;
+        let options = RequestOptions {
+            echo: Some(lease.echo()),
+            ..self.options
+        };
+        Ok(SayoDeviceApi {
+            uuid: self.uuid,
+            options,
+            cancel: self.cancel.clone(),
+            lease: Some(Arc::new(lease)),
+            context: self.context.clone(),
+        })
+    }
+
+    // 返回绑定取消令牌的句柄，令牌取消后该句柄上的请求、枚举和传输尽快以 Cancelled 结束
+    pub fn with_cancellation(&self, token: CancellationToken) -> SayoDeviceApi {
+        SayoDeviceApi {
+            uuid: self.uuid,
+            options: self.options,
+            cancel: Some(token),
+            lease: self.lease.clone(),
+            context: self.context.clone(),
+        }
+    }
+
+    // 请求实际使用的 echo：RequestOptions::echo，未指定时为所属上下文的默认 echo
+    pub fn echo(&self) -> u8 {
+        match (self.options.echo, self.context()) {
+            (Some(echo), _) => echo,
+            (None, Ok(context)) => lock(&context.echo_pool).default_echo(),
+            (None, Err(_)) => Self::ECHO,
+        }
+    }
+
+    pub fn cancellation_token(&self) -> Option<&CancellationToken> {
+        self.cancel.as_ref()
+    }
+
+    // 开始抓取该设备的原始报告，可在运行中随时开关
+    pub fn start_capture(
+        &self,
+        format: CaptureFormat,
+        sink: impl std::io::Write + Send + 'static,
+    ) -> DeviceResult<()> {
+        self.context()?.captures.start(self.uuid, format, Box::new(sink))
+    }
+
+    // 结束抓包，返回已记录的报告数
+    pub fn stop_capture(&self) -> DeviceResult<usize> {
+        self.context()?.captures.stop(self.uuid)
+    }
+
+    pub fn is_capturing(&self) -> bool {
+        self.context()
+            .is_ok_and(|context| context.captures.is_capturing(self.uuid))
+    }
+
+    // 广播记录流，可同时存在多个订阅者；设备断开或上下文关闭后流结束
+    pub fn broadcasts(&self) -> BroadcastStream {
+        self.broadcasts_with_capacity(DEFAULT_BROADCAST_CAPACITY)
+    }
+
+    // capacity 为该订阅者最多缓存的记录数，超出时丢弃最旧的记录
+    pub fn broadcasts_with_capacity(&self, capacity: usize) -> BroadcastStream {
+        match self.context() {
+            Ok(context) => context.broadcasts.subscribe(self.uuid, capacity),
+            Err(_) => BroadcastStream::closed(),
+        }
+    }
+
+    // 与 broadcasts 相同，记录已解码为 BroadcastEvent
+    pub fn broadcast_events(&self) -> BroadcastStream<BroadcastEvent> {
+        match self.context() {
+            Ok(context) => context
+                .broadcasts
+                .subscribe_events(self.uuid, DEFAULT_BROADCAST_CAPACITY),
+            Err(_) => BroadcastStream::closed(),
+        }
+    }
+
+    // 该设备仍在读取的广播订阅数
+    pub fn broadcast_subscriber_count(&self) -> usize {
+        self.context()
+            .map_or(0, |context| context.broadcasts.subscriber_count(self.uuid))
+    }
+
+    // 0xE1 行程广播流，每帧为各键行程（微米）
+    pub fn key_travel(&self) -> BroadcastStream<LevelsFrame> {
+        match self.context() {
+            Ok(context) => levels::subscribe_levels(&context.broadcasts, self.uuid, LEVELS_BUFFER_SIZE),
+            Err(_) => BroadcastStream::closed(),
+        }
+    }
+
+    // 由按键、手柄、鼠标广播重建的输入状态，只在变化时产出
+    pub fn input_state(&self) -> InputStream {
+        InputStream::new(self.broadcast_events())
+    }
+
+    // 固件日志与错误消息，已拼接并解码
+    pub fn logs(&self) -> LogStream {
+        match self.context() {
+            Ok(context) => context.logs.subscribe_logs(self.uuid, DEFAULT_BROADCAST_CAPACITY),
+            Err(_) => LogStream::closed(),
+        }
+    }
+
+    pub fn log_history(&self) -> Vec<LogEntry> {
+        self.context()
+            .map(|context| context.logs.history(self.uuid))
+            .unwrap_or_default()
+    }
+
+    pub fn clear_log_history(&self) {
+        if let Ok(context) = self.context() {
+            context.logs.clear(self.uuid);
+        }
+    }
+
+    // 设备能力，连接时预读并缓存在所属上下文，缓存缺失时读取 DeviceInfo
+    pub async fn capabilities(&self) -> DeviceResult<Capabilities> {
+        match self.cached_capabilities() {
+            Some(capabilities) => Ok(capabilities),
+            None => self.read_capabilities().await,
+        }
+    }
+
+    pub fn cached_capabilities(&self) -> Option<Capabilities> {
+        let context = self.context().ok()?;
+        lock(&context.capabilities).get(&self.uuid).cloned().flatten()
+    }
+
+    fn cache_capabilities(&self, info: &DeviceInfo) -> DeviceResult<Capabilities> {
+        let capabilities = Capabilities::from_device_info(info);
+        let context = self.context()?;
+        lock(&context.capabilities).insert(self.uuid, Some(capabilities.clone()));
+        Ok(capabilities)
+    }
+
+    // 直接走 request_once，request_with_header 的能力检查会用到这里。
+    // 失败时记下本次连接探测失败，之后的请求不再为能力检查等待超时
+    async fn read_capabilities(&self) -> DeviceResult<Capabilities> {
+        let res = self.read_device_info_capabilities().await;
+        if let Err(e) = &res
+            && !matches!(e, DeviceError::Cancelled | DeviceError::ContextClosed)
+            && let Ok(context) = self.context()
+        {
+            lock(&context.capabilities).entry(self.uuid).or_insert(None);
+        }
+        res
+    }
+
+    async fn read_device_info_capabilities(&self) -> DeviceResult<Capabilities> {
+        let report_id = self.get_report_id();
+        let (header, info) = self
+            .request_once(report_id, CMD_DEVICE_INFO, 0x00, &DeviceInfo::empty(), &self.options)
+            .await?;
+        let status = header.status(None).ok_or(ReportError::BadReportHeader)?;
+        if !status.is_success() {
+            return Err(DeviceError::DeviceStatus {
+                status,
+                cmd: CMD_DEVICE_INFO,
+                index: 0x00,
+            });
+        }
+        self.cache_capabilities(&info)
+    }
+
+    // api_list 未声明的命令直接返回 Unsupported，不等待超时；
+    // 能力读取失败时不做检查，交给设备状态码，同一连接内只探测一次
+    async fn check_supported(&self, cmd: u8) -> DeviceResult<()> {
+        if cmd == CMD_DEVICE_INFO {
+            return Ok(());
+        }
+        let probed = lock(&self.context()?.capabilities).get(&self.uuid).cloned();
+        let capabilities = match probed {
+            Some(Some(capabilities)) => capabilities,
+            Some(None) => return Ok(()),
+            None => match self.read_capabilities().await {
+                Ok(capabilities) => capabilities,
+                Err(DeviceError::Cancelled) => return Err(DeviceError::Cancelled),
+                Err(e) => {
+                    debug!(uuid:% = uuid::Uuid::from_u128(self.uuid), cmd; "capabilities unavailable: {}", e);
+                    return Ok(());
+                }
+            },
+        };
+        match capabilities.supports(cmd) {
+            true => Ok(()),
+            false => Err(DeviceError::Unsupported { cmd }),
+        }
+    }
+
+    // 读出全部配置用于备份，见 backup 模块
+    pub async fn snapshot(&self) -> DeviceResult<ConfigArchive> {
+        backup::snapshot(self).await
+    }
+
+    // 当前配置与归档（例如标准样机的备份）比较，见 diff 模块
+    pub async fn diff(&self, golden: &ConfigArchive) -> DeviceResult<ConfigDiff> {
+        Ok(diff::diff(golden, &self.snapshot().await?))
+    }
+
+    // 比较归档与设备当前配置，不写入，见 restore 模块
+    pub async fn preview_restore(&self, archive: &ConfigArchive) -> DeviceResult<RestorePreview> {
+        restore::preview(self, archive).await
+    }
+
+    // sections 为空时恢复所有有变化的部分
+    pub async fn restore(
+        &self,
+        archive: &ConfigArchive,
+        sections: &[Section],
+        options: RestoreOptions,
+    ) -> DeviceResult<RestoreReport> {
+        restore::restore(self, archive, sections, options).await
+    }
+
+    fn check_cancelled(&self) -> DeviceResult<()> {
+        match &self.cancel {
+            Some(token) if token.is_cancelled() => Err(DeviceError::Cancelled),
+            _ => Ok(()),
+        }
+    }
+
+    pub async fn passiv_mode(&self) -> DeviceResult<()> {
+        self.context()?.on_connection_changed(self.uuid, false).await
+    }
+
+    pub async fn active_mode(&self) -> DeviceResult<()> {
+        let context = self.context()?;
+        let transport = context.transport();
+        if !transport.has_report_id(self.uuid, 0x21)
+            && !transport.has_report_id(self.uuid, 0x22)
+            && !transport.has_report_id(self.uuid, 0x02)
+        {
+            return Err(DeviceError::DeviceNotFound(self.uuid));
         }
-        return on_connection_changed(self.uuid, true).await;
+        context.on_connection_changed(self.uuid, true).await
     }
 
     pub async fn is_active_mode(&self) -> bool {
-        REPORT_BUFFER_CODEC.lock().await.contains_key(&self.uuid)
+        match self.context() {
+            Ok(context) => context.has_codec(self.uuid),
+            Err(_) => false,
+        }
     }
 
     pub fn has_report_id(&self, report_id: u8) -> bool {
-        println!("sayo has_report_id {:02X?}", report_id);
+        trace!(uuid:% = uuid::Uuid::from_u128(self.uuid), report_id; "has_report_id");
         // For the common IDs 0x21 and 0x22, use the same cache strategy as get_report_id.
+        let Ok(context) = self.context() else {
+            return false;
+        };
+        let transport = context.transport();
         if report_id == 0x21 || report_id == 0x22 {
-            let mut map = REPORT_ID_CACHE_MAP.lock().unwrap();
+            let mut map = lock(&context.report_id_cache);
             let cache = map.entry(self.uuid).or_insert_with(ReportIdCache::new);
             if cache.should_refresh() {
-                let hid = HidDevice::from(self.uuid);
-                let now_22 = hid.has_report_id(0x22);
-                let now_21 = hid.has_report_id(0x21);
+                let now_22 = transport.has_report_id(self.uuid, 0x22);
+                let now_21 = transport.has_report_id(self.uuid, 0x21);
                 cache.has_22 = Some(now_22);
                 cache.has_21 = Some(now_21);
                 cache.mark_refreshed();
@@ -526,19 +536,20 @@ impl SayoDeviceApi {
             return match report_id {
                 0x22 => cache
                     .has_22
-                    .unwrap_or_else(|| HidDevice::from(self.uuid).has_report_id(0x22)),
+                    .unwrap_or_else(|| transport.has_report_id(self.uuid, 0x22)),
                 0x21 => cache
                     .has_21
-                    .unwrap_or_else(|| HidDevice::from(self.uuid).has_report_id(0x21)),
+                    .unwrap_or_else(|| transport.has_report_id(self.uuid, 0x21)),
                 _ => false,
             };
         }
         // For other IDs, fall back to direct query.
-        HidDevice::from(self.uuid).has_report_id(report_id)
+        transport.has_report_id(self.uuid, report_id)
     }
 
-    async fn send_hid_report(&self, data: Vec<Vec<u8>>) -> Result<(), &'static str> {
-        let hid = HidDevice::from(self.uuid);
+    async fn send_hid_report(&self, data: Vec<Vec<u8>>) -> DeviceResult<()> {
+        let context = self.context()?;
+        let transport = context.transport();
         for report in data {
             // if report[6] != 0x13 && report[6] != 0x25 && report[6] != 0x15 && report[6] != 0x27 {
             //     println!(
@@ -548,19 +559,14 @@ impl SayoDeviceApi {
             //     );
             // }
             // println!("Sending report: {:02X?}", report);
-            let timeout = future_delay(SEND_TIMEOUT_MS);
-            let send = hid.send_report(report);
+            context.captures.record(self.uuid, Direction::Out, &report);
+            let timeout = future_delay(self.options.send_timeout_ms);
+            let send = transport.send_report(self.uuid, report);
             let send_timeout = futures::future::select(Box::pin(send), Box::pin(timeout));
             match send_timeout.await {
-                Either::Left(res) => match res.0 {
-                    Ok(_) => (),
-                    Err(e) => {
-                        println!("Send report failed: {:?}", e);
-                        return Err("Send report failed");
-                    }
-                },
+                Either::Left(res) => res.0?,
                 Either::Right(_) => {
-                    return Err("Send report Timeout");
+                    return Err(DeviceError::SendReportFailed("Send report Timeout".to_string()));
                 }
             };
         }
@@ -569,47 +575,67 @@ impl SayoDeviceApi {
     async fn request_with_header<T: CodecableHidPackage>(
         &self,
         report_id: u8,
-        echo: u8,
         cmd: u8,
         index: u8,
         content: &T,
-    ) -> Option<(HidReportHeader, T)> {
-        let wrap_codec = match require_report_codec(self.uuid) {
-            Some(codec) => codec,
-            None => {
-                println!("No codec found for device (lock busy?)");
-                return None;
+    ) -> DeviceResult<(HidReportHeader, T)> {
+        self.check_supported(cmd).await?;
+        let options = self.options;
+        let mut attempt = 0;
+        loop {
+            self.check_cancelled()?;
+            let res = self
+                .request_once(report_id, cmd, index, content, &options)
+                .await;
+            let retryable = match &res {
+                Ok((header, _)) => header.status(None).is_some_and(|s| s.is_retryable()),
+                Err(e) => e.is_retryable(),
+            };
+            if !retryable || attempt >= options.retries {
+                return res;
+            }
+            attempt += 1;
+            if options.retry_backoff_ms > 0 {
+                future_delay(options.retry_backoff_ms * attempt as u32).await;
             }
+        }
+    }
+
+    async fn request_once<T: CodecableHidPackage>(
+        &self,
+        report_id: u8,
+        cmd: u8,
+        index: u8,
+        content: &T,
+        options: &RequestOptions,
+    ) -> DeviceResult<(HidReportHeader, T)> {
+        let context = self.context()?;
+        let echo = match options.echo {
+            Some(echo) => echo,
+            None => lock(&context.echo_pool).default_echo(),
         };
+        let wrap_codec = context.require_codec(self.uuid);
+        drop(context);
         // 获取响应句柄后立刻释放锁，避免阻塞后续上报拼包
         let response = {
-            let mut codec_guard = wrap_codec.lock().await;
-            codec_guard.request_response::<T>(report_id, cmd, index)
+            let codec_guard = wrap_codec.lock().await;
+            codec_guard.request_response::<T>(report_id, echo, cmd, index, options)
         };
-        let reports = match report_codec::encode_report(report_id, echo, cmd, index, content) {
-            Ok(reports) => reports,
-            Err(e) => {
-                println!("Request with header: Encode report failed: {}", e);
-                return None;
-            }
-        };
-        match self.send_hid_report(reports).await {
-            Ok(_) => {
-                //println!("Request with header: Send report success");
-            }
-            Err(_) => {
-                println!("Request with header: Send report failed");
-                return None;
-            }
+        let reports = report_codec::encode_report(report_id, echo, cmd, index, content)?;
+        self.send_hid_report(reports).await?;
+        let Some(token) = &self.cancel else {
+            return Ok(response.await?);
         };
-        match response.await {
-            Ok((header, content)) => {
-                //println!("Request with header: Response from device {:02X?}", cmd);
-                return Some((header, content));
-            }
-            Err(_) => {
-                println!("Request with header: No response from device");
-                return None;
+        match futures::future::select(Box::pin(response), token.cancelled()).await {
+            Either::Left((res, _)) => Ok(res?),
+            Either::Right((_, response)) => {
+                // 只释放本次请求的等待者，共用 echo 的其他请求照常等待应答
+                drop(response);
+                wrap_codec
+                    .lock()
+                    .await
+                    .release_waiters(report_id, echo, cmd, index);
+                Err(DeviceError::Cancelled)
             }
         }
     }
@@ -617,85 +643,48 @@ impl SayoDeviceApi {
     async fn request<T: CodecableHidPackage>(
         &self,
         report_id: u8,
-        echo: u8,
         cmd: u8,
         index: u8,
         content: &T,
-    ) -> Option<T> {
-        let response = self
-            .request_with_header(report_id, echo, cmd, index, content)
-            .await;
-        return match response {
-            Some((header, content)) => {
-                let status = header.status(None).expect("Bad Report Header");
-                if status != STATUS_OK && status != STATUS_PARTIAL && status != STATUS_COMPLETE {
-                    return None;
-                }
-                Some(content)
-            }
-            None => None,
-        };
+    ) -> DeviceResult<T> {
+        let (header, content) = self
+            .request_with_header(report_id, cmd, index, content)
+            .await?;
+        let status = header.status(None).ok_or(ReportError::BadReportHeader)?;
+        if !status.is_success() {
+            return Err(DeviceError::DeviceStatus { status, cmd, index });
+        }
+        Ok(content)
     }
 
-    async fn request_all_index<T: CodecableHidPackage>(&self, report_id: u8, cmd: u8) -> Vec<T> {
+    async fn request_all_index<T: CodecableHidPackage>(
+        &self,
+        report_id: u8,
+        cmd: u8,
+    ) -> DeviceResult<Vec<T>> {
         let mut res: Vec<T> = Vec::new();
         let mut index = 0;
-        let mut consecutive_failures = 0;
 
+        // 超时和可重试状态码的重试由 request_with_header 按 options.retries 处理
         loop {
-            if consecutive_failures >= MAX_RETRY_COUNT {
-                println!(
-                    "Request all index: Too many consecutive failures for cmd {:02X?}",
-                    cmd
-                );
+            self.check_cancelled()?;
+            let (header, content) = self
+                .request_with_header(report_id, cmd, index, &T::empty())
+                .await?;
+
+            let status = header.status(None).ok_or(ReportError::BadReportHeader)?;
+            if status.is_success() {
+                res.push(content);
+                index += 1;
+            } else if status == ResponseStatus::IndexMissing {
+                // 越过最后一个 index，枚举结束
                 break;
-            }
-
-            let response = self
-                .request_with_header(report_id, SayoDeviceApi::ECHO, cmd, index, &T::empty())
-                .await;
-
-            let (header, content) = match response {
-                Some((header, content)) => {
-                    // println!("Request all index: Response from device {:02X?} {:02X?}", cmd, index);
-                    consecutive_failures = 0; // 重置失败计数
-                    (header, content)
-                }
-                None => {
-                    println!(
-                        "Request all index: No response from device {:02X?} {:02X?}",
-                        cmd, index
-                    );
-                    consecutive_failures += 1;
-                    continue;
-                }
-            };
-
-            match header.status(None) {
-                Some(status) => {
-                    if status == STATUS_OK || status == STATUS_PARTIAL || status == STATUS_COMPLETE
-                    {
-                        res.push(content);
-                        index += 1;
-                    } else {
-                        println!(
-                            "Request all index: Response from device with bad status {:02X?} {:02X?} {:02X?}",
-                            cmd, index, status
-                        );
-                        break;
-                    }
-                }
-                None => {
-                    println!(
-                        "Request all index: Response from device with bad header {:02X?} {:02X?} ",
-                        cmd, index
-                    );
-                    break;
-                }
+            } else {
+                return Err(DeviceError::DeviceStatus { status, cmd, index });
             }
 
             if index == 0xff {
-                println!("Request all index: Reached end of index {:02X?} ", cmd);
+                debug!(uuid:% = uuid::Uuid::from_u128(self.uuid), cmd; "request all index: reached index 0xFF");
                 break;
             }
 
@@ -704,8 +693,8 @@ impl SayoDeviceApi {
             //     future_delay(10).await;
             // }
         }
-        println!("Request all index: Done with {:} elements", res.len());
-        return res;
+        debug!(uuid:% = uuid::Uuid::from_u128(self.uuid), cmd, count = res.len(); "request all index done");
+        Ok(res)
     }
 }
 
@@ -714,42 +703,39 @@ impl SayoDeviceApi {
         return self.uuid;
     }
 
-    pub fn vid(&self) -> u16 {
-        let hid = HidDevice::from(self.uuid);
-        match hid.vid() {
-            Ok(vid) => vid,
-            Err(_) => 0,
-        }
+    pub fn vid(&self) -> DeviceResult<u16> {
+        self.transport()?
+            .vid(self.uuid)
+            .ok_or(DeviceError::DeviceNotFound(self.uuid))
     }
 
-    pub fn pid(&self) -> u16 {
-        let hid = HidDevice::from(self.uuid);
-        match hid.pid() {
-            Ok(pid) => pid,
-            Err(_) => 0,
-        }
+    pub fn pid(&self) -> DeviceResult<u16> {
+        self.transport()?
+            .pid(self.uuid)
+            .ok_or(DeviceError::DeviceNotFound(self.uuid))
     }
 
     pub fn get_product_name(&self) -> Option<String> {
         // println!("sayo get_product_name");
-        return match HidDevice::from(self.uuid).get_product_name() {
-            Ok(name) => {
-                // println!("sayo get_product_name: {:?}", name);
-                name.clone()
-            }
-            Err(_) => None,
-        };
+        self.transport().ok()?.product_name(self.uuid)
     }
 
     pub fn get_report_id(&self) -> u8 {
+        if let Some(report_id) = self.options.report_id {
+            return report_id;
+        }
         // println!("sayo get_report_id");
         // Use cached result with warmup/dynamic strategy.
-        let mut map = REPORT_ID_CACHE_MAP.lock().unwrap();
+        // 上下文已关闭时随后的请求会返回 ContextClosed，这里给出任意值即可
+        let Ok(context) = self.context() else {
+            return 0x21;
+        };
+        let mut map = lock(&context.report_id_cache);
         let cache = map.entry(self.uuid).or_insert_with(ReportIdCache::new);
         if cache.should_refresh() {
-            let hid = HidDevice::from(self.uuid);
-            let now_22 = hid.has_report_id(0x22);
-            let now_21 = hid.has_report_id(0x21);
+            let transport = context.transport();
+            let now_22 = transport.has_report_id(self.uuid, 0x22);
+            let now_21 = transport.has_report_id(self.uuid, 0x21);
             cache.has_22 = Some(now_22);
             cache.has_21 = Some(now_21);
             cache.mark_refreshed();
@@ -766,7 +752,7 @@ impl SayoDeviceApi {
         return self.get_report_id() == 0x22;
     }
 
-    pub async fn reboot(&self) -> bool {
+    pub async fn reboot(&self) -> DeviceResult<()> {
         let report_id = self.get_report_id();
         const INDEX: u8 = 0x00;
         let empty = ByteArray::new(RwBytes::new(vec![
@@ -775,14 +761,12 @@ impl SayoDeviceApi {
             SUBCMD_REBOOT,
             !SUBCMD_REBOOT,
         ]));
-        let response = self.request(report_id, SayoDeviceApi::ECHO, CMD_REBOOT, INDEX, &empty);
-        match response.await {
-            Some(_) => false,
-            None => true,
-        }
+        self.request(report_id, CMD_REBOOT, INDEX, &empty)
+            .await
+            .map(|_| ())
     }
 
-    pub async fn recovery(&self) -> bool {
+    pub async fn recovery(&self) -> DeviceResult<()> {
         let report_id = self.get_report_id();
         const INDEX: u8 = 0x00;
         let empty = ByteArray::new(RwBytes::new(vec![
@@ -791,14 +775,12 @@ impl SayoDeviceApi {
             SUBCMD_RECOVERY,
             !SUBCMD_RECOVERY,
         ]));
-        let response = self.request(report_id, SayoDeviceApi::ECHO, CMD_REBOOT, INDEX, &empty);
-        match response.await {
-            Some(_) => false,
-            None => true,
-        }
+        self.request(report_id, CMD_REBOOT, INDEX, &empty)
+            .await
+            .map(|_| ())
     }
 
-    pub async fn into_bootloader(&self) -> bool {
+    pub async fn into_bootloader(&self) -> DeviceResult<()> {
         let report_id = self.get_report_id();
         const INDEX: u8 = 0x00;
         let empty = ByteArray::new(RwBytes::new(vec![
@@ -807,14 +789,12 @@ impl SayoDeviceApi {
             SUBCMD_BOOTLOADER,
             !SUBCMD_BOOTLOADER,
         ]));
-        let response = self.request(report_id, SayoDeviceApi::ECHO, CMD_REBOOT, INDEX, &empty);
-        match response.await {
-            Some(_) => false,
-            None => true,
-        }
+        self.request(report_id, CMD_REBOOT, INDEX, &empty)
+            .await
+            .map(|_| ())
     }
 
-    pub async fn set_device_name(&self, name: String, len: usize) -> Option<String> {
+    pub async fn set_device_name(&self, name: String, len: usize) -> DeviceResult<String> {
         let str = StringContent::new(RwBytes::from_str(Encoding::UTF16LE, &name));
         str.encoding_byte.set(Some(u8::from(Encoding::UTF16LE)));
         // str.str(Some(name));
@@ -824,51 +804,58 @@ impl SayoDeviceApi {
         let mut content = str.bytes.into_vec();
         content.resize(len, 0);
         let bytes_content = ByteArray::new(RwBytes::new(content));
-        let response = self.request(report_id, SayoDeviceApi::ECHO, CMD, INDEX, &bytes_content);
-        match response.await {
-            Some(content) => StringContent {
-                encoding_byte: Cell::new(Some(0x03)),
-                bytes: content.bytes,
-            }
-            .str(None),
-            None => None,
+        let content = self
+            .request(report_id, CMD, INDEX, &bytes_content)
+            .await?;
+        StringContent {
+            encoding_byte: Cell::new(Some(0x03)),
+            bytes: content.bytes,
         }
+        .str(None)
+        .ok_or(DeviceError::EncodingError("device name".to_string()))
+    }
+
+    pub async fn get_device_name(&self) -> DeviceResult<(String, usize)> {
+        let content = self.get_device_name_content().await?;
+        Ok((
+            content.str(None).unwrap_or("".to_string()),
+            content.bytes_len(),
+        ))
     }
 
-    pub async fn get_device_name(&self) -> Option<(String, usize)> {
+    // 设备名原始内容，encoding_byte 为设备应答的编码
+    pub async fn get_device_name_content(&self) -> DeviceResult<StringContent> {
         let str = StringContent::empty();
         str.encoding_byte.set(Some(u8::from(Encoding::UTF16LE)));
         let report_id = self.get_report_id();
         const CMD: u8 = 0x01;
         const INDEX: u8 = 0x00;
-        let response = self.request(report_id, SayoDeviceApi::ECHO, CMD, INDEX, &str);
-        let content = match response.await {
-            Some(content) => content,
-            None => return None,
-        };
-        Some((
-            content.str(None).unwrap_or("".to_string()),
-            content.bytes_len(),
-        ))
+        self.request(report_id, CMD, INDEX, &str).await
+    }
+
+    // 按 value 的编码写入设备名，bytes 即整段名字区域
+    pub async fn set_device_name_content(&self, value: StringContent) -> DeviceResult<StringContent> {
+        let report_id = self.get_report_id();
+        const CMD: u8 = 0x01;
+        const INDEX: u8 = 0x00;
+        self.request(report_id, CMD, INDEX, &value).await
     }
 
-    pub async fn get_device_info(&self) -> Option<DeviceInfo> {
+    pub async fn get_device_info(&self) -> DeviceResult<DeviceInfo> {
         let report_id = self.get_report_id();
         const INDEX: u8 = 0x00;
         let empty = DeviceInfo::empty();
-        let response = self.request(report_id, SayoDeviceApi::ECHO, CMD_DEVICE_INFO, INDEX, &empty);
-        let device_info = match response.await {
-            Some(info) => info,
-            None => return None,
-        };
-        Some(device_info)
+        let info = self.request(report_id, CMD_DEVICE_INFO, INDEX, &empty)
+            .await?;
+        // 顺带刷新能力缓存
+        let _ = self.cache_capabilities(&info);
+        Ok(info)
     }
-    pub async fn set_device_info(&self, device_info: &DeviceInfo) -> Option<DeviceInfo> {
+    pub async fn set_device_info(&self, device_info: &DeviceInfo) -> DeviceResult<DeviceInfo> {
         let report_id = self.get_report_id();
         const INDEX: u8 = 0x00;
         let response = self.request(
             report_id,
-            SayoDeviceApi::ECHO,
             CMD_DEVICE_INFO,
             INDEX,
             device_info,
@@ -876,19 +863,18 @@ impl SayoDeviceApi {
         response.await
     }
 
-    pub async fn get_system_info(&self) -> Option<SystemInfo> {
+    pub async fn get_system_info(&self) -> DeviceResult<SystemInfo> {
         let report_id = self.get_report_id();
         const INDEX: u8 = 0x00;
         let empty = SystemInfo::empty();
-        let response = self.request(report_id, SayoDeviceApi::ECHO, CMD_SYSTEM_INFO, INDEX, &empty);
+        let response = self.request(report_id, CMD_SYSTEM_INFO, INDEX, &empty);
         response.await
     }
-    pub async fn set_system_info(&self, system_info: &SystemInfo) -> Option<SystemInfo> {
+    pub async fn set_system_info(&self, system_info: &SystemInfo) -> DeviceResult<SystemInfo> {
         let report_id = self.get_report_id();
         const INDEX: u8 = 0x00;
         let response = self.request(
             report_id,
-            SayoDeviceApi::ECHO,
             CMD_SYSTEM_INFO,
             INDEX,
             system_info,
@@ -896,145 +882,141 @@ impl SayoDeviceApi {
         response.await
     }
 
-    pub async fn get_optional_bytes(&self) -> Option<DeviceConfig> {
+    pub async fn get_optional_bytes(&self) -> DeviceResult<DeviceConfig> {
         let report_id = self.get_report_id();
         const CMD: u8 = 0x03;
         const INDEX: u8 = 0x00;
         let empty = DeviceConfig::empty();
-        let response = self.request(report_id, SayoDeviceApi::ECHO, CMD, INDEX, &empty);
+        let response = self.request(report_id, CMD, INDEX, &empty);
         response.await
     }
     pub async fn set_optional_bytes(
         &self,
         optional_bytes: &DeviceConfig,
-    ) -> Option<DeviceConfig> {
+    ) -> DeviceResult<DeviceConfig> {
         let report_id = self.get_report_id();
         const CMD: u8 = 0x03;
         const INDEX: u8 = 0x00;
-        let response = self.request(report_id, SayoDeviceApi::ECHO, CMD, INDEX, optional_bytes);
+        let response = self.request(report_id, CMD, INDEX, optional_bytes);
         response.await
     }
 
-    pub async fn get_rf_config(&self) -> Option<RFConfig> {
+    pub async fn get_rf_config(&self) -> DeviceResult<RFConfig> {
         let report_id = self.get_report_id();
         const CMD: u8 = 0x04;
         const INDEX: u8 = 0x00;
         let empty = RFConfig::empty();
-        let response = self.request(report_id, SayoDeviceApi::ECHO, CMD, INDEX, &empty);
+        let response = self.request(report_id, CMD, INDEX, &empty);
         response.await
     }
 
-    pub async fn set_rf_config(&self, rf_config: &RFConfig) -> Option<RFConfig> {
+    pub async fn set_rf_config(&self, rf_config: &RFConfig) -> DeviceResult<RFConfig> {
         let report_id = self.get_report_id();
         const CMD: u8 = 0x04;
         const INDEX: u8 = 0x00;
-        let response = self.request(report_id, SayoDeviceApi::ECHO, CMD, INDEX, rf_config);
+        let response = self.request(report_id, CMD, INDEX, rf_config);
         response.await
     }
 
-    pub async fn lock_device(&self, password: &StringContent) -> Option<bool> {
+    pub async fn lock_device(&self, password: &StringContent) -> DeviceResult<()> {
         if password.encoding_byte.get() != Some(u8::from(Encoding::ASCII)) {
-            println!("Password must be ASCII");
-            return None;
+            return Err(DeviceError::InvalidData("Password must be ASCII".to_string()));
         }
         if password.bytes_len() > 32 {
-            println!("Password length must be between 4 and 32");
-            return None;
+            return Err(DeviceError::InvalidData(
+                "Password length must be between 4 and 32".to_string(),
+            ));
         }
         let report_id = self.get_report_id();
         const CMD: u8 = 0x05;
         const INDEX: u8 = 0x00;
-        let response = self.request(report_id, SayoDeviceApi::ECHO, CMD, INDEX, password);
-        match response.await {
-            Some(_) => Some(true),
-            None => Some(false),
-        }
+        self.request(report_id, CMD, INDEX, password)
+            .await
+            .map(|_| ())
     }
 
-    pub async fn unlock_device(&self, password: &StringContent) -> Option<bool> {
+    pub async fn unlock_device(&self, password: &StringContent) -> DeviceResult<()> {
         if password.encoding_byte.get() != Some(u8::from(Encoding::ASCII)) {
-            println!("Password must be ASCII");
-            return None;
+            return Err(DeviceError::InvalidData("Password must be ASCII".to_string()));
         }
         if password.bytes_len() > 32 || password.bytes_len() < 4 {
-            println!("Password length must be between 4 and 32");
-            return None;
+            return Err(DeviceError::InvalidData(
+                "Password length must be between 4 and 32".to_string(),
+            ));
         }
         let report_id = self.get_report_id();
         const CMD: u8 = 0x06;
         const INDEX: u8 = 0x00;
-        let response = self.request(report_id, SayoDeviceApi::ECHO, CMD, INDEX, password);
-        match response.await {
-            Some(_) => Some(true),
-            None => Some(false),
-        }
+        self.request(report_id, CMD, INDEX, password)
+            .await
+            .map(|_| ())
     }
 
-    pub async fn get_monkey_gpios(&self) -> Option<MonkeyGpios> {
+    pub async fn get_monkey_gpios(&self) -> DeviceResult<MonkeyGpios> {
         let report_id = self.get_report_id();
         const CMD: u8 = MonkeyGpios::CMD.unwrap();
         const INDEX: u8 = 0x00;
         let empty = MonkeyGpios::empty();
-        let response = self.request(report_id, SayoDeviceApi::ECHO, CMD, INDEX, &empty);
+        let response = self.request(report_id, CMD, INDEX, &empty);
         response.await
     }
 
-    pub async fn set_monkey_gpios(&self, monkey_gpios: &MonkeyGpios) -> Option<MonkeyGpios> {
+    pub async fn set_monkey_gpios(&self, monkey_gpios: &MonkeyGpios) -> DeviceResult<MonkeyGpios> {
         let report_id = self.get_report_id();
         const CMD: u8 = MonkeyGpios::CMD.unwrap();
         const INDEX: u8 = 0x00;
-        let response = self.request(report_id, SayoDeviceApi::ECHO, CMD, INDEX, monkey_gpios);
+        let response = self.request(report_id, CMD, INDEX, monkey_gpios);
         response.await
     }
 
-    pub async fn get_key_infos(&self) -> Vec<KeyInfo> {
+    pub async fn get_key_infos(&self) -> DeviceResult<Vec<KeyInfo>> {
         let report_id = self.get_report_id();
         const CMD: u8 = 0x10;
         self.request_all_index::<KeyInfo>(report_id, CMD).await
     }
 
-    pub async fn set_key_info(&self, index: u8, key_info: &KeyInfo) -> Option<KeyInfo> {
+    pub async fn set_key_info(&self, index: u8, key_info: &KeyInfo) -> DeviceResult<KeyInfo> {
         let report_id = self.get_report_id();
         const CMD: u8 = 0x10;
-        let response = self.request(report_id, SayoDeviceApi::ECHO, CMD, index, key_info);
+        let response = self.request(report_id, CMD, index, key_info);
         response.await
     }
 
-    pub async fn get_led_infos(&self) -> Vec<LEDInfo> {
+    pub async fn get_led_infos(&self) -> DeviceResult<Vec<LEDInfo>> {
         let report_id = self.get_report_id();
         const CMD: u8 = 0x11;
         self.request_all_index::<LEDInfo>(report_id, CMD).await
     }
 
-    pub async fn set_led_info(&self, index: u8, led_info: &LEDInfo) -> Option<LEDInfo> {
+    pub async fn set_led_info(&self, index: u8, led_info: &LEDInfo) -> DeviceResult<LEDInfo> {
         let report_id = self.get_report_id();
         const CMD: u8 = 0x11;
-        let response = self.request(report_id, SayoDeviceApi::ECHO, CMD, index, led_info);
+        let response = self.request(report_id, CMD, index, led_info);
         response.await
     }
 
-    pub async fn get_color_tables(&self) -> Vec<ColorTable> {
+    pub async fn get_color_tables(&self) -> DeviceResult<Vec<ColorTable>> {
         let report_id = self.get_report_id();
         const CMD: u8 = 0x12;
         self.request_all_index::<ColorTable>(report_id, CMD).await
     }
 
-    pub async fn set_color_table(&self, index: u8, color_table: &ColorTable) -> Option<ColorTable> {
+    pub async fn set_color_table(&self, index: u8, color_table: &ColorTable) -> DeviceResult<ColorTable> {
         let report_id = self.get_report_id();
         const CMD: u8 = 0x12;
-        let response = self.request(report_id, SayoDeviceApi::ECHO, CMD, index, color_table);
+        let response = self.request(report_id, CMD, index, color_table);
         response.await
     }
 
-    pub async fn get_touch_sensitivity(&self, index: u8) -> Option<TouchSensitivity> {
+    pub async fn get_touch_sensitivity(&self, index: u8) -> DeviceResult<TouchSensitivity> {
         let report_id = self.get_report_id();
         const CMD: u8 = 0x13;
         let empty = TouchSensitivity::empty();
-        let response = self.request(report_id, SayoDeviceApi::ECHO, CMD, index, &empty);
+        let response = self.request(report_id, CMD, index, &empty);
         response.await
     }
 
-    pub async fn get_touch_sensitivitys(&self) -> Vec<TouchSensitivity> {
+    pub async fn get_touch_sensitivitys(&self) -> DeviceResult<Vec<TouchSensitivity>> {
         let report_id = self.get_report_id();
         const CMD: u8 = 0x13;
         self.request_all_index::<TouchSensitivity>(report_id, CMD)
@@ -1045,60 +1027,57 @@ impl SayoDeviceApi {
         &self,
         index: u8,
         touch_sensitivity: &TouchSensitivity,
-    ) -> Option<TouchSensitivity> {
+    ) -> DeviceResult<TouchSensitivity> {
         let report_id = self.get_report_id();
         const CMD: u8 = 0x13;
-        let response = self.request(report_id, SayoDeviceApi::ECHO, CMD, index, touch_sensitivity);
+        let response = self.request(report_id, CMD, index, touch_sensitivity);
         response.await
     }
 
-    pub async fn get_passwords(&self) -> Vec<StringContent> {
+    pub async fn get_passwords(&self) -> DeviceResult<Vec<StringContent>> {
         let report_id = self.get_report_id();
         const CMD: u8 = 0x16;
         self.request_all_index::<StringContent>(report_id, CMD)
             .await
     }
 
-    pub async fn set_password(&self, index: u8, value: StringContent) -> Option<StringContent> {
+    pub async fn set_password(&self, index: u8, value: StringContent) -> DeviceResult<StringContent> {
         let report_id = self.get_report_id();
         const CMD: u8 = 0x16;
-        let response = self.request(report_id, SayoDeviceApi::ECHO, CMD, index, &value);
+        let response = self.request(report_id, CMD, index, &value);
         response.await
     }
 
-    pub async fn get_strings(&self) -> Vec<StringContent> {
+    pub async fn get_strings(&self) -> DeviceResult<Vec<StringContent>> {
         let report_id = self.get_report_id();
         const CMD: u8 = 0x17;
         self.request_all_index::<StringContent>(report_id, CMD)
             .await
     }
 
-    pub async fn set_string(&self, index: u8, value: StringContent) -> Option<StringContent> {
+    pub async fn set_string(&self, index: u8, value: StringContent) -> DeviceResult<StringContent> {
         let report_id = self.get_report_id();
         const CMD: u8 = 0x17;
-        let response = self.request(report_id, SayoDeviceApi::ECHO, CMD, index, &value);
+        let response = self.request(report_id, CMD, index, &value);
         response.await
     }
 
-    pub async fn get_script_names(&self) -> Vec<StringContent> {
+    pub async fn get_script_names(&self) -> DeviceResult<Vec<StringContent>> {
         let report_id = self.get_report_id();
         const CMD: u8 = 0x19;
         self.request_all_index::<StringContent>(report_id, CMD)
             .await
     }
 
-    pub async fn set_script_name(&self, index: u8, value: StringContent) -> Option<StringContent> {
+    pub async fn set_script_name(&self, index: u8, value: StringContent) -> DeviceResult<StringContent> {
         let report_id = self.get_report_id();
         const CMD: u8 = 0x19;
-        let response = self.request(report_id, SayoDeviceApi::ECHO, CMD, index, &value);
+        let response = self.request(report_id, CMD, index, &value);
         response.await
     }
 
-    pub async fn pull_screen_buffer(&self, len: &u32) -> Vec<u8> {
-        let Some(wrap_codec) = require_report_codec(self.uuid) else {
-            println!("No codec found for device (lock busy?)");
-            return Vec::new();
-        };
+    pub async fn pull_screen_buffer(&self, len: &u32) -> DeviceResult<Vec<u8>> {
+        let wrap_codec = self.context()?.require_codec(self.uuid);
         // 仅在读取缓冲区时持锁，随后立即释放以便 on_report_arrived 拼包
         let mut res: Vec<u8> = vec![0; len.clone() as usize];
         {
@@ -1111,23 +1090,12 @@ impl SayoDeviceApi {
         let index: u8 = 0x00;
         let empty = ScreenBuffer::empty();
         let reports =
-            match report_codec::encode_report(report_id, SayoDeviceApi::ECHO, cmd, index, &empty) {
-                Ok(reports) => reports,
-                Err(e) => {
-                    println!("Pull screen buffer: Encode report failed: {}", e);
-                    return res;
-                }
-            };
-        match self.send_hid_report(reports).await {
-            Ok(_) => (),
-            Err(_) => {
-                println!("Pull screen buffer: Send report failed");
-            }
-        }
-        return res;
+            report_codec::encode_report(report_id, self.echo(), cmd, index, &empty)?;
+        self.send_hid_report(reports).await?;
+        Ok(res)
     }
 
-    pub async fn get_lcd_draw_datas(&self, layer: ScreenLayer) -> Vec<LCDDrawData> {
+    pub async fn get_lcd_draw_datas(&self, layer: ScreenLayer) -> DeviceResult<Vec<LCDDrawData>> {
         let report_id = self.get_report_id();
         let cmd = layer as u8;
         return self.request_all_index(report_id, cmd).await;
@@ -1138,47 +1106,47 @@ impl SayoDeviceApi {
         layer: u8,
         index: u8,
         data: &LCDDrawData,
-    ) -> Option<LCDDrawData> {
+    ) -> DeviceResult<LCDDrawData> {
         let report_id = self.get_report_id();
         let cmd = layer;
-        let response = self.request(report_id, SayoDeviceApi::ECHO, cmd, index, data);
+        let response = self.request(report_id, cmd, index, data);
         response.await
     }
 
-    pub async fn get_hall_50um(&self, key_to_record: Option<u8>) -> Option<ByteArray> {
+    pub async fn get_hall_50um(&self, key_to_record: Option<u8>) -> DeviceResult<ByteArray> {
         let report_id = self.get_report_id();
         const CMD: u8 = 0x15;
         let bytes = match key_to_record {
             Some(key) => ByteArray::new(RwBytes::new(vec![key])),
             None => ByteArray::empty(),
         };
-        let response = self.request(report_id, SayoDeviceApi::ECHO, CMD, 0, &bytes);
+        let response = self.request(report_id, CMD, 0, &bytes);
         response.await
     }
 
-    pub async fn get_hall_info_um(&self, key_to_record: Option<u8>) -> Option<ByteArray> {
+    pub async fn get_hall_info_um(&self, key_to_record: Option<u8>) -> DeviceResult<ByteArray> {
         let report_id = self.get_report_id();
         const CMD: u8 = 0x15;
         let bytes = match key_to_record {
             Some(key) => ByteArray::new(RwBytes::new(vec![key])),
             None => ByteArray::empty(),
         };
-        let response = self.request(report_id, SayoDeviceApi::ECHO, CMD, 1, &bytes);
+        let response = self.request(report_id, CMD, 1, &bytes);
         response.await
     }
 
-    pub async fn get_analog_key_infos(&self) -> Vec<AnalogKeyInfo> {
+    pub async fn get_analog_key_infos(&self) -> DeviceResult<Vec<AnalogKeyInfo>> {
         let report_id = self.get_report_id();
         let cmd: u8 = AnalogKeyInfo::CMD.expect("No CMD found for AnalogKeyInfo");
         let response = self.request_all_index::<AnalogKeyInfo>(report_id, cmd);
         response.await
     }
 
-    pub async fn get_analog_key_info(&self, index: u8) -> Option<AnalogKeyInfo> {
+    pub async fn get_analog_key_info(&self, index: u8) -> DeviceResult<AnalogKeyInfo> {
         let report_id = self.get_report_id();
         let cmd: u8 = AnalogKeyInfo::CMD.expect("No CMD found for AnalogKeyInfo");
         let empty = AnalogKeyInfo::empty();
-        let response = self.request(report_id, SayoDeviceApi::ECHO, cmd, index, &empty);
+        let response = self.request(report_id, cmd, index, &empty);
         response.await
     }
 
@@ -1186,25 +1154,23 @@ impl SayoDeviceApi {
         &self,
         index: u8,
         key_info: &AnalogKeyInfo,
-    ) -> Option<AnalogKeyInfo> {
+    ) -> DeviceResult<AnalogKeyInfo> {
         let report_id = self.get_report_id();
         let cmd: u8 = AnalogKeyInfo::CMD.expect("No CMD found for AnalogKeyInfo");
-        let response = self.request(report_id, SayoDeviceApi::ECHO, cmd, index, key_info);
+        let response = self.request(report_id, cmd, index, key_info);
         response.await
     }
 
-    pub async fn save_all(&self) -> bool {
+    pub async fn save_all(&self) -> DeviceResult<()> {
         let report_id = self.get_report_id();
         const INDEX: u8 = 0x00;
         let empty = ByteArray::new(RwBytes::new(vec![0x96, 0x72]));
-        let response = self.request(report_id, SayoDeviceApi::ECHO, CMD_SAVE_ALL, INDEX, &empty);
-        match response.await {
-            Some(_) => true,
-            None => false,
-        }
+        self.request(report_id, CMD_SAVE_ALL, INDEX, &empty)
+            .await
+            .map(|_| ())
     }
 
-    pub async fn get_display_assets_address_len(&self, index: u8) -> u32 {
+    pub async fn get_display_assets_address_len(&self, index: u8) -> DeviceResult<u32> {
         self.get_addressable_data_len::<DisplayAssetsPacket>(index)
             .await
     }
@@ -1213,21 +1179,17 @@ impl SayoDeviceApi {
         &self,
         index: u8,
         addr: u32,
-    ) -> Option<DisplayAssetsPacket> {
+    ) -> DeviceResult<DisplayAssetsPacket> {
         self.get_addressable_data_with_addr::<DisplayAssetsPacket>(index, addr)
             .await
     }
 
     //max len, display assets
-    pub async fn get_display_assets(&self, index: u8) -> Option<(u32, DisplayAssets)> {
-        let (size, bytes) = match self
+    pub async fn get_display_assets(&self, index: u8) -> DeviceResult<(u32, DisplayAssets)> {
+        let (size, bytes) = self
             .get_addressable_data::<DisplayAssetsPacket>(index)
-            .await
-        {
-            Some((size, data)) => (size, data),
-            None => return None,
-        };
-        Some((size, DisplayAssets::new(bytes)))
+            .await?;
+        Ok((size, DisplayAssets::new(bytes)))
     }
 
     pub async fn set_display_assets(
@@ -1239,7 +1201,7 @@ impl SayoDeviceApi {
         + Send
         + Sync
         + 'static,
-    ) -> bool {
+    ) -> DeviceResult<TransferReport> {
         self.set_addressable_data::<DisplayAssetsPacket>(
             index,
             display_assets.bytes.clone(),
@@ -1249,36 +1211,35 @@ impl SayoDeviceApi {
         .await
     }
 
-    pub async fn get_script_address_len(&self, index: u8) -> u32 {
+    pub async fn get_script_address_len(&self, index: u8) -> DeviceResult<u32> {
         self.get_addressable_data_len::<SayoScriptPacket>(index)
             .await
     }
 
-    pub async fn get_script_with_addr(&self, index: u8, addr: u32) -> Option<SayoScriptPacket> {
+    pub async fn get_script_with_addr(&self, index: u8, addr: u32) -> DeviceResult<SayoScriptPacket> {
         self.get_addressable_data_with_addr::<SayoScriptPacket>(index, addr)
             .await
     }
 
-    pub async fn get_script(&self, index: u8) -> Option<(u32, SayoScriptContent)> {
+    pub async fn get_script(&self, index: u8) -> DeviceResult<(u32, SayoScriptContent)> {
         //max address, script
-        let (size, bytes) = match self.get_addressable_data::<SayoScriptPacket>(index).await {
-            Some((size, data)) => (size, data),
-            None => return None,
-        };
-        Some((size, SayoScriptContent::new(bytes)))
+        let (size, bytes) = self.get_addressable_data::<SayoScriptPacket>(index).await?;
+        Ok((size, SayoScriptContent::new(bytes)))
     }
 
-    pub async fn get_all_scripts(&self) -> Vec<(u32, SayoScriptContent)> {
+    pub async fn get_all_scripts(&self) -> DeviceResult<Vec<(u32, SayoScriptContent)>> {
         let mut res = Vec::new();
         let mut index = 0;
         loop {
             match self.get_script(index).await {
-                Some((max_len, script)) => res.push((max_len, script)),
-                None => break,
+                Ok((max_len, script)) => res.push((max_len, script)),
+                // 越过最后一个脚本
+                Err(e) if e.status() == Some(ResponseStatus::IndexMissing) => break,
+                Err(e) => return Err(e),
             }
             index += 1;
         }
-        return res;
+        Ok(res)
     }
 
     pub async fn set_script(
@@ -1290,7 +1251,7 @@ impl SayoDeviceApi {
         + Send
         + Sync
         + 'static,
-    ) -> bool {
+    ) -> DeviceResult<TransferReport> {
         self.set_addressable_data::<SayoScriptPacket>(
             index,
             script.bytes.clone(),
@@ -1303,31 +1264,26 @@ impl SayoDeviceApi {
     pub async fn get_addressable_data_len<T: AddressableData + CodecableHidPackage>(
         &self,
         index: u8,
-    ) -> u32 {
+    ) -> DeviceResult<u32> {
         let report_id = self.get_report_id();
         let cmd = T::CMD.expect("No CMD found for AddressableData in get_addressable_data_len");
         let over_addr = T::new(RwBytes::new(vec![0xFF, 0xFF, 0xFF, 0xFF]));
-        let res = self
-            .request_with_header(report_id, SayoDeviceApi::ECHO, cmd, index, &over_addr)
-            .await;
-        if res.is_none() {
-            return 0;
-        }
-        let (header, body) = res.expect("No response from device");
-        if header.status(None) != Some(STATUS_OVERFLOW) {
-            return 0;
+        let (header, body) = self
+            .request_with_header(report_id, cmd, index, &over_addr)
+            .await?;
+        let status = header.status(None).ok_or(ReportError::BadReportHeader)?;
+        if status != ResponseStatus::TooLong {
+            return Err(DeviceError::DeviceStatus { status, cmd, index });
         }
-        return match body.address(None) {
-            Some(addr) => addr,
-            None => 0,
-        };
+        body.address(None)
+            .ok_or(DeviceError::InvalidResponse("missing address length".to_string()))
     }
 
     pub async fn get_addressable_data_with_addr<T: AddressableData + CodecableHidPackage>(
         &self,
         index: u8,
         addr: u32,
-    ) -> Option<T> {
+    ) -> DeviceResult<T> {
         let report_id = self.get_report_id();
         let cmd: u8 =
             T::CMD.expect("No CMD found for AddressableData in get_addressable_data_with_addr");
@@ -1338,7 +1294,7 @@ impl SayoDeviceApi {
             (addr >> 24) as u8,
         ]));
 
-        let response = self.request(report_id, SayoDeviceApi::ECHO, cmd, index, &empty);
+        let response = self.request(report_id, cmd, index, &empty);
         response.await
     }
 
@@ -1346,19 +1302,20 @@ impl SayoDeviceApi {
         &self,
         index: u8,
         on_data_recv: SafeCallback<Vec<u8>, bool>,
-    ) {
+    ) -> DeviceResult<()> {
         let max_len = match self
             .get_addressable_data_len::<DisplayAssetsPacket>(index)
             .await
         {
-            0 => {
-                #[cfg(target_arch = "wasm32")]
+            Ok(0) => {
                 on_data_recv.call(vec![0x00; 0]).await;
-                #[cfg(not(target_arch = "wasm32"))]
+                return Ok(());
+            }
+            Ok(len) => len,
+            Err(e) => {
                 on_data_recv.call(vec![0x00; 0]).await;
-                return;
+                return Err(e);
             }
-            len => len,
         };
         #[cfg(target_arch = "wasm32")]
         on_data_recv.call(max_len.to_le_bytes().to_vec()).await;
@@ -1368,30 +1325,31 @@ impl SayoDeviceApi {
         let mut retry_cnt = 0;
 
         while bytes.len() < max_len as usize {
+            if let Err(e) = self.check_cancelled() {
+                on_data_recv.call(vec![0x00; 0]).await;
+                return Err(e);
+            }
             let data_packet = match self
                 .get_addressable_data_with_addr::<DisplayAssetsPacket>(index, bytes.len() as u32)
                 .await
             {
-                Some(data) => data,
-                None => {
-                    if retry_cnt >= 3 {
-                        break;
+                Ok(data) => data,
+                Err(e) => {
+                    if retry_cnt >= 3 || matches!(e, DeviceError::Cancelled) {
+                        on_data_recv.call(vec![0x00; 0]).await;
+                        return Err(e);
                     }
                     retry_cnt += 1;
                     continue;
                 }
             };
             retry_cnt = 0;
-            if data_packet
-                .address(None)
-                .expect("Can not get address for data_packet")
-                != bytes.len() as u32
-            {
-                #[cfg(target_arch = "wasm32")]
-                on_data_recv.call(vec![0x00; 0]).await;
-                #[cfg(not(target_arch = "wasm32"))]
+            if data_packet.address(None) != Some(bytes.len() as u32) {
                 on_data_recv.call(vec![0x00; 0]).await;
-                break;
+                return Err(DeviceError::InvalidResponse(format!(
+                    "Data addr not match: expect {:#X}",
+                    bytes.len()
+                )));
             }
             #[cfg(target_arch = "wasm32")]
             let next = on_data_recv
@@ -1410,7 +1368,7 @@ impl SayoDeviceApi {
                 )
                 .await;
             if !next {
-                println!("on_data_recv done");
+                debug!(uuid:% = uuid::Uuid::from_u128(self.uuid), index; "display assets stream stopped by receiver");
                 break;
             }
             bytes.append(
@@ -1423,38 +1381,28 @@ impl SayoDeviceApi {
         _ = self
             .get_addressable_data_len::<DisplayAssetsPacket>(index)
             .await;
-        #[cfg(target_arch = "wasm32")]
         on_data_recv.call(vec![0x00; 0]).await;
-        #[cfg(not(target_arch = "wasm32"))]
-        on_data_recv.call(vec![0x00; 0]).await;
-        return;
+        Ok(())
     }
 
     pub async fn get_addressable_data<T: AddressableData + CodecableHidPackage>(
         &self,
         index: u8,
-    ) -> Option<(u32, RwBytes)> {
-        let max_len = match self.get_addressable_data_len::<T>(index).await {
-            0 => return None,
-            len => len,
-        };
+    ) -> DeviceResult<(u32, RwBytes)> {
+        let max_len = self.get_addressable_data_len::<T>(index).await?;
         let mut bytes = Vec::new();
         // let mut current_data_end = 0;
 
         while bytes.len() < max_len as usize {
-            let data_packet = match self
+            self.check_cancelled()?;
+            let data_packet = self
                 .get_addressable_data_with_addr::<T>(index, bytes.len() as u32)
-                .await
-            {
-                Some(data) => data,
-                None => break,
-            };
-            if data_packet
-                .address(None)
-                .expect("Can not get address for data_packet")
-                != bytes.len() as u32
-            {
-                panic!("Data addr not match");
+                .await?;
+            if data_packet.address(None) != Some(bytes.len() as u32) {
+                return Err(DeviceError::InvalidResponse(format!(
+                    "Data addr not match: expect {:#X}",
+                    bytes.len()
+                )));
             }
             bytes.append(
                 &mut data_packet
@@ -1490,7 +1438,7 @@ impl SayoDeviceApi {
         if bytes.len() < max_len as usize {
             bytes.resize(max_len as usize, 0x00);
         }
-        Some((max_len, RwBytes::new(bytes)))
+        Ok((max_len, RwBytes::new(bytes)))
     }
 
     // data should be whole data, that mean data should begin at address 0x00000000
@@ -1503,15 +1451,19 @@ impl SayoDeviceApi {
         + Send
         + Sync
         + 'static,
-    ) -> bool {
-        println!("set_addressable_data: {:?} at {:?}", data, base_addr);
+    ) -> DeviceResult<TransferReport> {
         let report_id = self.get_report_id();
         let cmd: u8 = T::CMD.expect("No CMD found for AddressableData in set_addressable_data");
 
         let max_packet_len = match report_id {
             REPORT_ID_BOOTUP => MAX_PACKET_LEN_REPORT_21,
             REPORT_ID_MAIN => MAX_PACKET_LEN_REPORT_22,
-            _ => 0,
+            _ => {
+                return Err(DeviceError::InvalidData(format!(
+                    "unsupported report id {:#04X}",
+                    report_id
+                )));
+            }
         };
 
         let mut address = base_addr;
@@ -1526,106 +1478,121 @@ impl SayoDeviceApi {
             addr_end += ADDR_ALIGNMENT - (addr_end % ADDR_ALIGNMENT);
         }
 
-        println!(
-            "set_addressable_data: address: {:?} addr_end: {:?} data len: {:?}",
-            address,
-            addr_end,
-            data.len()
+        debug!(
+            uuid:% = uuid::Uuid::from_u128(self.uuid), cmd, index, base_addr, address, addr_end, len = data.len();
+            "set addressable data"
         );
 
         let bytes = if addr_end > data.len() {
             let data_len = data.len();
-            let mut copy = match data.ref_at(address, data_len - address) {
-                Some(data) => data.into_vec(),
-                None => {
-                    println!("Can not get ref_at in set_addressable_data 0");
-                    return false;
-                }
-            };
+            let mut copy = data
+                .ref_at(address, data_len - address)
+                .ok_or(DeviceError::InvalidData(format!(
+                    "base address {:#X} out of data range",
+                    address
+                )))?
+                .into_vec();
             copy.append(&mut vec![0x00; addr_end - data_len as usize]);
             RwBytes::new(copy)
         } else {
-            match data.ref_at(address, addr_end - address) {
-                Some(data) => data,
-                None => {
-                    println!("Can not get ref_at in set_addressable_data 1");
-                    return false;
-                }
-            }
+            data.ref_at(address, addr_end - address)
+                .ok_or(DeviceError::InvalidData(format!(
+                    "base address {:#X} out of data range",
+                    address
+                )))?
         };
 
-        println!("set_addressable_data: bytes: {:?}", bytes);
+        trace!(uuid:% = uuid::Uuid::from_u128(self.uuid), cmd, index; "set addressable data bytes: {:02X?}", bytes.vec(0, None, None).unwrap_or_default());
 
         // println!("send data: len: {:?} {:02X?}", bytes.len(), bytes.clone().into_vec());
-        let mut packets: Vec<T> = Vec::new();
+        let mut chunks = Vec::new();
         for i in (0..bytes.len()).step_by(max_packet_len) {
-            let addr = address + i;
-            let mut packet_data = Vec::new();
             let packet_len = std::cmp::min(max_packet_len, bytes.len() - i);
-            packet_data.push(addr as u8);
-            packet_data.push((addr >> 8) as u8);
-            packet_data.push((addr >> 16) as u8);
-            packet_data.push((addr >> 24) as u8);
-            packet_data.append(
-                &mut bytes
+            chunks.push(TransferChunk {
+                addr: (address + i) as u32,
+                data: bytes
                     .ref_at(i, packet_len)
-                    .expect(format!("Can not get ref_at in set_addressable_data 2").as_str())
+                    .expect("Can not get ref_at in set_addressable_data 2")
                     .into_vec(),
-            );
-            let packet = T::new(RwBytes::new(packet_data));
-            packets.push(packet);
-        }
-        let mut responses = Vec::new();
-        for packet in &packets {
-            let response = self.request(report_id, SayoDeviceApi::ECHO, cmd, index, packet);
-            responses.push(response);
+            });
         }
-        let mut complate = true;
-        let mut failed_index = Vec::new();
-        let mut res_index = 0;
-        for response in responses {
-            match response.await {
-                Some(_) => (),
-                None => {
-                    complate = false;
-                    failed_index.push(res_index);
+        let options = self.options;
+        // on_progress 返回 false 时只取消本次传输：令牌派生自调用方的令牌，调用方取消时一并取消
+        let token = match &self.cancel {
+            Some(cancel) => cancel.child_token(),
+            None => CancellationToken::new(),
+        };
+        // 丢包由 transfer_retransmits 重传，单包请求不再叠加 options.retries
+        let this = self
+            .with_options(RequestOptions { retries: 0, ..options })
+            .with_cancellation(token.clone());
+        let this = &this;
+        let report = transfer::run_windowed(
+            chunks,
+            options.transfer_window,
+            options.transfer_retransmits,
+            &token,
+            |chunk| async move {
+                let mut packet_data = chunk.addr.to_le_bytes().to_vec();
+                packet_data.extend(chunk.data);
+                let packet = T::new(RwBytes::new(packet_data));
+                let response = this.request(report_id, cmd, index, &packet).await?;
+                // 应答携带地址时核对，避免把其他包的应答当作本包的确认
+                match response.address(None) {
+                    Some(addr) if addr != chunk.addr => Err(DeviceError::InvalidResponse(format!(
+                        "Data addr not match: expect {:#X}, got {:#X}",
+                        chunk.addr, addr
+                    ))),
+                    _ => Ok(()),
                 }
-            }
-            res_index += 1;
-            let progress = res_index as f32 / packets.len() as f32;
-            #[cfg(target_arch = "wasm32")]
-            let _ = on_progress(progress).await;
-            #[cfg(not(target_arch = "wasm32"))]
-            let _ = block_in_thread(on_progress(progress));
+            },
+            |progress| {
+                let progress = on_progress(progress);
+                async move {
+                    #[cfg(target_arch = "wasm32")]
+                    return progress.await;
+                    #[cfg(not(target_arch = "wasm32"))]
+                    return block_in_thread(progress);
+                }
+            },
+        )
+        .await;
+        if token.is_cancelled() {
+            debug!(
+                uuid:% = uuid::Uuid::from_u128(self.uuid), cmd, index;
+                "send addressable data cancelled with ranges {:X?}", report.unconfirmed
+            );
+            return Err(DeviceError::TransferCancelled(report));
         }
-        _ = self.get_addressable_data_len::<T>(index).await;
-        if complate {
-            println!(
-                "send addressable data complate with len {:?} in {:?} packets",
-                bytes.len(),
-                packets.len()
+        if report.is_complete() {
+            // 写入完成后读一次长度作为收尾，失败时如实返回
+            self.get_addressable_data_len::<T>(index).await?;
+            debug!(
+                uuid:% = uuid::Uuid::from_u128(self.uuid), cmd, index, len = report.total_bytes, packets = report.packets, retransmitted = report.retransmitted;
+                "send addressable data complete"
             );
+            Ok(report)
         } else {
-            println!(
-                "send addressable data failed with packets {:?}",
-                failed_index
+            warn!(
+                uuid:% = uuid::Uuid::from_u128(self.uuid), cmd, index;
+                "send addressable data failed with ranges {:X?}", report.unconfirmed
             );
+            Err(DeviceError::TransferIncomplete(report))
         }
-        return complate;
     }
 
-    pub async fn get_analog_key_infos2(&self) -> Vec<AnalogKeyInfo2> {
+    pub async fn get_analog_key_infos2(&self) -> DeviceResult<Vec<AnalogKeyInfo2>> {
         let report_id = self.get_report_id();
         let cmd: u8 = AnalogKeyInfo2::CMD.expect("No CMD found for AnalogKeyInfo2");
         let response = self.request_all_index::<AnalogKeyInfo2>(report_id, cmd);
         response.await
     }
 
-    pub async fn get_analog_key_info2(&self, index: u8) -> Option<AnalogKeyInfo2> {
+    pub async fn get_analog_key_info2(&self, index: u8) -> DeviceResult<AnalogKeyInfo2> {
         let report_id = self.get_report_id();
         let cmd: u8 = AnalogKeyInfo2::CMD.expect("No CMD found for AnalogKeyInfo2");
         let empty = AnalogKeyInfo2::empty();
-        let response = self.request(report_id, SayoDeviceApi::ECHO, cmd, index, &empty);
+        let response = self.request(report_id, cmd, index, &empty);
         response.await
     }
 
@@ -1633,25 +1600,64 @@ impl SayoDeviceApi {
         &self,
         index: u8,
         key_info: &mut AnalogKeyInfo2,
-    ) -> Option<AnalogKeyInfo2> {
+    ) -> DeviceResult<AnalogKeyInfo2> {
         let report_id = self.get_report_id();
         let cmd: u8 = AnalogKeyInfo2::CMD.expect("No CMD found for AnalogKeyInfo2");
-        let response = self.request(report_id, SayoDeviceApi::ECHO, cmd, index, key_info);
+        let response = self.request(report_id, cmd, index, key_info);
         response.await
     }
 
-    pub async fn get_advanced_keys(&self) -> Vec<AdvancedKeyBinding> {
+    // 以下三个方法按设备能力自动选择 AnalogKeyInfo2（0x1C）或 AnalogKeyInfo（0x14），
+    // 统一以 AnalogKeyInfo2 表示，行程单位为微米
+    pub async fn get_analog_keys(&self) -> DeviceResult<Vec<AnalogKeyInfo2>> {
+        let capabilities = self.capabilities().await?;
+        if capabilities.uses_analog_key_info2() {
+            return self.get_analog_key_infos2().await;
+        }
+        let keys = self.get_analog_key_infos().await?;
+        Ok(keys
+            .into_iter()
+            .map(|mut key| AnalogKeyInfo2::from_v1(&mut key, capabilities.ver))
+            .collect())
+    }
+
+    pub async fn get_analog_key(&self, index: u8) -> DeviceResult<AnalogKeyInfo2> {
+        let capabilities = self.capabilities().await?;
+        if capabilities.uses_analog_key_info2() {
+            return self.get_analog_key_info2(index).await;
+        }
+        let mut key = self.get_analog_key_info(index).await?;
+        Ok(AnalogKeyInfo2::from_v1(&mut key, capabilities.ver))
+    }
+
+    pub async fn set_analog_key(
+        &self,
+        index: u8,
+        key_info: &AnalogKeyInfo2,
+    ) -> DeviceResult<AnalogKeyInfo2> {
+        let capabilities = self.capabilities().await?;
+        if capabilities.uses_analog_key_info2() {
+            let report_id = self.get_report_id();
+            let cmd: u8 = AnalogKeyInfo2::CMD.expect("No CMD found for AnalogKeyInfo2");
+            return self.request(report_id, cmd, index, key_info).await;
+        }
+        let v1 = key_info.to_v1(capabilities.ver);
+        let mut key = self.set_analog_key_info(index, &v1).await?;
+        Ok(AnalogKeyInfo2::from_v1(&mut key, capabilities.ver))
+    }
+
+    pub async fn get_advanced_keys(&self) -> DeviceResult<Vec<AdvancedKeyBinding>> {
         let report_id = self.get_report_id();
         let cmd: u8 = AdvancedKeyBinding::CMD.expect("No CMD found for AdvancedKeyBinding");
         let response = self.request_all_index::<AdvancedKeyBinding>(report_id, cmd);
         response.await
     }
 
-    pub async fn get_advanced_key(&self, index: u8) -> Option<AdvancedKeyBinding> {
+    pub async fn get_advanced_key(&self, index: u8) -> DeviceResult<AdvancedKeyBinding> {
         let report_id = self.get_report_id();
         let cmd: u8 = AdvancedKeyBinding::CMD.expect("No CMD found for AdvancedKeyBinding");
         let empty = AdvancedKeyBinding::empty();
-        let response = self.request(report_id, SayoDeviceApi::ECHO, cmd, index, &empty);
+        let response = self.request(report_id, cmd, index, &empty);
         response.await
     }
 
@@ -1659,30 +1665,28 @@ impl SayoDeviceApi {
         &self,
         index: u8,
         key_info: &AdvancedKeyBinding,
-    ) -> Option<AdvancedKeyBinding> {
+    ) -> DeviceResult<AdvancedKeyBinding> {
         let report_id = self.get_report_id();
         let cmd: u8 = AdvancedKeyBinding::CMD.expect("No CMD found for AdvancedKeyBinding");
-        let response = self.request(report_id, SayoDeviceApi::ECHO, cmd, index, key_info);
+        let response = self.request(report_id, cmd, index, key_info);
         response.await
     }
 
-    pub async fn get_key_physical_status(&self) -> Option<Vec<u8>> {
+    pub async fn get_key_physical_status(&self) -> DeviceResult<Vec<u8>> {
         let report_id = self.get_report_id();
         let cmd: u8 = 0x1E;
-        let response = self
-            .request(report_id, SayoDeviceApi::ECHO, cmd, 0, &ByteArray::empty())
-            .await;
+        let byte_array = self
+            .request(report_id, cmd, 0, &ByteArray::empty())
+            .await?;
         // map bit to byte
         let mut res: Vec<u8> = Vec::new();
-        if let Some(byte_array) = response {
-            let bytes = byte_array.data(None).expect("No bytes in ByteArray");
-            for byte in bytes {
-                for i in 0..8 {
-                    res.push((byte >> i) & 0x01);
-                }
+        let bytes = byte_array.data(None).unwrap_or_default();
+        for byte in bytes {
+            for i in 0..8 {
+                res.push((byte >> i) & 0x01);
             }
         }
-        Some(res)
+        Ok(res)
     }
 
     // pub async fn set_key_phyical_status(&self, status: Vec<u8>) -> bool {
@@ -1700,36 +1704,36 @@ impl SayoDeviceApi {
     //     response.is_some()
     // }
 
-    pub async fn get_led_effect(&self) -> Option<LedEffect> {
+    pub async fn get_led_effect(&self) -> DeviceResult<LedEffect> {
         let report_id = self.get_report_id();
         let cmd: u8 = 0x26;
-        self.request(report_id, SayoDeviceApi::ECHO, cmd, 0, &LedEffect::empty())
+        self.request(report_id, cmd, 0, &LedEffect::empty())
             .await
     }
 
-    pub async fn set_led_effect(&self, effect: &LedEffect) -> Option<LedEffect> {
+    pub async fn set_led_effect(&self, effect: &LedEffect) -> DeviceResult<LedEffect> {
         let report_id = self.get_report_id();
         let cmd: u8 = 0x26;
-        self.request(report_id, SayoDeviceApi::ECHO, cmd, 0, effect)
+        self.request(report_id, cmd, 0, effect)
             .await
     }
 
-    pub async fn get_led_index_count(&self) -> u8 {
+    pub async fn get_led_index_count(&self) -> DeviceResult<u8> {
         let report_id = self.get_report_id();
         const CMD: u8 = 0x27;
 
-        self.request_all_index::<ByteArray>(report_id, CMD)
-            .await
-            .len() as u8
+        Ok(self
+            .request_all_index::<ByteArray>(report_id, CMD)
+            .await?
+            .len() as u8)
     }
 
-    pub async fn get_led_status(&self, from_index: Option<u8>) -> Option<ByteArray> {
+    pub async fn get_led_status(&self, from_index: Option<u8>) -> DeviceResult<ByteArray> {
         let report_id = self.get_report_id();
         const CMD: u8 = 0x27;
         let bytes = ByteArray::empty();
         let response = self.request(
             report_id,
-            SayoDeviceApi::ECHO,
             CMD,
             from_index.unwrap_or(0x00),
             &bytes,
@@ -1737,10 +1741,9 @@ impl SayoDeviceApi {
         response.await
     }
 
-    pub async fn get_gamepad_cfg(&self) -> Option<GamePadCfg> {
+    pub async fn get_gamepad_cfg(&self) -> DeviceResult<GamePadCfg> {
         self.request(
             self.get_report_id(),
-            SayoDeviceApi::ECHO,
             0x28,
             0,
             &GamePadCfg::empty(),
@@ -1748,27 +1751,27 @@ impl SayoDeviceApi {
         .await
     }
 
-    pub async fn set_gamepad_cfg(&self, cfg: &GamePadCfg) -> Option<GamePadCfg> {
-        self.request(self.get_report_id(), SayoDeviceApi::ECHO, 0x28, 0, cfg)
+    pub async fn set_gamepad_cfg(&self, cfg: &GamePadCfg) -> DeviceResult<GamePadCfg> {
+        self.request(self.get_report_id(), 0x28, 0, cfg)
             .await
     }
 
-    pub async fn get_ambient_led(&self, index: u8) -> Option<AmbientLED> {
+    pub async fn get_ambient_led(&self, index: u8) -> DeviceResult<AmbientLED> {
         let report_id = self.get_report_id();
         const CMD: u8 = 0x2A;
         let empty = AmbientLED::empty();
-        let response = self.request(report_id, SayoDeviceApi::ECHO, CMD, index, &empty);
+        let response = self.request(report_id, CMD, index, &empty);
         response.await
     }
 
-    pub async fn set_ambient_led(&self, index: u8, ambient_led: &AmbientLED) -> Option<AmbientLED> {
+    pub async fn set_ambient_led(&self, index: u8, ambient_led: &AmbientLED) -> DeviceResult<AmbientLED> {
         let report_id = self.get_report_id();
         const CMD: u8 = 0x2A;
-        let response = self.request(report_id, SayoDeviceApi::ECHO, CMD, index, ambient_led);
+        let response = self.request(report_id, CMD, index, ambient_led);
         response.await
     }
 
-    pub async fn get_ambient_leds(&self) -> Vec<AmbientLED> {
+    pub async fn get_ambient_leds(&self) -> DeviceResult<Vec<AmbientLED>> {
         let report_id = self.get_report_id();
         const CMD: u8 = 0x2A;
         self.request_all_index::<AmbientLED>(report_id, CMD).await
@@ -7,9 +7,19 @@ pub const BROADCAST_TYPE_PROFILE: u8 = 0x05;
 pub const BROADCAST_TYPE_RSSI: u8 = 0x06;
 pub const BROADCAST_TYPE_KEY_PRESS: u8 = 0x10;
 pub const BROADCAST_TYPE_KEY_RELEASE: u8 = 0x11;
+pub const BROADCAST_TYPE_SK_ADD: u8 = 0x12;
+pub const BROADCAST_TYPE_SK_DEL: u8 = 0x13;
+pub const BROADCAST_TYPE_GK_ADD: u8 = 0x14;
+pub const BROADCAST_TYPE_GK_DEL: u8 = 0x15;
+pub const BROADCAST_TYPE_JOYSTICK_ADD: u8 = 0x16;
+pub const BROADCAST_TYPE_JOYSTICK_DEL: u8 = 0x17;
+pub const BROADCAST_TYPE_JOYSTICK_HAT: u8 = 0x18;
 pub const BROADCAST_TYPE_HALL_KEY_RELOAD: u8 = 0x19;
 pub const BROADCAST_TYPE_SYS_TIME_MS: u8 = 0x80;
+pub const BROADCAST_TYPE_MU_DATA: u8 = 0x81;
 pub const BROADCAST_TYPE_SYS_TIME: u8 = 0xC0;
+pub const BROADCAST_TYPE_MO_DATA: u8 = 0xC2;
+pub const BROADCAST_TYPE_POINT: u8 = 0xC3;
 pub const BROADCAST_TYPE_LEVELS: u8 = 0xE1;
 pub const BROADCAST_TYPE_ERROR_MSG: u8 = 0xFE;
 pub const BROADCAST_TYPE_LOG_MSG: u8 = 0xFF;
@@ -74,6 +84,13 @@ pub const LEVELS_DATA_LEN_35: u8 = 35;
 pub const LEVELS_BUFFER_SIZE: usize = 1600;
 pub const LEVEL_THRESHOLD: u16 = 50;
 pub const LEVEL_MASK: u16 = 0x3FFF;
+// 固件 120 起提供 AnalogKeyInfo2（0x1C）；之前的 AnalogKeyInfo（0x14）行程以 50 微米为一档，之后为 0.01mm
+pub const ANALOG_KEY_V2_VERSION: u16 = 120;
+
+// 设备日志
+pub const LOG_HISTORY_SIZE: usize = 1000;
+pub const LOG_MAX_MESSAGE_LEN: usize = 4096;
+pub const LOG_REASSEMBLY_TIMEOUT_US: u64 = 200_000;
 
 // 重试和超时常量 - 修复类型匹配
 pub const MAX_RETRY_COUNT: usize = 8;
@@ -86,6 +103,7 @@ pub const ADDR_ALIGNMENT: usize = 4096;
 pub const STATUS_OK: u8 = 0x00;
 pub const STATUS_PARTIAL: u8 = 0x02;
 pub const STATUS_COMPLETE: u8 = 0x03;
+pub const STATUS_INDEX_MISSING: u8 = 0x10;
 pub const STATUS_OVERFLOW: u8 = 0x11;
 
 // 子命令常量
@@ -1,5 +1,9 @@
 use std::fmt;
 
+use crate::report_codec::{ReportError, ResponseStatus};
+use crate::restore::{ArchiveMismatch, RestoreReport};
+use crate::transfer::TransferReport;
+
 #[derive(Debug, Clone)]
 pub enum DeviceError {
     ConnectionFailed(String),
@@ -10,6 +14,31 @@ pub enum DeviceError {
     EncodingError(String),
     InvalidData(String),
     LockError(String),
+    // 设备应答了非成功状态码，例如 0x10 index 不存在、0x3F cmd 不存在
+    DeviceStatus {
+        status: ResponseStatus,
+        cmd: u8,
+        index: u8,
+    },
+    Report(ReportError),
+    // 可寻址数据传输结束时仍有未确认的地址范围
+    TransferIncomplete(TransferReport),
+    Cancelled,
+    // 可寻址数据传输中途被取消，报告中为已确认和未确认的范围
+    TransferCancelled(TransferReport),
+    // 进程内的 echo 池已分配完
+    EchoExhausted,
+    // 句柄所属的 SayoContext 已关闭或释放
+    ContextClosed,
+    // 设备的 api_list 未声明该命令，请求没有发出
+    Unsupported { cmd: u8 },
+    // 配置归档与目标设备型号不同，拒绝恢复
+    ArchiveMismatch(ArchiveMismatch),
+    // 恢复中途出错（error）或读回与归档不一致，未保存；report 为已完成的部分
+    RestoreIncomplete {
+        report: RestoreReport,
+        error: Option<Box<DeviceError>>,
+    },
 }
 
 impl fmt::Display for DeviceError {
@@ -23,12 +52,94 @@ impl fmt::Display for DeviceError {
             DeviceError::EncodingError(msg) => write!(f, "编码错误: {}", msg),
             DeviceError::InvalidData(msg) => write!(f, "无效数据: {}", msg),
             DeviceError::LockError(msg) => write!(f, "锁错误: {}", msg),
+            DeviceError::DeviceStatus { status, cmd, index } => write!(
+                f,
+                "设备返回状态 {:#04X} ({}): cmd {:#04X} index {:#04X}",
+                u8::from(*status),
+                status,
+                cmd,
+                index
+            ),
+            DeviceError::Report(e) => write!(f, "报告编解码错误: {}", e),
+            DeviceError::TransferIncomplete(report) => {
+                write!(f, "传输未完成: 未确认地址 {:X?}", report.unconfirmed)
+            }
+            DeviceError::Cancelled => write!(f, "操作已取消"),
+            DeviceError::TransferCancelled(report) => {
+                write!(f, "传输已取消: 未确认地址 {:X?}", report.unconfirmed)
+            }
+            DeviceError::EchoExhausted => write!(f, "echo 池已用完"),
+            DeviceError::ContextClosed => write!(f, "上下文已关闭"),
+            DeviceError::Unsupported { cmd } => write!(f, "设备不支持 cmd {:#04X}", cmd),
+            DeviceError::ArchiveMismatch(mismatch) => write!(f, "配置归档不匹配: {}", mismatch),
+            DeviceError::RestoreIncomplete { report, error } => {
+                write!(f, "恢复未完成: 已写入 {} 项", report.written.len())?;
+                if let Some((section, index)) = report.failed {
+                    write!(f, "，写入 {}[{}] 失败", section, index)?;
+                }
+                if !report.mismatched.is_empty() {
+                    write!(f, "，读回不一致 {:?}", report.mismatched)?;
+                }
+                match error {
+                    Some(error) => write!(f, ": {}", error),
+                    None => Ok(()),
+                }
+            }
         }
     }
 }
 
 impl std::error::Error for DeviceError {}
 
+impl DeviceError {
+    // 设备状态码，仅 DeviceStatus 有
+    pub fn status(&self) -> Option<ResponseStatus> {
+        match self {
+            DeviceError::DeviceStatus { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+
+    // 当前固件不支持该命令或该 index
+    pub fn is_unsupported(&self) -> bool {
+        matches!(self, DeviceError::Unsupported { .. })
+            || matches!(
+                self.status(),
+                Some(ResponseStatus::IndexMissing) | Some(ResponseStatus::UnknownCmd)
+            )
+    }
+
+    // 超时、CRC 等偶发错误，重发请求可能成功
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            DeviceError::ReceiveTimeout => true,
+            DeviceError::Report(ReportError::CrcError) => true,
+            DeviceError::DeviceStatus { status, .. } => status.is_retryable(),
+            _ => false,
+        }
+    }
+
+    // 设备已拔出或无法通信
+    pub fn is_disconnected(&self) -> bool {
+        matches!(
+            self,
+            DeviceError::ConnectionFailed(_)
+                | DeviceError::SendReportFailed(_)
+                | DeviceError::DeviceNotFound(_)
+                | DeviceError::ContextClosed
+        )
+    }
+}
+
+impl From<ReportError> for DeviceError {
+    fn from(e: ReportError) -> Self {
+        match e {
+            ReportError::Timeout => DeviceError::ReceiveTimeout,
+            e => DeviceError::Report(e),
+        }
+    }
+}
+
 pub type DeviceResult<T> = Result<T, DeviceError>;
 
 // 辅助函数用于安全的字符串转换
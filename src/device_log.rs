@@ -0,0 +1,369 @@
+// 设备日志：收集 0xFF LOG_MSG / 0xFE ERROR_MSG 广播中的固件文本。
+// 一条消息以 '\n' 或 NUL 结束；没有结束符且位于报告末尾的片段视为被报告边界截断，
+// 与下一个报告中同类型的片段拼接，超过 LOG_REASSEMBLY_TIMEOUT_US 未续上则按已收到的内容输出：
+// 下一个广播到达、读取 history 或轮询日志流时检查超时，日志流另有定时器在到期时唤醒。
+// 文本优先按 UTF-8 解码，失败时按 GB18030。
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll};
+
+use encoding_rs::GB18030;
+use futures::{Future, Stream};
+
+use crate::broadcast::{self, BroadcastStream, Lagged, RecordContext, Sender};
+use crate::cross_platform_utils::now_micros;
+use crate::device_constants::*;
+use crate::structures::BroadCastData;
+use crate::utility::{future_delay, lock};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LogLevel {
+    Log,
+    Error,
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogLevel::Log => write!(f, "LOG"),
+            LogLevel::Error => write!(f, "ERR"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEntry {
+    pub level: LogLevel,
+    // 收到消息第一个片段的时间
+    pub received_us: u64,
+    pub device_time_ms: Option<u16>,
+    pub text: String,
+}
+
+impl fmt::Display for LogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.device_time_ms {
+            Some(ms) => write!(f, "[{:5}ms] {} {}", ms, self.level, self.text),
+            None => write!(f, "[{:>7}] {} {}", "-", self.level, self.text),
+        }
+    }
+}
+
+struct Pending {
+    context: RecordContext,
+    bytes: Vec<u8>,
+}
+
+#[derive(Default)]
+struct DeviceLog {
+    history: VecDeque<LogEntry>,
+    pending: HashMap<LogLevel, Pending>,
+    senders: Vec<Sender<LogEntry>>,
+}
+
+impl DeviceLog {
+    fn flush(&mut self, level: LogLevel) {
+        let Some(pending) = self.pending.remove(&level) else {
+            return;
+        };
+        let text = decode_text(&pending.bytes);
+        let text = text.trim_end_matches('\r');
+        if text.is_empty() {
+            return;
+        }
+        let entry = LogEntry {
+            level,
+            received_us: pending.context.received_us,
+            device_time_ms: pending.context.device_time_ms,
+            text: text.to_string(),
+        };
+        match level {
+            LogLevel::Log => log::debug!(text = entry.text.as_str(); "device log"),
+            LogLevel::Error => log::warn!(text = entry.text.as_str(); "device error"),
+        }
+        if self.history.len() >= LOG_HISTORY_SIZE {
+            self.history.pop_front();
+        }
+        self.history.push_back(entry.clone());
+        self.senders.retain(|sender| sender.send(entry.clone()));
+    }
+
+    fn close(&mut self) {
+        self.flush(LogLevel::Log);
+        self.flush(LogLevel::Error);
+        for sender in self.senders.drain(..) {
+            sender.close();
+        }
+    }
+
+    // 输出超时的片段，返回距离剩余片段超时还有多少微秒
+    fn expire(&mut self, now_us: u64) -> Option<u64> {
+        for level in [LogLevel::Log, LogLevel::Error] {
+            if let Some(pending) = self.pending.get(&level)
+                && now_us.saturating_sub(pending.context.received_us) > LOG_REASSEMBLY_TIMEOUT_US
+            {
+                self.flush(level);
+            }
+        }
+        self.pending
+            .values()
+            .map(|pending| {
+                (pending.context.received_us + LOG_REASSEMBLY_TIMEOUT_US + 1).saturating_sub(now_us)
+            })
+            .min()
+    }
+
+    fn feed(&mut self, level: LogLevel, context: &RecordContext, bytes: &[u8], continued: bool) {
+        for &byte in bytes {
+            if byte == b'\n' || byte == 0 {
+                self.flush(level);
+                continue;
+            }
+            let pending = self.pending.entry(level).or_insert_with(|| Pending {
+                context: *context,
+                bytes: Vec::new(),
+            });
+            pending.bytes.push(byte);
+            if pending.bytes.len() >= LOG_MAX_MESSAGE_LEN {
+                self.flush(level);
+            }
+        }
+        if !continued {
+            self.flush(level);
+        }
+    }
+}
+
+pub(crate) fn decode_text(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => GB18030.decode_without_bom_handling(bytes).0.into_owned(),
+    }
+}
+
+fn log_level(record: &BroadCastData) -> Option<LogLevel> {
+    match record.data_type(None)? {
+        BROADCAST_TYPE_LOG_MSG => Some(LogLevel::Log),
+        BROADCAST_TYPE_ERROR_MSG => Some(LogLevel::Error),
+        _ => None,
+    }
+}
+
+type Logs = Mutex<HashMap<u128, DeviceLog>>;
+
+// 一个上下文内各设备的日志，由 SayoContext 持有；释放时输出未完成的消息并结束日志流
+#[derive(Default)]
+pub(crate) struct DeviceLogs {
+    logs: Arc<Logs>,
+}
+
+impl DeviceLogs {
+    // 上下文对每个广播报告调用
+    pub(crate) fn ingest(&self, uuid: u128, records: &[(RecordContext, BroadCastData)]) {
+        let Some((first, _)) = records.first() else {
+            return;
+        };
+        let mut logs = lock(&self.logs);
+        let has_logs = records
+            .iter()
+            .any(|(_, record)| log_level(record).is_some());
+        if !has_logs && !logs.contains_key(&uuid) {
+            return;
+        }
+        let log = logs.entry(uuid).or_default();
+        log.expire(first.received_us);
+        for (i, (context, record)) in records.iter().enumerate() {
+            let Some(level) = log_level(record) else {
+                continue;
+            };
+            let Some(bytes) = record.data(None) else {
+                continue;
+            };
+            log.feed(level, context, &bytes, i + 1 == records.len());
+        }
+    }
+
+    // 设备断开：输出未完成的消息并结束订阅，历史保留以便查看断开前的日志
+    pub(crate) fn close(&self, uuid: u128) {
+        if let Some(log) = lock(&self.logs).get_mut(&uuid) {
+            log.close();
+        }
+    }
+
+    // 最近 LOG_HISTORY_SIZE 条日志，按时间顺序
+    pub(crate) fn history(&self, uuid: u128) -> Vec<LogEntry> {
+        let mut logs = lock(&self.logs);
+        let Some(log) = logs.get_mut(&uuid) else {
+            return Vec::new();
+        };
+        log.expire(now_micros());
+        log.history.iter().cloned().collect()
+    }
+
+    pub(crate) fn close_all(&self) {
+        for log in lock(&self.logs).values_mut() {
+            log.close();
+        }
+    }
+
+    pub(crate) fn clear(&self, uuid: u128) {
+        if let Some(log) = lock(&self.logs).get_mut(&uuid) {
+            log.history.clear();
+        }
+    }
+
+    // 订阅之后完成的日志；需要之前的内容先读 history
+    pub(crate) fn subscribe_logs(&self, uuid: u128, capacity: usize) -> LogStream {
+        let (sender, entries) = broadcast::channel(capacity);
+        lock(&self.logs)
+            .entry(uuid)
+            .or_default()
+            .senders
+            .push(sender);
+        LogStream {
+            uuid,
+            logs: Arc::downgrade(&self.logs),
+            entries,
+            timer: None,
+        }
+    }
+}
+
+impl Drop for DeviceLogs {
+    fn drop(&mut self) {
+        self.close_all();
+    }
+}
+
+// wasm 上的定时器不满足 Send
+#[cfg(not(target_arch = "wasm32"))]
+type FlushTimer = Pin<Box<dyn Future<Output = ()> + Send>>;
+#[cfg(target_arch = "wasm32")]
+type FlushTimer = Pin<Box<dyn Future<Output = ()>>>;
+
+// 日志流：有未完成的片段时挂一个定时器，超时后即使没有新广播也会输出
+pub struct LogStream {
+    uuid: u128,
+    logs: Weak<Logs>,
+    entries: BroadcastStream<LogEntry>,
+    timer: Option<FlushTimer>,
+}
+
+impl LogStream {
+    pub(crate) fn closed() -> Self {
+        LogStream {
+            uuid: 0,
+            logs: Weak::new(),
+            entries: BroadcastStream::closed(),
+            timer: None,
+        }
+    }
+
+    fn expire(&self) -> Option<u64> {
+        let logs = self.logs.upgrade()?;
+        let mut logs = lock(&logs);
+        logs.get_mut(&self.uuid)?.expire(now_micros())
+    }
+}
+
+impl Stream for LogStream {
+    type Item = Result<LogEntry, Lagged>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            let remaining_us = this.expire();
+            if let Poll::Ready(item) = Pin::new(&mut this.entries).poll_next(cx) {
+                this.timer = None;
+                return Poll::Ready(item);
+            }
+            let Some(remaining_us) = remaining_us else {
+                this.timer = None;
+                return Poll::Pending;
+            };
+            let timer = this
+                .timer
+                .get_or_insert_with(|| Box::pin(future_delay(remaining_us.div_ceil(1000) as u32)));
+            match timer.as_mut().poll(cx) {
+                Poll::Ready(()) => this.timer = None,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::byte_converter::RwBytes;
+    use crate::structures::BroadCast;
+    use futures::StreamExt;
+    use pollster::block_on;
+
+    fn record(data_type: u8, text: &[u8]) -> Vec<u8> {
+        // 长度字节包含自身
+        let mut bytes = vec![data_type, text.len() as u8 + 1];
+        bytes.extend_from_slice(text);
+        bytes
+    }
+
+    fn publish(device_logs: &DeviceLogs, uuid: u128, records: Vec<u8>) {
+        let broadcast = BroadCast {
+            bytes: RwBytes::new(records),
+        };
+        device_logs.ingest(uuid, &broadcast::records(&broadcast));
+    }
+
+    #[test]
+    fn test_log_reassembly() {
+        let uuid = 0x1E7E_0017;
+        let device_logs = DeviceLogs::default();
+        let mut logs = device_logs.subscribe_logs(uuid, 8);
+
+        // 同一报告中两条完整消息，最后一条被报告边界截断
+        let mut records = vec![0x80, 0x64, 0x00];
+        records.extend(record(BROADCAST_TYPE_LOG_MSG, b"boot ok\n"));
+        records.extend(record(BROADCAST_TYPE_ERROR_MSG, "电压低".as_bytes()));
+        records.extend(record(BROADCAST_TYPE_LOG_MSG, b"adc ch"));
+        publish(&device_logs, uuid, records);
+        let mut records = record(BROADCAST_TYPE_LOG_MSG, b"0=512\r\n\0\0");
+        // GB18030 编码的 "温度"
+        records.extend(record(
+            BROADCAST_TYPE_LOG_MSG,
+            &[0xCE, 0xC2, 0xB6, 0xC8, 0x00],
+        ));
+        publish(&device_logs, uuid, records);
+
+        let entries: Vec<LogEntry> = (0..4)
+            .map(|_| block_on(logs.next()).unwrap().unwrap())
+            .collect();
+        let texts: Vec<&str> = entries.iter().map(|entry| entry.text.as_str()).collect();
+        assert_eq!(texts, vec!["boot ok", "电压低", "adc ch0=512", "温度"]);
+        assert_eq!(entries[1].level, LogLevel::Error);
+        assert_eq!(entries[2].device_time_ms, Some(100));
+        assert_eq!(entries[0].to_string(), "[  100ms] LOG boot ok");
+        assert_eq!(device_logs.history(uuid), entries);
+
+        // 没有后续广播时，截断的片段在超时后由日志流的定时器输出
+        publish(
+            &device_logs,
+            uuid,
+            record(BROADCAST_TYPE_ERROR_MSG, b"stall"),
+        );
+        let entry = block_on(logs.next()).unwrap().unwrap();
+        assert_eq!(
+            (entry.level, entry.text.as_str()),
+            (LogLevel::Error, "stall")
+        );
+        assert_eq!(device_logs.history(uuid).len(), 5);
+
+        device_logs.close(uuid);
+        assert!(block_on(logs.next()).is_none());
+        assert_eq!(device_logs.history(uuid).len(), 5);
+        device_logs.clear(uuid);
+        assert!(device_logs.history(uuid).is_empty());
+    }
+}
@@ -0,0 +1,386 @@
+// 配置比较：两份配置统一用 ConfigArchive 表示（设备先 snapshot，文件用 read_from），
+// 每个条目按 dissector 解码成字段树后逐字段比较，得到带路径的变化列表。
+// 模拟按键的实时测量值不算变化；字段相同但字节不同的条目（脚本、图像数据）整体报告为 bytes 变化。
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::backup::{ConfigArchive, Section};
+use crate::byte_converter::RwBytes;
+use crate::dissector::{Dissect, Field, FieldValue, dissect_payload};
+use crate::report_codec::ResponseStatus;
+use crate::structures::{DisplayAssets, SayoScriptContent};
+use crate::structures_codec::CodecableHidPackage;
+
+// AnalogKeyInfo2 中随按键状态变化的字段
+const LIVE_FIELDS: [&str; 3] = ["raw_data", "raw_um", "zero_pos"];
+
+// 报告中超过该长度的字节数组只显示长度
+const MAX_BYTES_SHOWN: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathSegment {
+    Field(&'static str),
+    Index(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+// 一处变化；path 为空表示整个条目只在一侧存在，不存在的一侧为 FieldValue::Missing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub section: Section,
+    pub index: u8,
+    pub path: Vec<PathSegment>,
+    pub old: FieldValue,
+    pub new: FieldValue,
+}
+
+impl Change {
+    pub fn kind(&self) -> ChangeKind {
+        match (&self.old, &self.new) {
+            (FieldValue::Missing, _) => ChangeKind::Added,
+            (_, FieldValue::Missing) => ChangeKind::Removed,
+            _ => ChangeKind::Modified,
+        }
+    }
+
+    // 最内层的字段名
+    pub fn field(&self) -> Option<&'static str> {
+        self.path.iter().rev().find_map(|segment| match segment {
+            PathSegment::Field(name) => Some(*name),
+            PathSegment::Index(_) => None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigDiff {
+    // (old, new)
+    pub model_code: (u16, u16),
+    pub firmware_version: (u16, u16),
+    pub changes: Vec<Change>,
+}
+
+impl ConfigDiff {
+    // 只比较配置条目，不看型号和固件版本
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn section(&self, section: Section) -> impl Iterator<Item = &Change> + '_ {
+        self.changes
+            .iter()
+            .filter(move |change| change.section == section)
+    }
+}
+
+// 按条目类型解码；脚本和显示资源不是单个报告的负载，单独处理
+fn decode(section: Section, index: u8, data: &[u8]) -> (&'static str, Vec<Field>) {
+    let bytes = RwBytes::new(data.to_vec());
+    let (type_name, mut fields) = match section {
+        Section::Script => (
+            SayoScriptContent::TYPE_NAME,
+            SayoScriptContent::new(bytes).fields(),
+        ),
+        Section::DisplayAssets => (DisplayAssets::TYPE_NAME, DisplayAssets::new(bytes).fields()),
+        // 字符串条目以编码字节开头
+        _ if section.is_string() => match data.split_first() {
+            Some((&encoding, text)) => {
+                dissect_payload(ResponseStatus::from(encoding), section.cmd(), index, text)
+            }
+            None => dissect_payload(ResponseStatus::Utf16le, section.cmd(), index, data),
+        },
+        // 设备名按 UTF16LE 保存
+        _ => dissect_payload(ResponseStatus::Utf16le, section.cmd(), index, data),
+    };
+    if section == Section::AnalogKey {
+        fields.retain(|field| !LIVE_FIELDS.contains(&field.name));
+    }
+    (type_name, fields)
+}
+
+struct Differ {
+    section: Section,
+    index: u8,
+    path: Vec<PathSegment>,
+    changes: Vec<Change>,
+}
+
+impl Differ {
+    fn push(&mut self, old: &FieldValue, new: &FieldValue) {
+        self.changes.push(Change {
+            section: self.section,
+            index: self.index,
+            path: self.path.clone(),
+            old: old.clone(),
+            new: new.clone(),
+        });
+    }
+
+    fn value(&mut self, old: &FieldValue, new: &FieldValue) {
+        if old == new {
+            return;
+        }
+        match (old, new) {
+            (FieldValue::Record(old_type, old), FieldValue::Record(new_type, new))
+                if old_type == new_type =>
+            {
+                self.fields(old, new)
+            }
+            (FieldValue::List(old), FieldValue::List(new)) => {
+                for i in 0..old.len().max(new.len()) {
+                    self.path.push(PathSegment::Index(i));
+                    self.value(
+                        old.get(i).unwrap_or(&FieldValue::Missing),
+                        new.get(i).unwrap_or(&FieldValue::Missing),
+                    );
+                    self.path.pop();
+                }
+            }
+            _ => self.push(old, new),
+        }
+    }
+
+    // 同一类型的字段顺序相同，按名称配对以防一侧数据过短
+    fn fields(&mut self, old: &[Field], new: &[Field]) {
+        let find = |fields: &[Field], name| {
+            fields
+                .iter()
+                .find(|field| field.name == name)
+                .map_or(FieldValue::Missing, |field| field.value.clone())
+        };
+        let names = old
+            .iter()
+            .chain(
+                new.iter()
+                    .filter(|field| old.iter().all(|f| f.name != field.name)),
+            )
+            .map(|field| field.name);
+        for name in names {
+            self.path.push(PathSegment::Field(name));
+            self.value(&find(old, name), &find(new, name));
+            self.path.pop();
+        }
+    }
+}
+
+fn diff_entry(section: Section, index: u8, old: Option<&[u8]>, new: Option<&[u8]>) -> Vec<Change> {
+    let mut differ = Differ {
+        section,
+        index,
+        path: Vec::new(),
+        changes: Vec::new(),
+    };
+    let record = |data: &[u8]| {
+        let (type_name, fields) = decode(section, index, data);
+        FieldValue::Record(type_name, fields)
+    };
+    match (old, new) {
+        (Some(old), Some(new)) => {
+            if section.config_bytes(old) == section.config_bytes(new) {
+                return Vec::new();
+            }
+            differ.fields(
+                &decode(section, index, old).1,
+                &decode(section, index, new).1,
+            );
+            if differ.changes.is_empty() {
+                differ.path.push(PathSegment::Field("bytes"));
+                differ.push(
+                    &FieldValue::Bytes(old.to_vec()),
+                    &FieldValue::Bytes(new.to_vec()),
+                );
+            }
+        }
+        (Some(old), None) => differ.push(&record(old), &FieldValue::Missing),
+        (None, Some(new)) => differ.push(&FieldValue::Missing, &record(new)),
+        (None, None) => {}
+    }
+    differ.changes
+}
+
+// 按 Section::ALL 的顺序、同一部分内按 index 列出 old 到 new 的变化
+pub fn diff(old: &ConfigArchive, new: &ConfigArchive) -> ConfigDiff {
+    let mut changes = Vec::new();
+    for section in Section::ALL {
+        let mut entries = BTreeMap::new();
+        for entry in old.section(section) {
+            entries.entry(entry.index).or_insert((None, None)).0 = Some(entry.data.as_slice());
+        }
+        for entry in new.section(section) {
+            entries.entry(entry.index).or_insert((None, None)).1 = Some(entry.data.as_slice());
+        }
+        for (index, (old, new)) in entries {
+            changes.extend(diff_entry(section, index, old, new));
+        }
+    }
+    ConfigDiff {
+        model_code: (old.model_code, new.model_code),
+        firmware_version: (old.firmware_version, new.firmware_version),
+        changes,
+    }
+}
+
+// 只有一个条目的部分不显示 index
+fn is_single(section: Section) -> bool {
+    matches!(
+        section,
+        Section::DeviceName
+            | Section::DeviceConfig
+            | Section::RfConfig
+            | Section::LedEffect
+            | Section::GamePadCfg
+    )
+}
+
+fn format_value(value: &FieldValue) -> String {
+    match value {
+        FieldValue::Bytes(bytes) if bytes.len() > MAX_BYTES_SHOWN => {
+            format!("<{} 字节>", bytes.len())
+        }
+        FieldValue::Record(type_name, _) => type_name.to_string(),
+        FieldValue::List(items) => format!("<{} 项>", items.len()),
+        value => format!("{:?}", value),
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.section)?;
+        if !is_single(self.section) {
+            write!(f, " {}", self.index)?;
+        }
+        // 按键和灯的 fn 列表是各层的配置
+        let mut path = self.path.as_slice();
+        if let [
+            PathSegment::Field("key_fn" | "led_fn"),
+            PathSegment::Index(layer),
+            rest @ ..,
+        ] = path
+        {
+            write!(f, " layer {}", layer)?;
+            path = rest;
+        }
+        write!(f, ":")?;
+        for (i, segment) in path.iter().enumerate() {
+            match segment {
+                PathSegment::Field(name) if i == 0 => write!(f, " {}", name)?,
+                PathSegment::Field(name) => write!(f, ".{}", name)?,
+                PathSegment::Index(index) => write!(f, "[{}]", index)?,
+            }
+        }
+        match self.kind() {
+            ChangeKind::Added => write!(f, " 新增 {}", format_value(&self.new)),
+            ChangeKind::Removed => write!(f, " 删除 {}", format_value(&self.old)),
+            ChangeKind::Modified => write!(
+                f,
+                " {} → {}",
+                format_value(&self.old),
+                format_value(&self.new)
+            ),
+        }
+    }
+}
+
+// 每行一处变化，首先列出型号和固件版本的差异
+impl fmt::Display for ConfigDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (old, new) = self.model_code;
+        if old != new {
+            writeln!(f, "model_code {:#06X} → {:#06X}", old, new)?;
+        }
+        let (old, new) = self.firmware_version;
+        if old != new {
+            writeln!(f, "ver {} → {}", old, new)?;
+        }
+        if self.is_empty() {
+            return writeln!(f, "配置相同");
+        }
+        for change in &self.changes {
+            writeln!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backup::ArchiveEntry;
+    use crate::structures::{AnalogKeyInfo2, KeyInfo};
+
+    #[test]
+    fn test_config_diff() {
+        let key = KeyInfo::new(RwBytes::new((0..32).collect()));
+        key.key_fn().unwrap()[1].key_val(Some(vec![0x04, 0, 0, 0]));
+        let analog = AnalogKeyInfo2::new(RwBytes::new(vec![0; 104]));
+        analog.trigger_level(Some(1200));
+        let mut golden = ConfigArchive::new(0x0106, 130);
+        // GB18030 编码的 "宏"
+        let string = vec![0x02, 0xBA, 0xEA, 0x00, 0x00];
+        for (section, data) in [
+            (Section::KeyInfo, key.into_vec()),
+            (Section::AnalogKey, analog.into_vec()),
+            (Section::String, string),
+            (Section::Script, vec![0x11; 64]),
+        ] {
+            golden.entries.push(ArchiveEntry {
+                section,
+                index: 12,
+                data,
+            });
+        }
+        assert!(diff(&golden, &golden).is_empty());
+
+        let mut unit = golden.clone();
+        unit.firmware_version = 131;
+        let key = KeyInfo::new(RwBytes::new(unit.entries[0].data.clone()));
+        key.key_fn().unwrap()[1].key_val(Some(vec![0x05, 0, 0, 0]));
+        unit.entries[0].data = key.into_vec();
+        let analog = AnalogKeyInfo2::new(RwBytes::new(unit.entries[1].data.clone()));
+        // 实时测量值不算变化
+        analog.raw_data(Some(0x1234));
+        analog.trigger_level(Some(1500));
+        unit.entries[1].data = analog.into_vec();
+        // 同一文本改存为 UTF-16LE
+        unit.entries[2].data = vec![0x03, 0x8F, 0x5B, 0x00, 0x00];
+        unit.entries[3].data[10] = 0x22;
+        unit.entries.push(ArchiveEntry {
+            section: Section::KeyInfo,
+            index: 13,
+            data: vec![0; 32],
+        });
+
+        let diff = diff(&golden, &unit);
+        let changes = &diff.changes;
+        assert_eq!(changes.len(), 5);
+        assert_eq!(
+            changes[0].path,
+            vec![
+                PathSegment::Field("key_fn"),
+                PathSegment::Index(1),
+                PathSegment::Field("key_val")
+            ]
+        );
+        assert_eq!(changes[0].kind(), ChangeKind::Modified);
+        assert_eq!(changes[1].kind(), ChangeKind::Added);
+        assert_eq!(changes[2].field(), Some("trigger_level"));
+        // 文本相同，只有编码变化
+        assert_eq!(changes[3].field(), Some("encoding"));
+        assert_eq!(changes[4].field(), Some("bytes"));
+
+        let report = diff.to_string();
+        assert!(report.starts_with("ver 130 → 131\n"));
+        assert!(report.contains("key-info 12 layer 1: key_val [04 00 00 00] → [05 00 00 00]\n"));
+        assert!(report.contains("key-info 13: 新增 KeyInfo\n"));
+        assert!(report.contains("analog-key 12: trigger_level 1200 → 1500\n"));
+        assert!(report.contains("string 12: encoding 2 → 3\n"));
+        assert!(report.contains("script 12: bytes <64 字节> → <64 字节>\n"));
+    }
+}
@@ -0,0 +1,814 @@
+// 协议解析：按 cmd/index 找到对应的结构体，逐字段解码，输出文本或 JSON。
+// 抓包的可读视图和各结构体的 Debug 都基于这里。
+
+use std::fmt;
+
+use crate::byte_converter::RwBytes;
+use crate::json::JsonValue;
+use crate::report_codec::{ReportError, ResponseStatus};
+use crate::structures::*;
+use crate::structures_codec::{AddressableData, CodecableHidPackage};
+use crate::utility::{hex, parse_hex};
+
+const HEADER_SIZE: usize = 8;
+
+#[derive(Clone, PartialEq, Eq)]
+pub enum FieldValue {
+    Uint(u64),
+    Int(i64),
+    Bool(bool),
+    Bytes(Vec<u8>),
+    Text(String),
+    // 嵌套结构体，附带类型名
+    Record(&'static str, Vec<Field>),
+    List(Vec<FieldValue>),
+    // 数据长度不足，字段读不出来
+    Missing,
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct Field {
+    pub name: &'static str,
+    pub value: FieldValue,
+}
+
+impl Field {
+    pub fn new(name: &'static str, value: FieldValue) -> Self {
+        Self { name, value }
+    }
+}
+
+pub trait Dissect {
+    const TYPE_NAME: &'static str;
+
+    fn fields(&self) -> Vec<Field>;
+
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::Record(Self::TYPE_NAME, self.fields())
+    }
+
+    // 按字段名把 JSON 对象写回结构体，未出现或为 null 的字段保持不变
+    fn apply_json(&self, _value: &JsonValue) -> Result<(), String> {
+        Err(format!("{} is read-only", Self::TYPE_NAME))
+    }
+}
+
+pub trait ToFieldValue {
+    fn to_field_value(&self) -> FieldValue;
+}
+
+macro_rules! uint_field_value {
+    ($($ty:ty),*) => {
+        $(impl ToFieldValue for $ty {
+            fn to_field_value(&self) -> FieldValue {
+                FieldValue::Uint(*self as u64)
+            }
+        })*
+    };
+}
+uint_field_value!(u8, u16, u32, usize);
+
+impl ToFieldValue for i16 {
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::Int(*self as i64)
+    }
+}
+
+impl ToFieldValue for bool {
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::Bool(*self)
+    }
+}
+
+impl ToFieldValue for String {
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::Text(self.clone())
+    }
+}
+
+impl ToFieldValue for Vec<u8> {
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::Bytes(self.clone())
+    }
+}
+
+impl ToFieldValue for Vec<bool> {
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::List(self.iter().map(|v| v.to_field_value()).collect())
+    }
+}
+
+impl ToFieldValue for (u8, u8) {
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::List(vec![self.0.to_field_value(), self.1.to_field_value()])
+    }
+}
+
+impl ToFieldValue for Vec<(u8, u8)> {
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::List(self.iter().map(|v| v.to_field_value()).collect())
+    }
+}
+
+impl ToFieldValue for ResponseStatus {
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::Text(format!("{:#04X} {}", u8::from(*self), self))
+    }
+}
+
+impl<T: Dissect> ToFieldValue for Vec<T> {
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::List(self.iter().map(|v| v.to_field_value()).collect())
+    }
+}
+
+impl<T: ToFieldValue> ToFieldValue for Option<T> {
+    fn to_field_value(&self) -> FieldValue {
+        match self {
+            Some(value) => value.to_field_value(),
+            None => FieldValue::Missing,
+        }
+    }
+}
+
+// to_json 的逆操作：把 JSON 值写进字段的当前值
+pub trait ApplyJson {
+    fn apply_json(&mut self, value: &JsonValue) -> Result<(), String>;
+}
+
+fn expected(what: &str, value: &JsonValue) -> String {
+    format!("expected {}, got {}", what, value.type_name())
+}
+
+macro_rules! int_apply_json {
+    ($($ty:ty),*) => {
+        $(impl ApplyJson for $ty {
+            fn apply_json(&mut self, value: &JsonValue) -> Result<(), String> {
+                match value {
+                    JsonValue::Number(n) => {
+                        *self = <$ty>::try_from(*n)
+                            .map_err(|_| format!("{} out of range for {}", n, stringify!($ty)))?;
+                        Ok(())
+                    }
+                    _ => Err(expected("number", value)),
+                }
+            }
+        })*
+    };
+}
+int_apply_json!(u8, u16, u32, usize, i16);
+
+impl ApplyJson for bool {
+    fn apply_json(&mut self, value: &JsonValue) -> Result<(), String> {
+        match value {
+            JsonValue::Bool(b) => {
+                *self = *b;
+                Ok(())
+            }
+            _ => Err(expected("bool", value)),
+        }
+    }
+}
+
+impl ApplyJson for String {
+    fn apply_json(&mut self, value: &JsonValue) -> Result<(), String> {
+        match value {
+            JsonValue::String(text) => {
+                *self = text.clone();
+                Ok(())
+            }
+            _ => Err(expected("string", value)),
+        }
+    }
+}
+
+// 字节数组为 hex 字符串
+impl ApplyJson for Vec<u8> {
+    fn apply_json(&mut self, value: &JsonValue) -> Result<(), String> {
+        let JsonValue::String(hex) = value else {
+            return Err(expected("hex string", value));
+        };
+        if !hex.len().is_multiple_of(2) {
+            return Err(format!("odd hex length {}", hex.len()));
+        }
+        *self = parse_hex(hex).ok_or_else(|| format!("invalid hex string {:?}", hex))?;
+        Ok(())
+    }
+}
+
+fn apply_items<T: Default + ApplyJson>(items: &mut Vec<T>, value: &JsonValue) -> Result<(), String> {
+    let JsonValue::Array(values) = value else {
+        return Err(expected("array", value));
+    };
+    items.resize_with(values.len(), T::default);
+    for (i, (item, value)) in items.iter_mut().zip(values).enumerate() {
+        item.apply_json(value).map_err(|e| format!("[{}]: {}", i, e))?;
+    }
+    Ok(())
+}
+
+impl ApplyJson for Vec<bool> {
+    fn apply_json(&mut self, value: &JsonValue) -> Result<(), String> {
+        apply_items(self, value)
+    }
+}
+
+impl ApplyJson for (u8, u8) {
+    fn apply_json(&mut self, value: &JsonValue) -> Result<(), String> {
+        let mut pair = vec![self.0, self.1];
+        apply_items(&mut pair, value)?;
+        match pair[..] {
+            [a, b] => {
+                *self = (a, b);
+                Ok(())
+            }
+            _ => Err(format!("expected 2 items, got {}", pair.len())),
+        }
+    }
+}
+
+impl ApplyJson for Vec<(u8, u8)> {
+    fn apply_json(&mut self, value: &JsonValue) -> Result<(), String> {
+        apply_items(self, value)
+    }
+}
+
+// 输出为 "0x03 ..." 文本，也接受数字
+impl ApplyJson for ResponseStatus {
+    fn apply_json(&mut self, value: &JsonValue) -> Result<(), String> {
+        let mut byte = u8::from(*self);
+        match value {
+            JsonValue::String(text) => {
+                let code = text.split_whitespace().next().unwrap_or_default();
+                byte = u8::from_str_radix(code.trim_start_matches("0x"), 16)
+                    .map_err(|_| format!("invalid status {:?}", text))?;
+            }
+            _ => byte.apply_json(value)?,
+        }
+        *self = ResponseStatus::from(byte);
+        Ok(())
+    }
+}
+
+// 嵌套结构体按位置逐个写入，数量须与当前一致
+impl<T: Dissect> ApplyJson for Vec<T> {
+    fn apply_json(&mut self, value: &JsonValue) -> Result<(), String> {
+        let JsonValue::Array(values) = value else {
+            return Err(expected("array", value));
+        };
+        if values.len() != self.len() {
+            return Err(format!("expected {} items, got {}", self.len(), values.len()));
+        }
+        for (i, (item, value)) in self.iter().zip(values).enumerate() {
+            item.apply_json(value).map_err(|e| format!("[{}]: {}", i, e))?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: ApplyJson> ApplyJson for Option<T> {
+    fn apply_json(&mut self, value: &JsonValue) -> Result<(), String> {
+        match self {
+            Some(current) => current.apply_json(value),
+            None => Err("field not present in data".to_string()),
+        }
+    }
+}
+
+// 无参访问器：嵌套结构体视图与父结构体共享字节，可以原地写入；
+// 其余结果是副本，只接受与当前值相同的 JSON
+pub trait ApplyGetter {
+    fn apply_getter(self, value: &JsonValue) -> Result<(), String>;
+}
+
+impl<T: Dissect> ApplyGetter for Option<Vec<T>> {
+    fn apply_getter(mut self, value: &JsonValue) -> Result<(), String> {
+        self.apply_json(value)
+    }
+}
+
+fn read_only<T: ApplyJson + Clone + PartialEq>(current: T, value: &JsonValue) -> Result<(), String> {
+    let mut updated = current.clone();
+    updated.apply_json(value)?;
+    match updated == current {
+        true => Ok(()),
+        false => Err("read-only".to_string()),
+    }
+}
+
+impl ApplyGetter for Vec<u8> {
+    fn apply_getter(self, value: &JsonValue) -> Result<(), String> {
+        read_only(self, value)
+    }
+}
+
+impl ApplyGetter for usize {
+    fn apply_getter(self, value: &JsonValue) -> Result<(), String> {
+        read_only(self, value)
+    }
+}
+
+// 为结构体生成 Dissect 和 Debug：方括号内为 fn(&self, Option<T>) 形式的访问器，
+// 花括号内为无参访问器
+macro_rules! dissect {
+    ($ty:ident [$($field:ident),* $(,)?] $({$($getter:ident),* $(,)?})?) => {
+        impl Dissect for $ty {
+            const TYPE_NAME: &'static str = stringify!($ty);
+
+            fn fields(&self) -> Vec<Field> {
+                vec![
+                    $(Field::new(stringify!($field), self.$field(None).to_field_value()),)*
+                    $($(Field::new(stringify!($getter), self.$getter().to_field_value()),)*)?
+                ]
+            }
+
+            fn apply_json(&self, value: &JsonValue) -> Result<(), String> {
+                let JsonValue::Object(entries) = value else {
+                    return Err(format!("{}: {}", Self::TYPE_NAME, expected("object", value)));
+                };
+                for (name, value) in entries {
+                    if *value == JsonValue::Null {
+                        continue;
+                    }
+                    let res = match name.as_str() {
+                        $(stringify!($field) => {
+                            let mut current = self.$field(None);
+                            current.apply_json(value).map(|_| {
+                                self.$field(current);
+                            })
+                        })*
+                        $($(stringify!($getter) => self.$getter().apply_getter(value),)*)?
+                        _ => Err("unknown field".to_string()),
+                    };
+                    res.map_err(|e| format!("{}.{}: {}", Self::TYPE_NAME, name, e))?;
+                }
+                Ok(())
+            }
+        }
+
+        impl fmt::Debug for $ty {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt::Debug::fmt(&self.to_field_value(), f)
+            }
+        }
+    };
+}
+
+dissect!(HidReportHeader [report_id, echo, crc, len, status, cmd, index]);
+dissect!(ByteArray [data]);
+dissect!(StringContent [encoding, str]);
+dissect!(DeviceInfo [
+    model_code, ver, usb0_ori, usb0_offset, usb1_ori, usb1_offset, batt_lv, key_fn_num,
+    key_fn, cpu_load_1s, cpu_load_1ms, api_list,
+]);
+dissect!(SystemInfo [
+    lcd_width, lcd_height, lcd_refresh_rate, cfg_selection, sys_time_ms, sys_time_s, vid, pid,
+    cpu_load_1m, cpu_load_5m, cpu_freq, hclk_freq, pclk1_freq, pclk2_freq, adc0_freq, adc1_freq,
+]);
+dissect!(DeviceConfig [
+    display_width, display_height, dev_feature_selection_0, dev_feature_selection_0_selectable,
+    enc_channel, enc_channel_selectable, key_release_delay, key_release_delay_range, lcd_timeout,
+    lcd_timeout_range, hid_feature_selection_0, hid_feature_selection_0_selectable,
+    hid_feature_selection_1, hid_feature_selection_1_selectable, keyboard_layout,
+    keyboard_layout_select_range, keyboard_language, keyboard_language_select_range,
+    dev_feature_selection_1, dev_feature_selection_1_selectable, usb_speed,
+    usb_speed_select_range, key_press_delay, key_press_delay_range, display_width_negative,
+    display_height_negative, hk_multisampling, hk_multisampling_select_range, led_dimming_time,
+    led_dimming_time_range, led_turn_off_time, led_turn_off_time_range,
+]);
+dissect!(RFConfig [
+    rf_addr, rf_mode, rf_mode_select_range, rf_ch, rf_ch_range, rf_gap, rf_gap_range,
+    rf_time_out, rf_time_out_range, rf_sleep_time, rf_sleep_time_range, rf_led_time,
+    rf_led_time_range,
+]);
+dissect!(MonkeyGpios [] { gpios });
+dissect!(KeyData [key_mode, key_opt0, key_opt1, key_opt2, key_val]);
+dissect!(KeyInfo [
+    valid, key_class, reserve0, key_site_x, key_site_y, key_width, key_height, fillet_angle,
+    reserve1,
+] { key_fn });
+dissect!(LedData [
+    led_mode, color_mode, speed, event, lighting_time, dark_time, r, g, b, color_table_number,
+]);
+dissect!(LEDInfo [
+    valid, led_class, reserve0, led_site_x, led_site_y, led_width, led_height, fillet_angle,
+    reserve1,
+] { led_fn });
+dissect!(SayoColorData [r, g, b]);
+dissect!(ColorTable [number_of_colors, reserve0] { data });
+dissect!(TouchSensitivity [trigger_value, trigger_value_range, raw_data, zero_pos]);
+dissect!(AnalogKeyInfo [
+    raw_level, polar, trigger_level, release_level, rapid_trigger_top, rapid_trigger_area,
+    rapid_trigger_level, rapid_release_level, raw_data, zero_pos, raw_um, reserve, level_data,
+]);
+dissect!(SayoScriptContent [] { len });
+dissect!(SayoScriptPacket [address, data]);
+dissect!(AnalogKeyInfo2 [
+    raw_data, raw_um, zero_pos, max_value, polar, stroke, rt_mode, switch_type, trigger_level,
+    release_level, rapid_trigger_top, rapid_trigger_area, rapid_trigger_level,
+    rapid_release_level,
+]);
+dissect!(AdvancedKeyBinding [mode, bind_key, res0, res1, key_datas, func_opts]);
+dissect!(TriggerKeyboardHid [modifier_keys, reserve0, key_code]);
+dissect!(TriggerMouseHid [mouse_keys, x, y, scroll]);
+dissect!(TriggerMeidaHid [key_code]);
+// 图像数据可能很大，只列元信息
+dissect!(DisplayData [
+    data_type, frame_number, character_code, color_table_count, width, height, data_len,
+]);
+dissect!(DisplayAssets [] { datas });
+dissect!(DisplayAssetsPacket [address, data]);
+dissect!(LCDDrawData [
+    data_type, event_key_id, event_type, fn_mask, site_x, site_y, color, bg_color, reserve,
+    text,
+]);
+dissect!(ScreenBuffer [addr, data]);
+dissect!(LedEffect [
+    r, g, b, enabled, mode, sub_mode, speed, brightness, numlock_color, capslock_color,
+    scrolllock_color, socd_color, fn_diff_color, tap_color,
+]);
+dissect!(GamePadCfg [gamepad_type, options, res, points, maps]);
+dissect!(AmbientLED [
+    brightness, speed, led_count, reserve, mode, r, g, b, sub_mode, r1, g1, b1, res1, r2, g2,
+    b2, res2, led_map,
+]);
+dissect!(BroadCast [] { data });
+
+// BroadCastData 已有按类型名输出的 Debug，这里只实现 Dissect
+impl Dissect for BroadCastData {
+    const TYPE_NAME: &'static str = "BroadCastData";
+
+    fn fields(&self) -> Vec<Field> {
+        vec![
+            Field::new("data_type", self.data_type(None).to_field_value()),
+            Field::new("type_str", FieldValue::Text(self.type_str())),
+            Field::new("data", self.data(None).to_field_value()),
+        ]
+    }
+
+    fn apply_json(&self, value: &JsonValue) -> Result<(), String> {
+        let JsonValue::Object(entries) = value else {
+            return Err(format!("{}: {}", Self::TYPE_NAME, expected("object", value)));
+        };
+        for (name, value) in entries {
+            if *value == JsonValue::Null {
+                continue;
+            }
+            // data 的长度由 data_type 决定，JSON 中 data_type 在前
+            let res = match name.as_str() {
+                "data_type" => {
+                    let mut current = self.data_type(None);
+                    current.apply_json(value).map(|_| {
+                        self.data_type(current);
+                    })
+                }
+                "type_str" => read_only(self.type_str(), value),
+                "data" => {
+                    let mut current = self.data(None);
+                    current.apply_json(value).map(|_| {
+                        self.data(current);
+                    })
+                }
+                _ => Err("unknown field".to_string()),
+            };
+            res.map_err(|e| format!("{}.{}: {}", Self::TYPE_NAME, name, e))?;
+        }
+        Ok(())
+    }
+}
+
+impl MonkeyGpios {
+    fn gpios(&self) -> Vec<u8> {
+        self.bytes.clone().into_vec()
+    }
+}
+
+// 一个完整的报告：帧头 + 按 cmd/index 解码后的负载
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dissection {
+    pub report_id: u8,
+    pub echo: u8,
+    pub status: ResponseStatus,
+    pub cmd: u8,
+    pub index: u8,
+    pub type_name: &'static str,
+    pub fields: Vec<Field>,
+}
+
+fn decode<T: CodecableHidPackage + Dissect>(payload: &[u8]) -> (&'static str, Vec<Field>) {
+    (T::TYPE_NAME, T::new(RwBytes::new(payload.to_vec())).fields())
+}
+
+// 字符串类 cmd 的编码由帧头状态码给出
+fn decode_string(status: ResponseStatus, payload: &[u8]) -> (&'static str, Vec<Field>) {
+    let content = StringContent::new(RwBytes::new(payload.to_vec()));
+    if status.is_string_encoding() {
+        content.encoding_byte.set(Some(u8::from(status)));
+    }
+    (StringContent::TYPE_NAME, content.fields())
+}
+
+// 按 cmd/index 选择结构体解码负载，未知 cmd 按原始字节输出
+pub fn dissect_payload(
+    status: ResponseStatus,
+    cmd: u8,
+    index: u8,
+    payload: &[u8],
+) -> (&'static str, Vec<Field>) {
+    match cmd {
+        0x00 => decode::<DeviceInfo>(payload),
+        0x01 | 0x16 | 0x17 | 0x19 => decode_string(status, payload),
+        0x02 => decode::<SystemInfo>(payload),
+        0x03 => decode::<DeviceConfig>(payload),
+        0x04 => decode::<RFConfig>(payload),
+        0x07 => decode::<MonkeyGpios>(payload),
+        0x10 => decode::<KeyInfo>(payload),
+        0x11 => decode::<LEDInfo>(payload),
+        0x12 => decode::<ColorTable>(payload),
+        0x13 => decode::<TouchSensitivity>(payload),
+        0x14 => decode::<AnalogKeyInfo>(payload),
+        0x1A => decode::<SayoScriptPacket>(payload),
+        0x1C => decode::<AnalogKeyInfo2>(payload),
+        0x1D => decode::<AdvancedKeyBinding>(payload),
+        0x1F => match index {
+            0x00 => decode::<TriggerKeyboardHid>(payload),
+            0x01 => decode::<TriggerMouseHid>(payload),
+            0x02 => decode::<TriggerMeidaHid>(payload),
+            _ => decode::<ByteArray>(payload),
+        },
+        0x20 => decode::<DisplayAssetsPacket>(payload),
+        // 屏幕图层
+        0x21..=0x23 => decode::<LCDDrawData>(payload),
+        0x25 => decode::<ScreenBuffer>(payload),
+        0x26 => decode::<LedEffect>(payload),
+        0x28 => decode::<GamePadCfg>(payload),
+        0x2A => decode::<AmbientLED>(payload),
+        0xFF => decode::<BroadCast>(payload),
+        _ => decode::<ByteArray>(payload),
+    }
+}
+
+// 解析已拼包的报告（帧头 + 完整负载）
+pub fn dissect_package(header: &HidReportHeader, payload: &[u8]) -> Result<Dissection, ReportError> {
+    let report_id = header.report_id(None).ok_or(ReportError::BadReportHeader)?;
+    let echo = header.echo(None).ok_or(ReportError::BadReportHeader)?;
+    let status = header.status(None).ok_or(ReportError::BadReportHeader)?;
+    let cmd = header.cmd(None).ok_or(ReportError::BadReportHeader)?;
+    let index = header.index(None).ok_or(ReportError::BadReportHeader)?;
+    // 读请求不带负载，不解码以免全部字段显示为缺失
+    let (type_name, fields) = match payload.is_empty() {
+        true => (dissect_payload(status, cmd, index, payload).0, Vec::new()),
+        false => dissect_payload(status, cmd, index, payload),
+    };
+    Ok(Dissection {
+        report_id,
+        echo,
+        status,
+        cmd,
+        index,
+        type_name,
+        fields,
+    })
+}
+
+// 解析单个原始报告，负载按帧头 len 截取；多包报告的单帧只解码本帧部分
+pub fn dissect_report(raw: &[u8]) -> Result<Dissection, ReportError> {
+    if raw.len() < HEADER_SIZE {
+        return Err(ReportError::BadHeaderLength(raw.len()));
+    }
+    let header = HidReportHeader::new(RwBytes::new(raw[..HEADER_SIZE].to_vec()));
+    let len = header.len(None).ok_or(ReportError::BadReportHeader)? as usize;
+    if len < 4 || len + 4 > raw.len() {
+        return Err(ReportError::BadReportLength(raw.len()));
+    }
+    dissect_package(&header, &raw[HEADER_SIZE..len + 4])
+}
+
+impl fmt::Debug for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldValue::Uint(v) => write!(f, "{}", v),
+            FieldValue::Int(v) => write!(f, "{}", v),
+            FieldValue::Bool(v) => write!(f, "{}", v),
+            FieldValue::Bytes(v) => write!(f, "[{}]", hex(v, " ").to_uppercase()),
+            FieldValue::Text(v) => write!(f, "{:?}", v),
+            FieldValue::Record(name, fields) => {
+                let mut s = f.debug_struct(name);
+                for field in fields {
+                    s.field(field.name, &field.value);
+                }
+                s.finish()
+            }
+            FieldValue::List(items) => f.debug_list().entries(items).finish(),
+            FieldValue::Missing => write!(f, "-"),
+        }
+    }
+}
+
+impl fmt::Debug for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {:?}", self.name, self.value)
+    }
+}
+
+fn json_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl FieldValue {
+    // 字节数组输出为小写 hex 字符串，缺失字段为 null
+    pub fn to_json(&self) -> String {
+        match self {
+            FieldValue::Uint(v) => v.to_string(),
+            FieldValue::Int(v) => v.to_string(),
+            FieldValue::Bool(v) => v.to_string(),
+            FieldValue::Bytes(v) => json_escape(&hex(v, "")),
+            FieldValue::Text(v) => json_escape(v),
+            FieldValue::Record(_, fields) => fields_to_json(fields),
+            FieldValue::List(items) => format!(
+                "[{}]",
+                items
+                    .iter()
+                    .map(|item| item.to_json())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+            FieldValue::Missing => "null".to_string(),
+        }
+    }
+
+    // 与 to_json 结构相同，直接构造 JsonValue；超出 i64 的无符号数按字符串输出
+    pub fn to_json_value(&self) -> JsonValue {
+        match self {
+            FieldValue::Uint(v) => i64::try_from(*v)
+                .map_or_else(|_| JsonValue::String(v.to_string()), JsonValue::Number),
+            FieldValue::Int(v) => JsonValue::Number(*v),
+            FieldValue::Bool(v) => JsonValue::Bool(*v),
+            FieldValue::Bytes(v) => JsonValue::String(hex(v, "")),
+            FieldValue::Text(v) => JsonValue::String(v.clone()),
+            FieldValue::Record(_, fields) => JsonValue::Object(
+                fields
+                    .iter()
+                    .map(|field| (field.name.to_string(), field.value.to_json_value()))
+                    .collect(),
+            ),
+            FieldValue::List(items) => {
+                JsonValue::Array(items.iter().map(FieldValue::to_json_value).collect())
+            }
+            FieldValue::Missing => JsonValue::Null,
+        }
+    }
+
+    fn write_text(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        match self {
+            FieldValue::Record(name, fields) => {
+                write!(f, "{}", name)?;
+                for field in fields {
+                    write!(f, "\n{:indent$}{}: ", "", field.name, indent = depth * 2)?;
+                    field.value.write_text(f, depth + 1)?;
+                }
+                Ok(())
+            }
+            FieldValue::List(items) if items.iter().any(|item| matches!(item, FieldValue::Record(..))) => {
+                for (i, item) in items.iter().enumerate() {
+                    write!(f, "\n{:indent$}[{}] ", "", i, indent = depth * 2)?;
+                    item.write_text(f, depth + 1)?;
+                }
+                Ok(())
+            }
+            value => write!(f, "{:?}", value),
+        }
+    }
+}
+
+fn fields_to_json(fields: &[Field]) -> String {
+    format!(
+        "{{{}}}",
+        fields
+            .iter()
+            .map(|field| format!("{}:{}", json_escape(field.name), field.value.to_json()))
+            .collect::<Vec<_>>()
+            .join(",")
+    )
+}
+
+impl Dissection {
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"report_id\":{},\"echo\":{},\"status\":{},\"cmd\":{},\"index\":{},\"type\":{},\"fields\":{}}}",
+            self.report_id,
+            self.echo,
+            u8::from(self.status),
+            self.cmd,
+            self.index,
+            json_escape(self.type_name),
+            fields_to_json(&self.fields)
+        )
+    }
+}
+
+// 文本视图：首行为帧头摘要，其后每行一个字段，嵌套结构体缩进
+impl fmt::Display for Dissection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "report {:#04X} echo {:#04X} cmd {:#04X} index {:#04X} status {:#04X} ({}) {}",
+            self.report_id,
+            self.echo,
+            self.cmd,
+            self.index,
+            u8::from(self.status),
+            self.status,
+            self.type_name
+        )?;
+        for field in &self.fields {
+            write!(f, "\n  {}: ", field.name)?;
+            field.value.write_text(f, 2)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report_codec::encode_frames;
+
+    #[test]
+    fn test_dissect_device_info_report() {
+        let mut body = vec![0u8; 20];
+        body[0..2].copy_from_slice(&0x0106u16.to_le_bytes());
+        body[2..4].copy_from_slice(&130u16.to_le_bytes());
+        body[12] = 0x1C;
+        let frames = encode_frames(0x22, 0x13, 0x00, 0x00, ResponseStatus::End, &body).unwrap();
+        let dissection = dissect_report(&frames[0]).unwrap();
+
+        assert_eq!(dissection.type_name, "DeviceInfo");
+        assert_eq!(dissection.cmd, 0x00);
+        assert_eq!(dissection.fields[0], Field::new("model_code", FieldValue::Uint(0x0106)));
+        assert_eq!(dissection.fields[1], Field::new("ver", FieldValue::Uint(130)));
+        assert!(dissection.to_string().contains("model_code: 262"));
+        assert!(dissection.to_json().contains("\"ver\":130"));
+        assert!(dissection.to_json().contains("\"api_list\":\"1c"));
+    }
+
+    #[test]
+    fn test_dissect_broadcast_and_string() {
+        // 两条广播：0x10 按键按下（2 字节），0x80 系统时间（3 字节），0x00 结束
+        let body = vec![0x10, 0x05, 0x80, 0x34, 0x12, 0x00];
+        let (type_name, fields) = dissect_payload(ResponseStatus::End, 0xFF, 0, &body);
+        assert_eq!(type_name, "BroadCast");
+        let FieldValue::List(records) = &fields[0].value else {
+            panic!("broadcast records should be a list");
+        };
+        assert_eq!(records.len(), 2);
+        let FieldValue::Record(_, first) = &records[0] else {
+            panic!("broadcast record should be a record");
+        };
+        assert_eq!(first[1].value, FieldValue::Text("BRD_TYPE_KB_KEY_PRESS".to_string()));
+        assert_eq!(first[2].value, FieldValue::Bytes(vec![0x05]));
+
+        let text: Vec<u8> = "Sayo\0"
+            .encode_utf16()
+            .flat_map(|c| c.to_le_bytes())
+            .collect();
+        let (_, fields) = dissect_payload(ResponseStatus::Utf16le, 0x17, 0, &text);
+        assert_eq!(fields[1].value, FieldValue::Text("Sayo".to_string()));
+    }
+
+    #[test]
+    fn test_apply_json_round_trip() {
+        let bytes: Vec<u8> = (0..32).collect();
+        let key_info = KeyInfo::new(RwBytes::new(bytes.clone()));
+        let json = crate::json::parse(&key_info.to_field_value().to_json()).unwrap();
+        assert_eq!(key_info.to_field_value().to_json_value(), json);
+
+        // 写回导出的 JSON 得到相同的字节，包括嵌套的 key_fn
+        let copy = KeyInfo::new(RwBytes::new(vec![0; 32]));
+        copy.apply_json(&json).unwrap();
+        assert_eq!(copy.into_vec(), bytes);
+
+        let patch = crate::json::parse(r#"{"key_site_x": 300, "key_fn": [{}, {"key_val": "04000000"}]}"#).unwrap();
+        copy.apply_json(&patch).unwrap();
+        assert_eq!(copy.key_site_x(None), Some(300));
+        assert_eq!(copy.key_fn().unwrap()[1].key_val(None), Some(vec![4, 0, 0, 0]));
+
+        let err = copy.apply_json(&crate::json::parse(r#"{"valid": 256}"#).unwrap()).unwrap_err();
+        assert!(err.starts_with("KeyInfo.valid"));
+        assert!(copy.apply_json(&crate::json::parse(r#"{"nope": 1}"#).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,184 @@
+// 应答按 echo 路由：设备把请求的 echo 原样带回，每个进程的解码器只接收本进程在用的 echo，
+// 其余应答完成拼包后丢弃。echo 0x00 保留给广播。
+//
+// 多个主机客户端共用一个设备时，各自的请求必须使用不同的 echo，否则会互相抢走应答：
+// - 同一进程内的独立组件各用 SayoDeviceApi::session() 取得独占 echo 的句柄，
+//   echo 从句柄所属上下文的池中分配，句柄全部释放后归还；
+// - 多个进程或多个连接同一设备的上下文之间不共享池，需各自配置不相交的池范围
+//   （SayoContext::set_echo_pool_range），上下文的默认 echo 也改到各自范围内
+//   （SayoContext::set_default_echo），单个句柄可用 RequestOptions::echo 指定，命令行工具用 -e；
+// - 与官方上位机共存时，先用 capture 抓包确认它使用的 echo（通常与 SayoDeviceApi::ECHO
+//   相同），把它从池中 reserve 掉，并把上下文的默认 echo 换成其他值。
+// 模块级的 set_pool_range、reserve、set_default_echo 作用于默认上下文 SayoContext::global()。
+
+use std::collections::BTreeSet;
+use std::ops::RangeInclusive;
+use std::sync::{Arc, Mutex, Weak};
+
+use crate::context::SayoContext;
+use crate::device::SayoDeviceApi;
+use crate::device_error_handling::{DeviceError, DeviceResult};
+use crate::utility::lock;
+
+pub const BROADCAST_ECHO: u8 = 0x00;
+pub const DEFAULT_ECHO_POOL: RangeInclusive<u8> = 0x40..=0x7F;
+
+#[derive(Debug, Clone)]
+pub struct EchoPool {
+    range: RangeInclusive<u8>,
+    reserved: BTreeSet<u8>,
+    leased: BTreeSet<u8>,
+    // 未指定 echo 的请求使用的值，不参与分配
+    default_echo: u8,
+    // 轮转分配，刚归还的 echo 不会马上再分出去，避免超时请求的迟到应答被新会话收到
+    next: u8,
+}
+
+impl EchoPool {
+    pub fn new(range: RangeInclusive<u8>) -> Self {
+        EchoPool {
+            next: *range.start(),
+            range,
+            reserved: BTreeSet::new(),
+            leased: BTreeSet::new(),
+            default_echo: SayoDeviceApi::ECHO,
+        }
+    }
+
+    pub fn default_echo(&self) -> u8 {
+        self.default_echo
+    }
+
+    pub fn set_default_echo(&mut self, echo: u8) {
+        self.default_echo = echo;
+    }
+
+    // 已分配的 echo 不受影响，之后按新范围分配
+    pub fn set_range(&mut self, range: RangeInclusive<u8>) {
+        self.next = *range.start();
+        self.range = range;
+    }
+
+    pub fn range(&self) -> &RangeInclusive<u8> {
+        &self.range
+    }
+
+    // 排除其他客户端使用的 echo
+    pub fn reserve(&mut self, echo: u8) {
+        self.reserved.insert(echo);
+    }
+
+    pub fn acquire(&mut self) -> Option<u8> {
+        let (start, end) = (*self.range.start(), *self.range.end());
+        if start > end {
+            return None;
+        }
+        let len = end as usize - start as usize + 1;
+        let offset = (self.next.max(start) as usize - start as usize) % len;
+        let echo = (0..len)
+            .map(|i| (start as usize + (offset + i) % len) as u8)
+            .find(|echo| {
+                *echo != BROADCAST_ECHO
+                    && *echo != self.default_echo
+                    && !self.reserved.contains(echo)
+                    && !self.leased.contains(echo)
+            })?;
+        self.leased.insert(echo);
+        self.next = echo.wrapping_add(1);
+        Some(echo)
+    }
+
+    pub fn release(&mut self, echo: u8) {
+        self.leased.remove(&echo);
+    }
+
+    pub fn is_leased(&self, echo: u8) -> bool {
+        self.leased.contains(&echo)
+    }
+
+    pub fn leased(&self) -> usize {
+        self.leased.len()
+    }
+}
+
+// 默认上下文的默认 echo，其他上下文见 SayoContext::default_echo
+pub fn default_echo() -> u8 {
+    SayoContext::global().default_echo()
+}
+
+pub fn set_default_echo(echo: u8) -> DeviceResult<()> {
+    SayoContext::global().set_default_echo(echo)
+}
+
+// 默认上下文的池，其他上下文见 SayoContext::set_echo_pool_range
+pub fn set_pool_range(range: RangeInclusive<u8>) {
+    SayoContext::global().set_echo_pool_range(range);
+}
+
+pub fn reserve(echo: u8) {
+    SayoContext::global().reserve_echo(echo);
+}
+
+// 上下文是否在使用该 echo，解码器据此决定是否分发应答
+pub(crate) fn is_local(pool: &Mutex<EchoPool>, echo: u8) -> bool {
+    let pool = lock(pool);
+    echo == pool.default_echo || pool.is_leased(echo)
+}
+
+// 独占的 echo，释放时归还到分配它的池中
+#[derive(Debug)]
+pub struct EchoLease {
+    echo: u8,
+    pool: Weak<Mutex<EchoPool>>,
+}
+
+impl EchoLease {
+    pub fn echo(&self) -> u8 {
+        self.echo
+    }
+}
+
+impl Drop for EchoLease {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.upgrade() {
+            lock(&pool).release(self.echo);
+        }
+    }
+}
+
+pub(crate) fn lease(pool: &Arc<Mutex<EchoPool>>) -> DeviceResult<EchoLease> {
+    let echo = lock(pool).acquire().ok_or(DeviceError::EchoExhausted)?;
+    Ok(EchoLease {
+        echo,
+        pool: Arc::downgrade(pool),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_echo_pool() {
+        let mut pool = EchoPool::new(0x20..=0x23);
+        pool.reserve(0x21);
+        assert_eq!(pool.acquire(), Some(0x20));
+        assert_eq!(pool.acquire(), Some(0x22));
+        pool.release(0x20);
+        // 轮转：刚归还的 0x20 排在最后
+        assert_eq!(pool.acquire(), Some(0x23));
+        assert_eq!(pool.acquire(), Some(0x20));
+        assert_eq!(pool.acquire(), None);
+        assert_eq!(pool.leased(), 3);
+
+        let mut pool = EchoPool::new(0x00..=0x01);
+        assert_eq!(pool.acquire(), Some(0x01));
+        assert_eq!(pool.acquire(), None);
+
+        // 默认 echo 不参与分配
+        let mut pool = EchoPool::new(0x20..=0x21);
+        pool.set_default_echo(0x20);
+        assert_eq!(pool.acquire(), Some(0x21));
+        assert_eq!(pool.acquire(), None);
+    }
+}
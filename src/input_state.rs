@@ -0,0 +1,289 @@
+// 由广播重建设备当前的输入状态，屏幕上的键盘/手柄测试页直接读取，
+// 不需要操作系统的输入钩子。设备只广播变化，状态从订阅开始累计，
+// 订阅前已按下的键在松开前不会出现。
+
+use std::collections::BTreeSet;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::Stream;
+
+use crate::broadcast::{BroadcastEvent, BroadcastStream, Lagged};
+
+// 0-7 之外的 hat 值表示回中
+const HAT_DIRECTIONS: u8 = 8;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InputState {
+    // 物理按键序号
+    pub pressed_keys: BTreeSet<u8>,
+    // 标准键盘键码（HID usage）
+    pub standard_keys: BTreeSet<u8>,
+    pub general_keys: BTreeSet<u8>,
+    pub joystick_buttons: BTreeSet<u8>,
+    // None 为回中，否则 0-7 从上开始顺时针
+    pub joystick_hat: Option<u8>,
+    pub joystick_axes: (u16, u16),
+    // 当前按下的多媒体键 usage，0 为无
+    pub media: u16,
+    pub mouse_buttons: u8,
+    // 自上次 take_mouse_delta 以来的累计位移
+    pub mouse_delta: MouseDelta,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MouseDelta {
+    pub dx: i32,
+    pub dy: i32,
+    pub wheel: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputChange {
+    Key { key: u8, pressed: bool },
+    StandardKey { code: u8, pressed: bool },
+    GeneralKey { code: u8, pressed: bool },
+    JoystickButton { button: u8, pressed: bool },
+    JoystickHat(Option<u8>),
+    JoystickAxes { x: u16, y: u16 },
+    Media(u16),
+    MouseButtons(u8),
+    MouseMove { dx: i8, dy: i8, wheel: i8 },
+}
+
+fn update_set(set: &mut BTreeSet<u8>, value: u8, pressed: bool) -> bool {
+    match pressed {
+        true => set.insert(value),
+        false => set.remove(&value),
+    }
+}
+
+impl InputState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 应用一条广播，返回实际发生的变化；重复的按下/松开不产生变化
+    pub fn apply(&mut self, event: &BroadcastEvent) -> Vec<InputChange> {
+        let mut changes = Vec::new();
+        match *event {
+            BroadcastEvent::KeyPress { key } | BroadcastEvent::KeyRelease { key } => {
+                let pressed = matches!(event, BroadcastEvent::KeyPress { .. });
+                if update_set(&mut self.pressed_keys, key, pressed) {
+                    changes.push(InputChange::Key { key, pressed });
+                }
+            }
+            BroadcastEvent::StandardKeyAdd(code) | BroadcastEvent::StandardKeyDel(code) => {
+                let pressed = matches!(event, BroadcastEvent::StandardKeyAdd(_));
+                if update_set(&mut self.standard_keys, code, pressed) {
+                    changes.push(InputChange::StandardKey { code, pressed });
+                }
+            }
+            BroadcastEvent::GeneralKeyAdd(code) | BroadcastEvent::GeneralKeyDel(code) => {
+                let pressed = matches!(event, BroadcastEvent::GeneralKeyAdd(_));
+                if update_set(&mut self.general_keys, code, pressed) {
+                    changes.push(InputChange::GeneralKey { code, pressed });
+                }
+            }
+            BroadcastEvent::JoystickButtonAdd(button)
+            | BroadcastEvent::JoystickButtonDel(button) => {
+                let pressed = matches!(event, BroadcastEvent::JoystickButtonAdd(_));
+                if update_set(&mut self.joystick_buttons, button, pressed) {
+                    changes.push(InputChange::JoystickButton { button, pressed });
+                }
+            }
+            BroadcastEvent::JoystickHat(hat) => {
+                let hat = (hat < HAT_DIRECTIONS).then_some(hat);
+                if self.joystick_hat != hat {
+                    self.joystick_hat = hat;
+                    changes.push(InputChange::JoystickHat(hat));
+                }
+            }
+            BroadcastEvent::Point { x, y } if self.joystick_axes != (x, y) => {
+                self.joystick_axes = (x, y);
+                changes.push(InputChange::JoystickAxes { x, y });
+            }
+            BroadcastEvent::Media(usage) if self.media != usage => {
+                self.media = usage;
+                changes.push(InputChange::Media(usage));
+            }
+            BroadcastEvent::Mouse {
+                buttons,
+                dx,
+                dy,
+                wheel,
+            } => {
+                if self.mouse_buttons != buttons {
+                    self.mouse_buttons = buttons;
+                    changes.push(InputChange::MouseButtons(buttons));
+                }
+                if (dx, dy, wheel) != (0, 0, 0) {
+                    self.mouse_delta.dx += dx as i32;
+                    self.mouse_delta.dy += dy as i32;
+                    self.mouse_delta.wheel += wheel as i32;
+                    changes.push(InputChange::MouseMove { dx, dy, wheel });
+                }
+            }
+            _ => {}
+        }
+        changes
+    }
+
+    // 取出并清零累计的鼠标位移，适合按帧刷新的界面
+    pub fn take_mouse_delta(&mut self) -> MouseDelta {
+        std::mem::take(&mut self.mouse_delta)
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.pressed_keys.is_empty()
+            && self.standard_keys.is_empty()
+            && self.general_keys.is_empty()
+            && self.joystick_buttons.is_empty()
+            && self.joystick_hat.is_none()
+            && self.media == 0
+            && self.mouse_buttons == 0
+    }
+}
+
+// 一条广播引起的变化及变化后的状态
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputUpdate {
+    pub changes: Vec<InputChange>,
+    pub state: InputState,
+}
+
+// 只在状态变化时产出；Lagged 之后丢失的松开事件可能让按键残留，
+// 调用方可以 reset 后重新累计
+pub struct InputStream {
+    events: BroadcastStream<BroadcastEvent>,
+    state: InputState,
+}
+
+impl InputStream {
+    pub(crate) fn new(events: BroadcastStream<BroadcastEvent>) -> Self {
+        InputStream {
+            events,
+            state: InputState::default(),
+        }
+    }
+
+    pub fn state(&self) -> &InputState {
+        &self.state
+    }
+
+    pub fn reset(&mut self) {
+        self.state = InputState::default();
+    }
+}
+
+impl Stream for InputStream {
+    type Item = Result<InputUpdate, Lagged>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            let event = match Pin::new(&mut this.events).poll_next(cx) {
+                Poll::Ready(Some(Ok(event))) => event,
+                Poll::Ready(Some(Err(lagged))) => return Poll::Ready(Some(Err(lagged))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+            let changes = this.state.apply(&event);
+            if !changes.is_empty() {
+                return Poll::Ready(Some(Ok(InputUpdate {
+                    changes,
+                    state: this.state.clone(),
+                })));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broadcast::Broadcasts;
+    use crate::byte_converter::RwBytes;
+    use crate::device_constants::*;
+    use crate::structures::BroadCast;
+    use futures::StreamExt;
+    use pollster::block_on;
+
+    #[test]
+    fn test_input_stream() {
+        let uuid = 0x1E7E_0016;
+        let broadcasts = Broadcasts::default();
+        let mut input = InputStream::new(broadcasts.subscribe_events(uuid, 16));
+
+        let mut records = Vec::new();
+        records.extend_from_slice(&[BROADCAST_TYPE_SK_ADD, 0x04, BROADCAST_TYPE_SK_ADD, 0x04]);
+        records.extend_from_slice(&[
+            BROADCAST_TYPE_JOYSTICK_ADD,
+            3,
+            BROADCAST_TYPE_JOYSTICK_HAT,
+            2,
+        ]);
+        records.extend_from_slice(&[BROADCAST_TYPE_MO_DATA, 0x01, 0xFE, 0x05, 0x00]);
+        records.extend_from_slice(&[BROADCAST_TYPE_POINT, 0x00, 0x80, 0xFF, 0x7F]);
+        records.extend_from_slice(&[
+            BROADCAST_TYPE_JOYSTICK_HAT,
+            0x0F,
+            BROADCAST_TYPE_SK_DEL,
+            0x04,
+        ]);
+        let broadcast = BroadCast {
+            bytes: RwBytes::new(records),
+        };
+        broadcasts.publish(uuid, &crate::broadcast::records(&broadcast));
+
+        let mut updates = Vec::new();
+        for _ in 0..7 {
+            updates.push(block_on(input.next()).unwrap().unwrap());
+        }
+        // 重复的 SK_ADD 不产生变化
+        assert_eq!(
+            updates[0].changes,
+            vec![InputChange::StandardKey {
+                code: 0x04,
+                pressed: true
+            }]
+        );
+        assert_eq!(
+            updates[1].changes,
+            vec![InputChange::JoystickButton {
+                button: 3,
+                pressed: true
+            }]
+        );
+        assert_eq!(updates[2].state.joystick_hat, Some(2));
+        assert_eq!(
+            updates[3].changes,
+            vec![
+                InputChange::MouseButtons(1),
+                InputChange::MouseMove {
+                    dx: -2,
+                    dy: 5,
+                    wheel: 0
+                },
+            ]
+        );
+        assert_eq!(updates[4].state.joystick_axes, (0x8000, 0x7FFF));
+        assert_eq!(updates[5].changes, vec![InputChange::JoystickHat(None)]);
+        assert!(updates[6].state.standard_keys.is_empty());
+
+        let mut state = input.state().clone();
+        assert_eq!(
+            state.take_mouse_delta(),
+            MouseDelta {
+                dx: -2,
+                dy: 5,
+                wheel: 0
+            }
+        );
+        assert_eq!(state.mouse_delta, MouseDelta::default());
+        assert!(!state.is_idle());
+
+        broadcasts.close(uuid);
+        assert!(block_on(input.next()).is_none());
+    }
+}
@@ -0,0 +1,121 @@
+// 0xE1 KEY_PRESS_LEN_UM 行程广播：长度字节为按键数（常见 34/35 键），
+// 其后每键一个 u16 LE，低 14 位是行程（微米），高 2 位为标志位。
+
+use crate::broadcast::{BroadcastStream, Broadcasts, RecordContext};
+use crate::device_constants::*;
+use crate::structures::BroadCastData;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyTravel {
+    // 在广播中的位置，即按键序号
+    pub key: u8,
+    pub travel_um: u16,
+    // 原始值的高 2 位
+    pub flags: u8,
+}
+
+impl KeyTravel {
+    // 小于 LEVEL_THRESHOLD 视为静止位置的噪声
+    pub fn is_active(&self) -> bool {
+        self.travel_um >= LEVEL_THRESHOLD
+    }
+}
+
+pub fn decode_levels(bytes: &[u8]) -> Vec<KeyTravel> {
+    bytes
+        .chunks_exact(2)
+        .enumerate()
+        .map(|(key, raw)| {
+            let raw = u16::from_le_bytes([raw[0], raw[1]]);
+            KeyTravel {
+                key: key as u8,
+                travel_um: raw & LEVEL_MASK,
+                flags: (raw >> 14) as u8,
+            }
+        })
+        .collect()
+}
+
+// 一次行程广播
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LevelsFrame {
+    // 主机收到报告的时间
+    pub received_us: u64,
+    // 同一报告中 SYS_TIME_MS 记录给出的设备时间
+    pub device_time_ms: Option<u16>,
+    pub keys: Vec<KeyTravel>,
+}
+
+impl LevelsFrame {
+    pub fn travel_um(&self, key: u8) -> Option<u16> {
+        self.keys.get(key as usize).map(|travel| travel.travel_um)
+    }
+
+    pub fn active_keys(&self) -> impl Iterator<Item = &KeyTravel> {
+        self.keys.iter().filter(|travel| travel.is_active())
+    }
+}
+
+fn levels_frame(context: &RecordContext, data: &BroadCastData) -> Option<LevelsFrame> {
+    if data.data_type(None)? != BROADCAST_TYPE_LEVELS {
+        return None;
+    }
+    Some(LevelsFrame {
+        received_us: context.received_us,
+        device_time_ms: context.device_time_ms,
+        keys: decode_levels(&data.data(None)?),
+    })
+}
+
+// 只包含行程广播的流，默认缓存 LEVELS_BUFFER_SIZE 帧
+pub(crate) fn subscribe_levels(
+    broadcasts: &Broadcasts,
+    uuid: u128,
+    capacity: usize,
+) -> BroadcastStream<LevelsFrame> {
+    broadcasts.subscribe_with(uuid, capacity, levels_frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::byte_converter::RwBytes;
+    use crate::structures::BroadCast;
+    use futures::StreamExt;
+    use pollster::block_on;
+
+    #[test]
+    fn test_levels_stream() {
+        let uuid = 0x1E7E_0015;
+        let broadcasts = Broadcasts::default();
+        let mut levels = subscribe_levels(&broadcasts, uuid, 4);
+
+        // SYS_TIME_MS、34 键行程、其后的按键按下记录仍能解析
+        let mut records = vec![0x80, 0x10, 0x27, BROADCAST_TYPE_LEVELS, LEVELS_DATA_LEN_34];
+        for key in 0..LEVELS_DATA_LEN_34 as u16 {
+            let raw = match key {
+                1 => 0x4000 | 1800,
+                2 => 30,
+                _ => 0,
+            };
+            records.extend_from_slice(&u16::to_le_bytes(raw));
+        }
+        records.extend_from_slice(&[0x10, 0x01, 0x00]);
+        let broadcast = BroadCast {
+            bytes: RwBytes::new(records),
+        };
+        assert_eq!(broadcast.data().unwrap().len(), 3);
+        broadcasts.publish(uuid, &crate::broadcast::records(&broadcast));
+
+        let frame = block_on(levels.next()).unwrap().unwrap();
+        assert_eq!(frame.device_time_ms, Some(10000));
+        assert_eq!(frame.keys.len(), LEVELS_DATA_LEN_34 as usize);
+        assert_eq!(frame.travel_um(1), Some(1800));
+        assert_eq!(frame.keys[1].flags, 1);
+        let active: Vec<u8> = frame.active_keys().map(|travel| travel.key).collect();
+        assert_eq!(active, vec![1]);
+
+        broadcasts.close(uuid);
+        assert!(block_on(levels.next()).is_none());
+    }
+}
@@ -1,12 +1,32 @@
+pub mod backup;
+pub mod broadcast;
 pub mod byte_converter;
+pub mod cancellation;
+pub mod capabilities;
+pub mod capture;
+pub mod context;
 pub mod cross_platform_utils;
 pub mod device;
 pub mod device_constants;
 pub mod device_error_handling;
+pub mod device_log;
+pub mod diff;
+pub mod dissector;
+pub mod echo;
+pub mod input_state;
+pub mod json;
+pub mod levels;
 pub mod lock_manager;
+pub mod replay;
 pub mod report_codec;
+pub mod restore;
+#[cfg(feature = "serde")]
+pub mod serialization;
+pub mod simulator;
 pub mod structures;
 pub mod structures_codec;
+pub mod transfer;
+pub mod transport;
 mod utility;
 
 pub fn add(left: u64, right: u64) -> u64 {
@@ -0,0 +1,436 @@
+// 回放 capture 录下的会话：作为 Transport 使用，按录制顺序比对主机发出的请求，
+// 匹配后按原始相对时间送出录到的应答和广播；不匹配时记录分歧，不推进回放位置。
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Mutex;
+
+use crate::capture::{CaptureRecord, Direction};
+use crate::device_error_handling::{DeviceError, DeviceResult};
+use crate::dissector;
+use crate::report_codec::get_crc16;
+use crate::transport::{ConnectionListener, ReportListener, Transport, TransportFuture};
+use crate::utility::{future_delay, lock};
+
+const HEADER_SIZE: usize = 8;
+
+// 请求的比对键：report id、cmd、index 和有效载荷，忽略 echo、crc 和填充
+fn request_key(report: &[u8]) -> Option<(u8, u8, u8, &[u8])> {
+    if report.len() < HEADER_SIZE {
+        return None;
+    }
+    let len = (u16::from_le_bytes([report[4], report[5]]) & 0x03FF) as usize;
+    let end = (HEADER_SIZE + len.saturating_sub(4)).min(report.len());
+    Some((report[0], report[6], report[7], &report[HEADER_SIZE..end]))
+}
+
+fn request_matches(expected: &[u8], actual: &[u8]) -> bool {
+    match (request_key(expected), request_key(actual)) {
+        (Some(expected), Some(actual)) => expected == actual,
+        _ => expected == actual,
+    }
+}
+
+// 录到的应答 echo 换成本次请求的 echo，并重算 crc
+fn rewrite_echo(report: &mut [u8], echo: u8) {
+    if report.len() < HEADER_SIZE {
+        return;
+    }
+    report[1] = echo;
+    if echo != 0 {
+        report[2] = 0;
+        report[3] = 0;
+        let crc = get_crc16(report);
+        report[2..4].copy_from_slice(&crc.to_le_bytes());
+    }
+}
+
+fn describe(report: &[u8]) -> String {
+    match dissector::dissect_report(report) {
+        Ok(dissection) => dissection.to_string(),
+        Err(e) => format!("{}: {:02X?}", e, report),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub uuid: u128,
+    // 该设备录制记录中的位置
+    pub position: usize,
+    // None 表示录制已结束却仍有请求
+    pub expected: Option<CaptureRecord>,
+    pub actual: Vec<u8>,
+}
+
+impl Divergence {
+    // 第一个不同字节的偏移，跳过 echo 和 crc；长度不同时为较短者的长度
+    pub fn first_difference(&self) -> Option<usize> {
+        let expected = &self.expected.as_ref()?.data;
+        let differs = |i: usize| !(1..4).contains(&i) && expected.get(i) != self.actual.get(i);
+        (0..expected.len().max(self.actual.len())).find(|i| differs(*i))
+    }
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "回放分歧: 设备 {} 记录 #{}",
+            uuid::Uuid::from_u128(self.uuid),
+            self.position
+        )?;
+        match &self.expected {
+            Some(expected) => writeln!(f, "期望: {}", describe(&expected.data))?,
+            None => writeln!(f, "期望: 录制已结束")?,
+        }
+        write!(f, "实际: {}", describe(&self.actual))?;
+        if let Some(offset) = self.first_difference() {
+            write!(f, "\n首个差异在字节 {}", offset)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ReplayReport {
+    pub matched: usize,
+    pub divergences: Vec<Divergence>,
+    // 各设备未被回放的记录数
+    pub unconsumed: HashMap<u128, usize>,
+}
+
+impl ReplayReport {
+    pub fn is_clean(&self) -> bool {
+        self.divergences.is_empty() && self.unconsumed.values().all(|count| *count == 0)
+    }
+}
+
+impl fmt::Display for ReplayReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "匹配请求 {} 个，分歧 {} 处",
+            self.matched,
+            self.divergences.len()
+        )?;
+        for (uuid, count) in &self.unconsumed {
+            if *count > 0 {
+                write!(
+                    f,
+                    "\n设备 {} 剩余 {} 条记录未回放",
+                    uuid::Uuid::from_u128(*uuid),
+                    count
+                )?;
+            }
+        }
+        for divergence in &self.divergences {
+            write!(f, "\n{}", divergence)?;
+        }
+        Ok(())
+    }
+}
+
+struct Session {
+    records: Vec<CaptureRecord>,
+    position: usize,
+    report_ids: HashSet<u8>,
+}
+
+impl Session {
+    // 取出从当前位置起连续的 IN 记录
+    fn take_incoming(&mut self) -> Vec<CaptureRecord> {
+        let begin = self.position;
+        while self
+            .records
+            .get(self.position)
+            .is_some_and(|record| record.direction == Direction::In)
+        {
+            self.position += 1;
+        }
+        self.records[begin..self.position].to_vec()
+    }
+}
+
+pub struct ReplayTransport {
+    sessions: Mutex<HashMap<u128, Session>>,
+    // 1.0 为原始速度，0.0 不等待
+    time_scale: f64,
+    matched: Mutex<usize>,
+    divergences: Mutex<Vec<Divergence>>,
+    report_listeners: Mutex<HashMap<u128, Vec<ReportListener>>>,
+    connection_listeners: Mutex<Vec<ConnectionListener>>,
+}
+
+impl ReplayTransport {
+    // records 可以混有多个设备，按 uuid 分开回放
+    pub fn new(records: Vec<CaptureRecord>) -> Self {
+        let mut sessions: HashMap<u128, Session> = HashMap::new();
+        for record in records {
+            let session = sessions.entry(record.uuid).or_insert_with(|| Session {
+                records: Vec::new(),
+                position: 0,
+                report_ids: HashSet::new(),
+            });
+            if record.direction == Direction::Out
+                && let Some(report_id) = record.data.first()
+            {
+                session.report_ids.insert(*report_id);
+            }
+            session.records.push(record);
+        }
+        ReplayTransport {
+            sessions: Mutex::new(sessions),
+            time_scale: 1.0,
+            matched: Mutex::new(0),
+            divergences: Mutex::new(Vec::new()),
+            report_listeners: Mutex::new(HashMap::new()),
+            connection_listeners: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn with_time_scale(mut self, time_scale: f64) -> Self {
+        self.time_scale = time_scale.max(0.0);
+        self
+    }
+
+    // 通知连接监听器录制中的设备已插入
+    pub async fn connect(&self) {
+        let uuids: Vec<u128> = lock(&self.sessions).keys().cloned().collect();
+        let listeners = lock(&self.connection_listeners).clone();
+        for uuid in uuids {
+            for listener in &listeners {
+                listener.call(uuid, true).await;
+            }
+        }
+    }
+
+    pub fn report(&self) -> ReplayReport {
+        ReplayReport {
+            matched: *lock(&self.matched),
+            divergences: lock(&self.divergences).clone(),
+            unconsumed: lock(&self.sessions)
+                .iter()
+                .map(|(uuid, session)| (*uuid, session.records.len() - session.position))
+                .collect(),
+        }
+    }
+
+    // 按相对 since_us 的时间差依次送出
+    async fn deliver(
+        &self,
+        uuid: u128,
+        since_us: u64,
+        records: Vec<CaptureRecord>,
+        echo: Option<(u8, u8)>,
+    ) {
+        let listeners = lock(&self.report_listeners)
+            .get(&uuid)
+            .cloned()
+            .unwrap_or_default();
+        let mut elapsed_us = since_us;
+        for record in records {
+            let wait_us = record.timestamp_us.saturating_sub(elapsed_us) as f64 * self.time_scale;
+            elapsed_us = elapsed_us.max(record.timestamp_us);
+            if wait_us >= 1000.0 {
+                future_delay((wait_us / 1000.0) as u32).await;
+            }
+            let mut report = record.data;
+            // 广播 echo 为 0，只改写与录制请求 echo 相同的应答
+            if let Some((recorded, actual)) = echo
+                && recorded != 0
+                && report.get(1) == Some(&recorded)
+            {
+                rewrite_echo(&mut report, actual);
+            }
+            for listener in &listeners {
+                listener.call(uuid, report.clone()).await;
+            }
+        }
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn init(&self) -> TransportFuture<'_, DeviceResult<()>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn request_device(&self, _filter: Vec<(u16, Option<u16>)>) -> TransportFuture<'_, DeviceResult<()>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn device_list(&self) -> DeviceResult<Vec<u128>> {
+        Ok(lock(&self.sessions).keys().cloned().collect())
+    }
+
+    fn sub_connection_changed(&self, listener: ConnectionListener) -> TransportFuture<'_, DeviceResult<()>> {
+        lock(&self.connection_listeners).push(listener);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn unsub_connection_changed(&self, _listener: ConnectionListener) -> TransportFuture<'_, DeviceResult<()>> {
+        // SafeCallback2 无法比较，直接清空
+        lock(&self.connection_listeners).clear();
+        Box::pin(async { Ok(()) })
+    }
+
+    fn send_report(&self, uuid: u128, report: Vec<u8>) -> TransportFuture<'_, DeviceResult<()>> {
+        Box::pin(async move {
+            let (expected, incoming) = {
+                let mut sessions = lock(&self.sessions);
+                let session = sessions.get_mut(&uuid).ok_or(DeviceError::DeviceNotFound(uuid))?;
+                let expected = session.records.get(session.position).cloned();
+                match &expected {
+                    Some(record)
+                        if record.direction == Direction::Out && request_matches(&record.data, &report) =>
+                    {
+                        session.position += 1;
+                        (record.clone(), session.take_incoming())
+                    }
+                    _ => {
+                        let divergence = Divergence {
+                            uuid,
+                            position: session.position,
+                            expected,
+                            actual: report,
+                        };
+                        log::warn!(
+                            uuid:% = uuid::Uuid::from_u128(uuid), position = divergence.position;
+                            "{}", divergence
+                        );
+                        let e = DeviceError::SendReportFailed(divergence.to_string());
+                        lock(&self.divergences).push(divergence);
+                        return Err(e);
+                    }
+                }
+            };
+            *lock(&self.matched) += 1;
+            let echo = match (expected.data.get(1), report.get(1)) {
+                (Some(recorded), Some(actual)) => Some((*recorded, *actual)),
+                _ => None,
+            };
+            self.deliver(uuid, expected.timestamp_us, incoming, echo).await;
+            Ok(())
+        })
+    }
+
+    fn add_report_listener(&self, uuid: u128, listener: &ReportListener) -> TransportFuture<'_, DeviceResult<()>> {
+        lock(&self.report_listeners)
+            .entry(uuid)
+            .or_default()
+            .push(listener.clone());
+        Box::pin(async move {
+            // 录制开头、首个请求之前的广播在注册监听器时送出
+            let leading = match lock(&self.sessions).get_mut(&uuid) {
+                Some(session) if session.position == 0 => session.take_incoming(),
+                _ => Vec::new(),
+            };
+            if let Some(first) = leading.first() {
+                let since_us = first.timestamp_us;
+                self.deliver(uuid, since_us, leading, None).await;
+            }
+            Ok(())
+        })
+    }
+
+    fn remove_report_listener(&self, uuid: u128, _listener: &ReportListener) -> TransportFuture<'_, DeviceResult<()>> {
+        // 每个设备只注册一个监听器，移除时整体清理
+        lock(&self.report_listeners).remove(&uuid);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn has_report_id(&self, uuid: u128, report_id: u8) -> bool {
+        lock(&self.sessions)
+            .get(&uuid)
+            .is_some_and(|session| session.report_ids.contains(&report_id))
+    }
+
+    // 抓包不含设备描述信息
+    fn vid(&self, _uuid: u128) -> Option<u16> {
+        None
+    }
+
+    fn pid(&self, _uuid: u128) -> Option<u16> {
+        None
+    }
+
+    fn product_name(&self, _uuid: u128) -> Option<String> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::{json_line, read_json_lines};
+    use crate::report_codec::{encode_frames, ResponseStatus};
+    use crate::simulator::VirtualDevice;
+    use pollster::block_on;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_replay_rewrites_echo_and_reports_divergence() {
+        let uuid = 0x5E55_1011;
+        let device = VirtualDevice::new(uuid);
+        let request = |echo: u8, cmd: u8| {
+            encode_frames(0x22, echo, cmd, 0, ResponseStatus::End, &[]).unwrap().remove(0)
+        };
+
+        // 用虚拟设备录一段会话
+        let mut recorded = Vec::new();
+        let mut timestamp_us = 0;
+        for cmd in [0x00, 0x02] {
+            let report = request(0x13, cmd);
+            let responses = device.handle_report(report.clone());
+            for (direction, data) in std::iter::once((Direction::Out, report))
+                .chain(responses.into_iter().map(|response| (Direction::In, response)))
+            {
+                timestamp_us += 500;
+                recorded.push(json_line(&CaptureRecord {
+                    timestamp_us,
+                    direction,
+                    uuid,
+                    data,
+                }));
+            }
+        }
+        let records = read_json_lines(recorded.concat().as_bytes()).unwrap();
+        let replay = ReplayTransport::new(records).with_time_scale(0.0);
+
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        let listener = ReportListener::new(move |_uuid: u128, report: Vec<u8>| {
+            let sink = sink.clone();
+            Box::pin(async move { sink.lock().unwrap().push(report) })
+                as std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send>>
+        });
+        block_on(replay.add_report_listener(uuid, &listener)).unwrap();
+        assert!(replay.has_report_id(uuid, 0x22));
+
+        block_on(replay.send_report(uuid, request(0x12, 0x00))).unwrap();
+        {
+            let received = received.lock().unwrap();
+            assert!(!received.is_empty());
+            let response = &received[0];
+            assert_eq!(response[1], 0x12);
+            let mut zeroed = response.clone();
+            zeroed[2] = 0;
+            zeroed[3] = 0;
+            assert_eq!(u16::from_le_bytes([response[2], response[3]]), get_crc16(&zeroed));
+        }
+
+        // cmd 不符：报告分歧且不推进
+        let err = block_on(replay.send_report(uuid, request(0x12, 0x03))).unwrap_err();
+        assert!(matches!(err, DeviceError::SendReportFailed(_)));
+        let report = replay.report();
+        assert_eq!(report.matched, 1);
+        assert_eq!(report.divergences.len(), 1);
+        assert_eq!(report.divergences[0].first_difference(), Some(6));
+        assert!(!report.is_clean());
+
+        block_on(replay.send_report(uuid, request(0x12, 0x02))).unwrap();
+        let report = replay.report();
+        assert_eq!(report.matched, 2);
+        assert_eq!(report.unconsumed[&uuid], 0);
+        assert!(report.to_string().contains("分歧 1 处"));
+    }
+}
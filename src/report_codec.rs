@@ -1,4 +1,4 @@
-use crate::byte_converter::RwBytes;
+use crate::byte_converter::{Encoding, RwBytes};
 use crate::structures_codec::CodecableHidPackage;
 use std::collections::HashMap;
 use std::mem::transmute;
@@ -8,9 +8,10 @@ use std::{borrow::BorrowMut, cmp::min, collections::VecDeque};
 use futures::future::Either;
 //use crate::api::sayo_device::structures_codec::structures_codec::*;
 use futures::{Future, channel::oneshot};
+use log::{debug, trace, warn};
 use std::sync::Mutex;
 
-use crate::device::SayoDeviceApi;
+use crate::device_constants::SEND_TIMEOUT_MS;
 use crate::structures::*;
 use crate::utility::future_delay;
 
@@ -46,6 +47,123 @@ impl std::fmt::Display for ReportError {
 
 impl std::error::Error for ReportError {}
 
+// 报告头中的状态码（sta_len 高 6 位）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ResponseStatus {
+    End,             // 0x00 成功，最后一包
+    Continue,        // 0x01 成功，后续还有包
+    Gb18030,         // 0x02 GB18030 字符串
+    Utf16le,         // 0x03 UTF16LE 字符串
+    IndexMissing,    // 0x10 index 不存在
+    TooLong,         // 0x11 数据过长，可寻址数据时携带最大长度
+    TooShort,        // 0x12 数据过短
+    Mismatch,        // 0x13 数据不匹配
+    Alignment,       // 0x14 地址未对齐
+    CrcError,        // 0x3C 设备端 CRC 校验失败
+    TransferTooLong, // 0x3D 整体传输过长
+    ReadOnly,        // 0x3E index 不可写
+    UnknownCmd,      // 0x3F cmd 不存在
+    Unknown(u8),
+}
+
+impl From<u8> for ResponseStatus {
+    fn from(value: u8) -> Self {
+        match value {
+            0x00 => ResponseStatus::End,
+            0x01 => ResponseStatus::Continue,
+            0x02 => ResponseStatus::Gb18030,
+            0x03 => ResponseStatus::Utf16le,
+            0x10 => ResponseStatus::IndexMissing,
+            0x11 => ResponseStatus::TooLong,
+            0x12 => ResponseStatus::TooShort,
+            0x13 => ResponseStatus::Mismatch,
+            0x14 => ResponseStatus::Alignment,
+            0x3C => ResponseStatus::CrcError,
+            0x3D => ResponseStatus::TransferTooLong,
+            0x3E => ResponseStatus::ReadOnly,
+            0x3F => ResponseStatus::UnknownCmd,
+            value => ResponseStatus::Unknown(value),
+        }
+    }
+}
+
+impl From<ResponseStatus> for u8 {
+    fn from(status: ResponseStatus) -> Self {
+        match status {
+            ResponseStatus::End => 0x00,
+            ResponseStatus::Continue => 0x01,
+            ResponseStatus::Gb18030 => 0x02,
+            ResponseStatus::Utf16le => 0x03,
+            ResponseStatus::IndexMissing => 0x10,
+            ResponseStatus::TooLong => 0x11,
+            ResponseStatus::TooShort => 0x12,
+            ResponseStatus::Mismatch => 0x13,
+            ResponseStatus::Alignment => 0x14,
+            ResponseStatus::CrcError => 0x3C,
+            ResponseStatus::TransferTooLong => 0x3D,
+            ResponseStatus::ReadOnly => 0x3E,
+            ResponseStatus::UnknownCmd => 0x3F,
+            ResponseStatus::Unknown(value) => value,
+        }
+    }
+}
+
+impl From<Encoding> for ResponseStatus {
+    fn from(encoding: Encoding) -> Self {
+        ResponseStatus::from(u8::from(encoding))
+    }
+}
+
+impl ResponseStatus {
+    pub fn is_success(&self) -> bool {
+        matches!(
+            self,
+            ResponseStatus::End
+                | ResponseStatus::Continue
+                | ResponseStatus::Gb18030
+                | ResponseStatus::Utf16le
+        )
+    }
+
+    pub fn is_string_encoding(&self) -> bool {
+        self.encoding().is_some()
+    }
+
+    // 传输层面的偶发错误，重发同一请求可能成功
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, ResponseStatus::CrcError | ResponseStatus::Mismatch)
+    }
+
+    pub fn encoding(&self) -> Option<Encoding> {
+        match self {
+            ResponseStatus::Gb18030 => Some(Encoding::GB18030),
+            ResponseStatus::Utf16le => Some(Encoding::UTF16LE),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ResponseStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResponseStatus::End => write!(f, "Success"),
+            ResponseStatus::Continue => write!(f, "Success, continue"),
+            ResponseStatus::Gb18030 => write!(f, "GB18030 string"),
+            ResponseStatus::Utf16le => write!(f, "UTF16LE string"),
+            ResponseStatus::IndexMissing => write!(f, "Index does not exist"),
+            ResponseStatus::TooLong => write!(f, "Data length too long"),
+            ResponseStatus::TooShort => write!(f, "Data length too short"),
+            ResponseStatus::Mismatch => write!(f, "Data mismatch"),
+            ResponseStatus::Alignment => write!(f, "Alignment error"),
+            ResponseStatus::CrcError => write!(f, "CRC error"),
+            ResponseStatus::TransferTooLong => write!(f, "Transfer length too long"),
+            ResponseStatus::ReadOnly => write!(f, "Index cannot be written"),
+            ResponseStatus::UnknownCmd => write!(f, "Cmd does not exist"),
+            ResponseStatus::Unknown(value) => write!(f, "Unknown status {:#04X}", value),
+        }
+    }
+}
+
 // 常量定义
 const REPORT_ID_21: u8 = 0x21;
 const REPORT_ID_22: u8 = 0x22;
@@ -54,6 +172,42 @@ const MAX_PACKAGE_LEN_22: usize = 1016;
 const HEADER_SIZE: usize = 8;
 const TIMEOUT_MS: u32 = 8000;
 
+// 请求选项，可设置在 SayoDeviceApi 实例上，或通过 with_options 按调用覆盖
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RequestOptions {
+    // 等待应答的超时
+    pub timeout_ms: u32,
+    // 每包发送的超时
+    pub send_timeout_ms: u32,
+    // 超时或可重试状态码时的重试次数，0 为不重试
+    pub retries: u8,
+    // 第 n 次重试前等待 n * retry_backoff_ms
+    pub retry_backoff_ms: u32,
+    // 强制使用 0x21/0x22，None 时按设备支持自动选择
+    pub report_id: Option<u8>,
+    // None 时使用所属上下文的默认 echo，SayoDeviceApi::session() 会换成独占的值
+    pub echo: Option<u8>,
+    // 可寻址数据传输时同时在途的包数
+    pub transfer_window: usize,
+    // 可寻址数据传输时失败包的重传轮数
+    pub transfer_retransmits: u8,
+}
+
+impl Default for RequestOptions {
+    fn default() -> Self {
+        RequestOptions {
+            timeout_ms: TIMEOUT_MS,
+            send_timeout_ms: SEND_TIMEOUT_MS,
+            retries: 0,
+            retry_backoff_ms: 0,
+            report_id: None,
+            echo: None,
+            transfer_window: 4,
+            transfer_retransmits: 3,
+        }
+    }
+}
+
 pub struct ReportDecoder {
     handle: u128,
     buffers: Mutex<HashMap<(u8, u8, u8, u8), Vec<u8>>>,
@@ -62,6 +216,8 @@ pub struct ReportDecoder {
     screen_buffer: Vec<u8>,
     broadcast: Arc<dyn Fn(u128, &mut BroadCast) + Send + Sync + 'static>,
     cmd_response: Arc<dyn Fn(u128, HidReportHeader, Vec<u8>) + Send + Sync + 'static>,
+    // 所属上下文是否在使用该 echo
+    local_echo: Arc<dyn Fn(u8) -> bool + Send + Sync + 'static>,
 }
 
 impl ReportDecoder {
@@ -69,6 +225,7 @@ impl ReportDecoder {
         handle: u128,
         on_broadcast: Arc<dyn Fn(u128, &mut BroadCast) + Send + Sync + 'static>,
         on_cmd_response: Arc<dyn Fn(u128, HidReportHeader, Vec<u8>) + Send + Sync + 'static>,
+        local_echo: Arc<dyn Fn(u8) -> bool + Send + Sync + 'static>,
     ) -> Self {
         ReportDecoder {
             buffers: Mutex::new(HashMap::new()),
@@ -77,13 +234,14 @@ impl ReportDecoder {
             handle: handle,
             broadcast: on_broadcast,
             cmd_response: on_cmd_response,
+            local_echo,
         }
     }
 
     pub fn join(&mut self, packet: &mut Vec<u8>) -> Result<(), ReportError> {
         // println!("report received {:02X?}", packet);
         if packet.len() < HEADER_SIZE {
-            println!("Bad Report Header Length {:?}", packet.len());
+            warn!(uuid:% = uuid::Uuid::from_u128(self.handle), len = packet.len(); "bad report header length");
             return Err(ReportError::BadHeaderLength(packet.len()));
         }
 
@@ -102,7 +260,7 @@ impl ReportDecoder {
             let crc = get_crc16(&packet);
             // println!("crc: {:02X?} {:02X?}", packet_crc, crc);
             if packet_crc != crc {
-                println!("CRC error, broken packet: {:02X?}", packet);
+                warn!(uuid:% = uuid::Uuid::from_u128(self.handle), cmd = packet[6], index = packet[7]; "CRC error, broken packet: {:02X?}", packet);
                 return Err(ReportError::CrcError);
             }
         }
@@ -112,7 +270,7 @@ impl ReportDecoder {
         let len = header.len(None).ok_or(ReportError::BadReportHeader)?;
         let handle = (report_id, echo, cmd, index);
         if len + 4 > packet.len() as u16 {
-            println!("Bad Report Length {:?}", packet.len());
+            warn!(uuid:% = uuid::Uuid::from_u128(self.handle), cmd, index, len = packet.len(); "bad report length");
             return Err(ReportError::BadReportLength(packet.len()));
         }
 
@@ -125,7 +283,7 @@ impl ReportDecoder {
         self.log_status(status, cmd, index, &packet);
 
         match status {
-            0x01 => {
+            ResponseStatus::Continue => {
                 // success & continue
                 if let Ok(mut buffers) = self.buffers.lock() {
                     let buffer = buffers.entry(handle).or_insert(Vec::new());
@@ -149,63 +307,19 @@ impl ReportDecoder {
         Ok(())
     }
 
-    fn log_status(&self, status: u8, cmd: u8, index: u8, data: &[u8]) {
+    fn log_status(&self, status: ResponseStatus, cmd: u8, index: u8, data: &[u8]) {
+        let uuid = uuid::Uuid::from_u128(self.handle);
         match status {
-            0x00 => {
-                // success & end
-            }
-            0x01 => {
-                // success & continue
-            }
-            0x02 => {
-                // gb18030 string
-            }
-            0x03 => {
-                // utf16le string
-                println!("UTF16LE string: {:02X?} {:02X?}", cmd, index);
-            }
-            0x10 => {
-                // index does not exist
-                println!("Index does not exist: {:02X?} {:02X?}", cmd, index);
-            }
-            0x11 => {
-                // data length too long
-                println!(
-                    "Data length too long: {:02X?} {:02X?} max len {:02X?}",
-                    cmd, index, data
-                );
-            }
-            0x12 => {
-                // data length too short
-                println!("Data length too short: {:02X?} {:02X?}", cmd, index);
-            }
-            0x13 => {
-                // data mismatch
-                println!("Data mismatch: {:02X?} {:02X?}", cmd, index);
-            }
-            0x14 => {
-                // alignment error
-                println!("Alignment error: {:02X?} {:02X?}", cmd, index);
-            }
-            0x3C => {
-                // crc error
-                println!("CRC error: {:02X?} {:02X?} {:02X?}", cmd, index, data);
-            }
-            0x3D => {
-                // data length too long
-                println!("Data length too long: {:02X?} {:02X?}", cmd, index);
+            _ if status.is_success() => {}
+            // 查询可寻址数据长度、枚举越界时的正常应答
+            ResponseStatus::TooLong | ResponseStatus::IndexMissing => {
+                debug!(uuid:% = uuid, cmd, index, status:% = status; "status reply: {:02X?}", data);
             }
-            0x3E => {
-                // index cannot be written
-                println!("Index cannot be written: {:02X?} {:02X?}", cmd, index);
-            }
-            0x3F => {
-                // cmd does not exist
-                println!("Cmd does not exist: {:02X?} {:02X?}", cmd, index);
+            ResponseStatus::CrcError => {
+                warn!(uuid:% = uuid, cmd, index, status:% = status; "device reported CRC error: {:02X?}", data);
             }
             _ => {
-                // unknown status
-                println!("Unknown status: {:02X?} {:02X?}", cmd, status);
+                warn!(uuid:% = uuid, cmd, index, status:% = status; "device reported error status");
             }
         }
     }
@@ -251,15 +365,24 @@ impl ReportDecoder {
             self.broadcast.clone()(self.handle, broadcast);
         } else {
             (self.cmd_response.clone())(self.handle, header.clone(), data.clone());
-            // 非本实例关心的 echo：依然完成拼包/CRC 校验，但在此处丢弃，不继续分发。
+            // 非本上下文在用的 echo（其他上下文、进程或上位机的应答）：依然完成拼包/CRC 校验，但在此处丢弃，不继续分发。
             // （broadcast 仍然保留 echo==0x00 的逻辑）
-            if echo != SayoDeviceApi::ECHO && echo != 0x00 {
+            if echo != 0x00 && !(self.local_echo)(echo) && !self.has_waiter_echo(echo) {
                 return;
             }
             self.on_response_arrived(header, data);
         }
     }
 
+    // 是否有请求在等待该 echo（RequestOptions 可指定非默认 echo）
+    fn has_waiter_echo(&self, echo: u8) -> bool {
+        let waiter_channels = match self.waiter_channels.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        waiter_channels.keys().any(|handle| handle.1 == echo)
+    }
+
     fn on_response_arrived(&mut self, header: HidReportHeader, data: Vec<u8>) {
         let handle = (
             header.report_id(None).unwrap_or(0),
@@ -271,7 +394,7 @@ impl ReportDecoder {
         if let (Some(cmd), Some(screen_cmd)) = (header.cmd(None), ScreenBuffer::CMD) {
             if cmd == screen_cmd {
                 if let Err(e) = self.fill_screen_buffer(data) {
-                    println!("Failed to fill screen buffer: {}", e);
+                    warn!(uuid:% = uuid::Uuid::from_u128(self.handle), cmd; "failed to fill screen buffer: {}", e);
                 }
                 return;
             }
@@ -301,7 +424,7 @@ impl ReportDecoder {
                 waiter
             }
             None => {
-                println!("No waiter list found for: {:02X?}", handle.1);
+                trace!(uuid:% = uuid::Uuid::from_u128(self.handle), echo = handle.1, cmd = handle.2, index = handle.3; "no waiter for response");
                 return;
             }
         };
@@ -311,22 +434,42 @@ impl ReportDecoder {
                 //_ = tx.send((header, data));
                 match tx.send((header, data)) {
                     Ok(_) => (), //println!("tx sent"),
-                    Err(err) => println!("tx send Error: {:?}", err),
+                    Err(_) => {
+                        trace!(uuid:% = uuid::Uuid::from_u128(self.handle), cmd = handle.2, index = handle.3; "waiter dropped before response arrived")
+                    }
                 }
             }
-            None => println!("No waiter found"),
+            None => trace!(uuid:% = uuid::Uuid::from_u128(self.handle), cmd = handle.2, index = handle.3; "no waiter for response"),
+        };
+    }
+
+    // 清理该 handle 下已放弃等待（接收端已释放）的等待者，同一 handle 上其他请求不受影响
+    pub fn release_waiters(&self, report_id: u8, echo: u8, cmd: u8, index: u8) {
+        let mut waiter_channels = match self.waiter_channels.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
         };
+        let handle = (report_id, echo, cmd, index);
+        if let Some(waiters) = waiter_channels.get_mut(&handle) {
+            waiters.retain(|tx| !tx.is_canceled());
+            if waiters.is_empty() {
+                waiter_channels.remove(&handle);
+            }
+        }
     }
 
     //add a request to the waiter list
     pub fn request_response<T: CodecableHidPackage>(
         &self,
         report_id: u8,
+        echo: u8,
         cmd: u8,
         index: u8,
+        options: &RequestOptions,
     ) -> impl Future<Output = Result<(HidReportHeader, T), ReportError>> + use<T> {
-        // 请求响应默认只等待本实例的 echo。
-        let handle = (report_id, SayoDeviceApi::ECHO, cmd, index);
+        let handle = (report_id, echo, cmd, index);
+        let timeout_ms = options.timeout_ms;
+        let device = self.handle;
         //println!("Request response: {:02X?}", handle);
         let (tx, rx) = oneshot::channel::<(HidReportHeader, Vec<u8>)>();
         let mut waiter_channels = match self.waiter_channels.lock() {
@@ -342,7 +485,7 @@ impl ReportDecoder {
         drop(waiter_channels);
 
         async move {
-            let timeout = future_delay(TIMEOUT_MS);
+            let timeout = future_delay(timeout_ms);
             // Box::pin to make the timeout future Unpin for select on Android
             let rx_timeout = futures::future::select(rx, timeout);
 
@@ -351,7 +494,7 @@ impl ReportDecoder {
             let rx_data = match rx_res {
                 Either::Left((rx_data, _)) => rx_data,
                 Either::Right(_) => {
-                    println!("request_response Timeout {:02X?}", handle);
+                    debug!(uuid:% = uuid::Uuid::from_u128(device), report_id, echo = handle.1, cmd, index, timeout_ms; "request timeout");
                     return Err(ReportError::Timeout);
                 }
             };
@@ -369,7 +512,7 @@ impl ReportDecoder {
                             unsafe {
                                 let str_content =
                                     transmute::<&mut T, &mut StringContent>(res.borrow_mut());
-                                str_content.encoding_byte.set(Some(status));
+                                str_content.encoding_byte.set(Some(u8::from(status)));
                             }
                         } else {
                             return Err(ReportError::BadReportHeader);
@@ -378,7 +521,7 @@ impl ReportDecoder {
                     (header, res)
                 }
                 Err(_) => {
-                    println!("rx Error");
+                    debug!(uuid:% = uuid::Uuid::from_u128(device), cmd, index; "response channel closed");
                     return Err(ReportError::ChannelError);
                 }
             };
@@ -388,7 +531,7 @@ impl ReportDecoder {
     }
 }
 
-fn get_crc16(data: &[u8]) -> u16 {
+pub(crate) fn get_crc16(data: &[u8]) -> u16 {
     let mut crc: u16 = 0x0000;
     for i in 0..data.len() {
         crc = crc.wrapping_add(match i % 2 {
@@ -424,29 +567,38 @@ pub fn encode_report<T: CodecableHidPackage>(
     value: &T,
 ) -> Result<Vec<Vec<u8>>, ReportError> {
     //println!("Encoding report: {:02X?}", report_id);
-    let max_package_len = match report_id {
-        REPORT_ID_21 => MAX_PACKAGE_LEN_21,
-        REPORT_ID_22 => MAX_PACKAGE_LEN_22,
-        _ => return Err(ReportError::UnsupportedReportId(report_id)),
+    let final_status = match T::CMD {
+        StringContent::CMD => unsafe {
+            let str_content = transmute::<&T, &StringContent>(value);
+            let encoding_byte = str_content
+                .encoding_byte
+                .get()
+                .ok_or(ReportError::BadEncodingByte)?;
+            ResponseStatus::from(encoding_byte)
+        },
+        _ => ResponseStatus::End,
     };
-    let value_bytes = value.into_vec();
+    encode_frames(report_id, echo, cmd, index, final_status, &value.into_vec())
+}
+
+// 按报告长度切包，除最后一包外 status 均为 0x01，最后一包使用 final_status
+pub fn encode_frames(
+    report_id: u8,
+    echo: u8,
+    cmd: u8,
+    index: u8,
+    final_status: ResponseStatus,
+    value_bytes: &[u8],
+) -> Result<Vec<Vec<u8>>, ReportError> {
+    let max_package_len = max_package_len(report_id)?;
     let mut reports: Vec<Vec<u8>> = Vec::new();
 
     let mut packaged_len = 0;
     while packaged_len < value_bytes.len() || packaged_len == 0 {
         let status = if packaged_len + max_package_len >= value_bytes.len() {
-            match T::CMD {
-                StringContent::CMD => unsafe {
-                    let str_content = transmute::<&T, &StringContent>(value);
-                    str_content
-                        .encoding_byte
-                        .get()
-                        .ok_or(ReportError::BadEncodingByte)?
-                },
-                _ => 0x00,
-            }
+            final_status
         } else {
-            0x01
+            ResponseStatus::Continue
         };
 
         let body_len = min(max_package_len, value_bytes.len() - packaged_len);
@@ -459,10 +611,6 @@ pub fn encode_report<T: CodecableHidPackage>(
         header.status(Some(status));
         // this should a single package length, not the whole value length
         header.len(Some((body_len + 0x04) as u16));
-        //println!("cmd: {:?}", header.cmd(Some(cmd)));
-        //println!("index: {:?}", header.index(Some(index)));
-        //println!("status: {:?}", header.status(Some(status)));
-        //println!("package_len: {:?}", header.len(Some((value_bytes.len() + 0x04) as u16)));
 
         let mut data = header.into_vec();
         let body = &value_bytes[packaged_len..packaged_len + body_len];
@@ -487,3 +635,12 @@ pub fn encode_report<T: CodecableHidPackage>(
 
     Ok(reports)
 }
+
+// 单包可携带的最大数据长度（不含 8 字节报告头）
+pub fn max_package_len(report_id: u8) -> Result<usize, ReportError> {
+    match report_id {
+        REPORT_ID_21 => Ok(MAX_PACKAGE_LEN_21),
+        REPORT_ID_22 => Ok(MAX_PACKAGE_LEN_22),
+        _ => Err(ReportError::UnsupportedReportId(report_id)),
+    }
+}
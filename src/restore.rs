@@ -0,0 +1,515 @@
+// 从配置归档恢复：先与设备当前配置比较得到预览，调用方选择要恢复的部分，
+// 只写入有变化的条目，写完整个部分后读回核对，全部一致才 save_all。
+// 归档的 model_code 与设备不同时拒绝恢复（可用 allow_model_mismatch 强制），
+// 固件版本不同只给出警告：模拟按键以微米保存，其余结构体按原始字节写回。
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::pin::Pin;
+
+use futures::Future;
+use log::{info, warn};
+
+use crate::backup::{self, ArchiveEntry, ConfigArchive, Section};
+use crate::byte_converter::RwBytes;
+use crate::device::SayoDeviceApi;
+use crate::device_error_handling::{DeviceError, DeviceResult};
+use crate::structures::*;
+use crate::structures_codec::CodecableHidPackage;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveMismatch {
+    Model { archive: u16, device: u16 },
+    Firmware { archive: u16, device: u16 },
+}
+
+impl fmt::Display for ArchiveMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveMismatch::Model { archive, device } => {
+                write!(f, "型号不同: 归档 {:#06X}，设备 {:#06X}", archive, device)
+            }
+            ArchiveMismatch::Firmware { archive, device } => {
+                write!(f, "固件版本不同: 归档 {}，设备 {}", archive, device)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RestoreOptions {
+    // 型号不同时仍然恢复
+    pub allow_model_mismatch: bool,
+    // 核对通过后调用 save_all
+    pub save: bool,
+}
+
+impl Default for RestoreOptions {
+    fn default() -> Self {
+        RestoreOptions {
+            allow_model_mismatch: false,
+            save: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectionPreview {
+    pub section: Section,
+    // 与设备当前值不同、恢复时会写入的 index
+    pub changed: Vec<u8>,
+    pub unchanged: usize,
+    // 归档中有、设备上不存在的 index，恢复时跳过
+    pub missing: Vec<u8>,
+    // 设备不支持该部分
+    pub unsupported: bool,
+}
+
+impl SectionPreview {
+    pub fn has_changes(&self) -> bool {
+        !self.changed.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RestorePreview {
+    pub warnings: Vec<ArchiveMismatch>,
+    pub sections: Vec<SectionPreview>,
+}
+
+impl RestorePreview {
+    pub fn changed_sections(&self) -> Vec<Section> {
+        self.sections
+            .iter()
+            .filter(|preview| preview.has_changes())
+            .map(|preview| preview.section)
+            .collect()
+    }
+}
+
+impl fmt::Display for RestorePreview {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for warning in &self.warnings {
+            writeln!(f, "警告: {}", warning)?;
+        }
+        for preview in &self.sections {
+            write!(f, "{:<16}", preview.section.name())?;
+            if preview.unsupported {
+                writeln!(f, "设备不支持")?;
+                continue;
+            }
+            match preview.changed.is_empty() {
+                true => write!(f, "无变化")?,
+                false => write!(f, "{} 项变化 {:?}", preview.changed.len(), preview.changed)?,
+            }
+            if !preview.missing.is_empty() {
+                write!(f, "，设备上缺少 {:?}", preview.missing)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RestoreReport {
+    pub warnings: Vec<ArchiveMismatch>,
+    pub written: Vec<(Section, u8)>,
+    // 设备上不存在的 index 或设备不支持的部分
+    pub skipped: Vec<(Section, u8)>,
+    // 读回与归档不一致
+    pub mismatched: Vec<(Section, u8)>,
+    // 写入时出错的条目，其后的条目和部分没有写入
+    pub failed: Option<(Section, u8)>,
+    pub saved: bool,
+}
+
+impl RestoreReport {
+    pub fn is_verified(&self) -> bool {
+        self.mismatched.is_empty()
+    }
+}
+
+fn check_device(archive: &ConfigArchive, info: &DeviceInfo) -> Vec<ArchiveMismatch> {
+    let mut warnings = Vec::new();
+    let model_code = info.model_code(None).unwrap_or(0);
+    if archive.model_code != model_code {
+        warnings.push(ArchiveMismatch::Model {
+            archive: archive.model_code,
+            device: model_code,
+        });
+    }
+    let ver = info.ver(None).unwrap_or(0);
+    if archive.firmware_version != ver {
+        warnings.push(ArchiveMismatch::Firmware {
+            archive: archive.firmware_version,
+            device: ver,
+        });
+    }
+    warnings
+}
+
+// 字符串条目开头的编码字节一并比较，只有编码不同也算变化
+fn same_config(section: Section, current: &[u8], archived: &[u8]) -> bool {
+    section.config_bytes(current) == section.config_bytes(archived)
+}
+
+// 按部分比较，只读取归档中出现的部分
+async fn compare_section(
+    device: &SayoDeviceApi,
+    archive: &ConfigArchive,
+    section: Section,
+) -> DeviceResult<(SectionPreview, BTreeMap<u8, Vec<u8>>)> {
+    let mut preview = SectionPreview {
+        section,
+        changed: Vec::new(),
+        unchanged: 0,
+        missing: Vec::new(),
+        unsupported: false,
+    };
+    let current: BTreeMap<u8, Vec<u8>> = match backup::read_section(device, section).await {
+        Ok(current) => current.into_iter().collect(),
+        Err(e) if e.is_unsupported() => {
+            preview.unsupported = true;
+            preview.missing = archive.section(section).map(|entry| entry.index).collect();
+            return Ok((preview, BTreeMap::new()));
+        }
+        Err(e) => return Err(e),
+    };
+    for entry in archive.section(section) {
+        match current.get(&entry.index) {
+            None => preview.missing.push(entry.index),
+            Some(data) if same_config(section, data, &entry.data) => preview.unchanged += 1,
+            Some(_) => preview.changed.push(entry.index),
+        }
+    }
+    Ok((preview, current))
+}
+
+pub async fn preview(
+    device: &SayoDeviceApi,
+    archive: &ConfigArchive,
+) -> DeviceResult<RestorePreview> {
+    let info = device.get_device_info().await?;
+    let mut preview = RestorePreview {
+        warnings: check_device(archive, &info),
+        sections: Vec::new(),
+    };
+    for section in archive.sections() {
+        let (section_preview, _) = compare_section(device, archive, section).await?;
+        preview.sections.push(section_preview);
+    }
+    Ok(preview)
+}
+
+fn no_progress(_: f32) -> Pin<Box<dyn Future<Output = bool> + Send + 'static>> {
+    Box::pin(async { true })
+}
+
+// 字符串条目为编码字节 + 文本字节，按归档的编码写回
+fn string_content(data: &[u8]) -> DeviceResult<StringContent> {
+    let (&encoding, text) = data
+        .split_first()
+        .ok_or_else(|| DeviceError::InvalidData("字符串条目缺少编码字节".to_string()))?;
+    let content = StringContent::new(RwBytes::new(text.to_vec()));
+    content.encoding_byte.set(Some(encoding));
+    Ok(content)
+}
+
+async fn write_entry(device: &SayoDeviceApi, entry: &ArchiveEntry) -> DeviceResult<()> {
+    let index = entry.index;
+    let bytes = || RwBytes::new(entry.data.clone());
+    match entry.section {
+        Section::DeviceName => {
+            device
+                .set_device_name_content(string_content(&entry.data)?)
+                .await?;
+        }
+        Section::DeviceConfig => {
+            device
+                .set_optional_bytes(&DeviceConfig::new(bytes()))
+                .await?;
+        }
+        Section::RfConfig => {
+            device.set_rf_config(&RFConfig::new(bytes())).await?;
+        }
+        Section::KeyInfo => {
+            device.set_key_info(index, &KeyInfo::new(bytes())).await?;
+        }
+        Section::LedInfo => {
+            device.set_led_info(index, &LEDInfo::new(bytes())).await?;
+        }
+        Section::ColorTable => {
+            device
+                .set_color_table(index, &ColorTable::new(bytes()))
+                .await?;
+        }
+        Section::AnalogKey => {
+            device
+                .set_analog_key(index, &AnalogKeyInfo2::new(bytes()))
+                .await?;
+        }
+        Section::AdvancedKey => {
+            device
+                .set_advanced_key(index, &AdvancedKeyBinding::new(bytes()))
+                .await?;
+        }
+        Section::LedEffect => {
+            device.set_led_effect(&LedEffect::new(bytes())).await?;
+        }
+        Section::GamePadCfg => {
+            device.set_gamepad_cfg(&GamePadCfg::new(bytes())).await?;
+        }
+        Section::AmbientLed => {
+            device
+                .set_ambient_led(index, &AmbientLED::new(bytes()))
+                .await?;
+        }
+        Section::String => {
+            device
+                .set_string(index, string_content(&entry.data)?)
+                .await?;
+        }
+        Section::ScriptName => {
+            device
+                .set_script_name(index, string_content(&entry.data)?)
+                .await?;
+        }
+        Section::Script => {
+            let script = SayoScriptContent::new(bytes());
+            device.set_script(index, &script, 0, no_progress).await?;
+        }
+        Section::DisplayAssets => {
+            let assets = DisplayAssets::new(bytes());
+            device
+                .set_display_assets(index, &assets, 0, no_progress)
+                .await?;
+        }
+        Section::LcdDrawData(layer) => {
+            device
+                .set_lcd_draw_data(layer as u8, index, &LCDDrawData::new(bytes()))
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+// 恢复选中的部分；sections 为空时恢复预览中所有有变化的部分。
+// 读回不一致或中途出错时不保存，返回带有已完成部分的 RestoreIncomplete
+pub async fn restore(
+    device: &SayoDeviceApi,
+    archive: &ConfigArchive,
+    sections: &[Section],
+    options: RestoreOptions,
+) -> DeviceResult<RestoreReport> {
+    let info = device.get_device_info().await?;
+    let warnings = check_device(archive, &info);
+    for warning in &warnings {
+        match warning {
+            ArchiveMismatch::Model { .. } if !options.allow_model_mismatch => {
+                return Err(DeviceError::ArchiveMismatch(*warning));
+            }
+            _ => warn!("restoring config archive: {}", warning),
+        }
+    }
+    let mut report = RestoreReport {
+        warnings,
+        ..Default::default()
+    };
+    if let Err(e) = apply(device, archive, sections, options, &mut report).await {
+        warn!(
+            uuid:% = uuid::Uuid::from_u128(device.get_uuid()),
+            written = report.written.len(),
+            saved = report.saved;
+            "config restore failed: {}", e
+        );
+        return Err(DeviceError::RestoreIncomplete {
+            report,
+            error: Some(Box::new(e)),
+        });
+    }
+    if !report.is_verified() {
+        return Err(DeviceError::RestoreIncomplete {
+            report,
+            error: None,
+        });
+    }
+    info!(
+        uuid:% = uuid::Uuid::from_u128(device.get_uuid()),
+        written = report.written.len(),
+        skipped = report.skipped.len(),
+        saved = report.saved;
+        "config restore done"
+    );
+    Ok(report)
+}
+
+// 写入、读回核对并保存，进度记在 report 中，出错时调用方仍能拿到已完成的部分
+async fn apply(
+    device: &SayoDeviceApi,
+    archive: &ConfigArchive,
+    sections: &[Section],
+    options: RestoreOptions,
+    report: &mut RestoreReport,
+) -> DeviceResult<()> {
+    let selected = archive
+        .sections()
+        .into_iter()
+        .filter(|section| sections.is_empty() || sections.contains(section));
+    for section in selected {
+        let (preview, _) = compare_section(device, archive, section).await?;
+        report
+            .skipped
+            .extend(preview.missing.iter().map(|index| (section, *index)));
+        if !preview.has_changes() {
+            continue;
+        }
+        let entries: BTreeMap<u8, &ArchiveEntry> = archive
+            .section(section)
+            .map(|entry| (entry.index, entry))
+            .collect();
+        for index in &preview.changed {
+            if let Err(e) = write_entry(device, entries[index]).await {
+                report.failed = Some((section, *index));
+                return Err(e);
+            }
+            report.written.push((section, *index));
+        }
+        let after: BTreeMap<u8, Vec<u8>> = backup::read_section(device, section)
+            .await?
+            .into_iter()
+            .collect();
+        for index in &preview.changed {
+            let verified = after
+                .get(index)
+                .is_some_and(|data| same_config(section, data, &entries[index].data));
+            if !verified {
+                report.mismatched.push((section, *index));
+            }
+        }
+    }
+
+    if options.save && report.is_verified() && !report.written.is_empty() {
+        device.save_all().await?;
+        report.saved = true;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::byte_converter::Encoding;
+    use crate::context::SayoContext;
+    use crate::device_constants::{CMD_DEVICE_INFO, CMD_DEVICE_NAME, CMD_STRING};
+    use crate::simulator::{VirtualBus, VirtualDevice};
+    use pollster::block_on;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_restore_preview_and_apply() {
+        let uuid = 0x5A10_0000_0000_0000_0000_0000_0000_0023;
+        let bus = Arc::new(VirtualBus::new());
+        let context = SayoContext::new(bus.clone());
+        block_on(context.init()).expect("init");
+        let device = VirtualDevice::new(uuid);
+        block_on(bus.attach(device.clone()));
+        let api = context.device(uuid);
+        let archive = block_on(backup::snapshot(&api)).expect("snapshot");
+
+        // 刷固件后调校丢失
+        let key = KeyInfo::new(RwBytes::new(device.entry(0x10, 2).unwrap()));
+        key.key_width(Some(30));
+        device.set_entry(0x10, 2, key.into_vec());
+        let analog = AnalogKeyInfo2::new(RwBytes::new(device.entry(0x1C, 1).unwrap()));
+        analog.trigger_level(Some(400));
+        // 实时测量值不算变化
+        analog.raw_data(Some(0x1234));
+        device.set_entry(0x1C, 1, analog.into_vec());
+        let analog = AnalogKeyInfo2::new(RwBytes::new(device.entry(0x1C, 2).unwrap()));
+        analog.raw_data(Some(0x1234));
+        device.set_entry(0x1C, 2, analog.into_vec());
+
+        let preview = block_on(preview(&api, &archive)).expect("preview");
+        assert!(preview.warnings.is_empty());
+        assert_eq!(
+            preview.changed_sections(),
+            vec![Section::KeyInfo, Section::AnalogKey]
+        );
+        assert!(preview.to_string().contains("key-info        1 项变化 [2]"));
+
+        // 只恢复按键
+        let report = block_on(restore(
+            &api,
+            &archive,
+            &[Section::KeyInfo],
+            RestoreOptions::default(),
+        ))
+        .expect("restore");
+        assert_eq!(report.written, vec![(Section::KeyInfo, 2)]);
+        assert!(report.saved);
+        assert_eq!(
+            device.entry(0x10, 2).unwrap(),
+            archive.entry(Section::KeyInfo, 2).unwrap().data
+        );
+        let analog = AnalogKeyInfo2::new(RwBytes::new(device.entry(0x1C, 1).unwrap()));
+        assert_eq!(analog.trigger_level(None), Some(400));
+
+        // 型号不同时拒绝
+        let info = DeviceInfo::new(RwBytes::new(device.entry(CMD_DEVICE_INFO, 0).unwrap()));
+        info.model_code(Some(0x0200));
+        device.set_entry(CMD_DEVICE_INFO, 0, info.into_vec());
+        let err = block_on(restore(&api, &archive, &[], RestoreOptions::default())).unwrap_err();
+        assert!(matches!(
+            err,
+            DeviceError::ArchiveMismatch(ArchiveMismatch::Model { .. })
+        ));
+        let options = RestoreOptions {
+            allow_model_mismatch: true,
+            ..Default::default()
+        };
+        let report = block_on(restore(&api, &archive, &[], options)).expect("forced restore");
+        assert_eq!(report.written, vec![(Section::AnalogKey, 1)]);
+        assert_eq!(report.warnings.len(), 1);
+
+        // 只有编码不同的字符串也会恢复，写回时使用归档中的编码
+        let text = device.entry(CMD_STRING, 1).unwrap();
+        device.set_string(CMD_STRING, 1, Encoding::UTF16LE, text);
+        let name = device.entry(CMD_DEVICE_NAME, 0).unwrap();
+        device.set_string(CMD_DEVICE_NAME, 0, Encoding::GB18030, name);
+        let preview = block_on(super::preview(&api, &archive)).expect("preview");
+        assert_eq!(
+            preview.changed_sections(),
+            vec![Section::DeviceName, Section::String]
+        );
+        let report = block_on(restore(&api, &archive, &[], options)).expect("restore strings");
+        assert_eq!(
+            report.written,
+            vec![(Section::DeviceName, 0), (Section::String, 1)]
+        );
+        let strings = block_on(api.get_strings()).unwrap();
+        assert_eq!(strings[1].encoding_byte.get(), Some(0x02));
+        let name = block_on(api.get_device_name_content()).unwrap();
+        assert_eq!(name.encoding_byte.get(), Some(0x03));
+
+        // 中途写入失败时返回已写入的部分，不保存
+        let key = KeyInfo::new(RwBytes::new(device.entry(0x10, 2).unwrap()));
+        key.key_width(Some(30));
+        device.set_entry(0x10, 2, key.into_vec());
+        let analog = AnalogKeyInfo2::new(RwBytes::new(device.entry(0x1C, 1).unwrap()));
+        analog.trigger_level(Some(400));
+        device.set_entry(0x1C, 1, analog.into_vec());
+        device.set_read_only(0x1C, true);
+        let err = block_on(restore(&api, &archive, &[], options)).unwrap_err();
+        let DeviceError::RestoreIncomplete { report, error } = err else {
+            panic!("expected RestoreIncomplete, got {:?}", err);
+        };
+        assert_eq!(report.written, vec![(Section::KeyInfo, 2)]);
+        assert_eq!(report.failed, Some((Section::AnalogKey, 1)));
+        assert!(!report.saved);
+        assert!(matches!(
+            error.as_deref(),
+            Some(DeviceError::DeviceStatus { .. })
+        ));
+    }
+}
@@ -0,0 +1,403 @@
+// serde 支持（serde feature）：结构体序列化为按字段命名的对象，字段与 dissector 的 JSON 相同，
+// 字节数组为小写 hex 字符串。另有两个元数据键：
+//   _len   结构体的字节长度，反序列化时按此长度分配，再按字段写入；
+//   _rest  字段没有覆盖到的字节（长度不足一项的尾部、字符串结束符之后的内容等），
+//          键为十进制偏移，值为 hex；全部被字段覆盖时省略。
+// 因此反序列化得到的字节与序列化前完全相同。StringContent 的编码不在字节中，由 encoding 字段保存。
+
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+use std::fmt;
+
+use crate::byte_converter::RwBytes;
+use crate::dissector::{ApplyJson, Dissect};
+use crate::json::JsonValue;
+use crate::structures::*;
+use crate::structures_codec::CodecableHidPackage;
+use crate::utility::hex;
+
+const LEN_KEY: &str = "_len";
+const REST_KEY: &str = "_rest";
+
+impl Serialize for JsonValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            JsonValue::Null => serializer.serialize_none(),
+            JsonValue::Bool(b) => serializer.serialize_bool(*b),
+            JsonValue::Number(n) => serializer.serialize_i64(*n),
+            JsonValue::String(s) => serializer.serialize_str(s),
+            JsonValue::Array(items) => {
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    seq.serialize_element(item)?;
+                }
+                seq.end()
+            }
+            JsonValue::Object(entries) => {
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (key, value) in entries {
+                    map.serialize_entry(key, value)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+struct JsonValueVisitor;
+
+impl<'de> Visitor<'de> for JsonValueVisitor {
+    type Value = JsonValue;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a JSON value without floats")
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<JsonValue, E> {
+        Ok(JsonValue::Bool(v))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<JsonValue, E> {
+        Ok(JsonValue::Number(v))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<JsonValue, E> {
+        i64::try_from(v)
+            .map(JsonValue::Number)
+            .map_err(|_| E::custom(format!("{} out of range", v)))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<JsonValue, E> {
+        Ok(JsonValue::String(v.to_string()))
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<JsonValue, E> {
+        Ok(JsonValue::String(v))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<JsonValue, E> {
+        Ok(JsonValue::Null)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<JsonValue, E> {
+        Ok(JsonValue::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<JsonValue, D::Error> {
+        JsonValue::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<JsonValue, A::Error> {
+        let mut items = Vec::new();
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(JsonValue::Array(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<JsonValue, A::Error> {
+        let mut entries = Vec::new();
+        while let Some(entry) = map.next_entry()? {
+            entries.push(entry);
+        }
+        Ok(JsonValue::Object(entries))
+    }
+}
+
+impl<'de> Deserialize<'de> for JsonValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(JsonValueVisitor)
+    }
+}
+
+// 与原字节不同的连续区间
+fn differing_runs(bytes: &[u8], rebuilt: &[u8]) -> Vec<(usize, Vec<u8>)> {
+    let mut runs: Vec<(usize, Vec<u8>)> = Vec::new();
+    for (i, (byte, rebuilt)) in bytes.iter().zip(rebuilt).enumerate() {
+        if byte == rebuilt {
+            continue;
+        }
+        match runs.last_mut() {
+            Some((offset, run)) if *offset + run.len() == i => run.push(*byte),
+            _ => runs.push((i, vec![*byte])),
+        }
+    }
+    runs
+}
+
+pub fn to_json_value<T: CodecableHidPackage + Dissect>(value: &T) -> JsonValue {
+    encode(value, T::new, &[])
+}
+
+pub fn from_json_value<T: CodecableHidPackage + Dissect>(value: &JsonValue) -> Result<T, String> {
+    decode(value, T::new, &[])
+}
+
+// 不在字节中的状态（如 StringContent 的编码）由 new 在构造时带上，
+// 对应的字段 state_keys 照常输出，但不参与按字段写入
+fn fields_of(entries: &[(String, JsonValue)], state_keys: &[&str]) -> JsonValue {
+    JsonValue::Object(
+        entries
+            .iter()
+            .filter(|(key, _)| {
+                key != LEN_KEY && key != REST_KEY && !state_keys.contains(&key.as_str())
+            })
+            .cloned()
+            .collect(),
+    )
+}
+
+fn encode<T: CodecableHidPackage + Dissect>(
+    value: &T,
+    new: impl Fn(RwBytes) -> T,
+    state_keys: &[&str],
+) -> JsonValue {
+    let bytes = value.into_vec();
+    let mut entries: Vec<(String, JsonValue)> = value
+        .fields()
+        .into_iter()
+        .map(|field| (field.name.to_string(), field.value.to_json_value()))
+        .collect();
+    // 按字段重建一次，找出字段没有覆盖的字节
+    let rebuilt = new(RwBytes::new(vec![0; bytes.len()]));
+    let _ = rebuilt.apply_json(&fields_of(&entries, state_keys));
+    let rest = differing_runs(&bytes, &rebuilt.into_vec());
+
+    entries.push((LEN_KEY.to_string(), JsonValue::Number(bytes.len() as i64)));
+    if !rest.is_empty() {
+        let rest = rest
+            .into_iter()
+            .map(|(offset, run)| (offset.to_string(), JsonValue::String(hex(&run, ""))))
+            .collect();
+        entries.push((REST_KEY.to_string(), JsonValue::Object(rest)));
+    }
+    JsonValue::Object(entries)
+}
+
+fn decode<T: CodecableHidPackage + Dissect>(
+    value: &JsonValue,
+    new: impl Fn(RwBytes) -> T,
+    state_keys: &[&str],
+) -> Result<T, String> {
+    let JsonValue::Object(entries) = value else {
+        return Err(format!("expected object, got {}", value.type_name()));
+    };
+    let len = match value.get(LEN_KEY) {
+        Some(JsonValue::Number(len)) => {
+            usize::try_from(*len).map_err(|_| format!("{}: invalid length {}", LEN_KEY, len))?
+        }
+        Some(other) => {
+            return Err(format!(
+                "{}: expected number, got {}",
+                LEN_KEY,
+                other.type_name()
+            ));
+        }
+        None => return Err(format!("missing {}", LEN_KEY)),
+    };
+    let rest = match value.get(REST_KEY) {
+        Some(rest) => parse_rest(rest)?,
+        None => Vec::new(),
+    };
+    // 先写入 _rest，只读字段（由字节推出）才能与 JSON 比对一致；
+    // 字段写入可能覆盖 _rest 的字节，最后再写一次
+    let mut bytes = vec![0; len];
+    patch(&mut bytes, &rest)?;
+    let res = new(RwBytes::new(bytes));
+    res.apply_json(&fields_of(entries, state_keys))?;
+    if rest.is_empty() {
+        return Ok(res);
+    }
+    let mut bytes = res.into_vec();
+    patch(&mut bytes, &rest)?;
+    Ok(new(RwBytes::new(bytes)))
+}
+
+fn parse_rest(rest: &JsonValue) -> Result<Vec<(usize, Vec<u8>)>, String> {
+    let JsonValue::Object(rest) = rest else {
+        return Err(format!(
+            "{}: expected object, got {}",
+            REST_KEY,
+            rest.type_name()
+        ));
+    };
+    let mut runs = Vec::new();
+    for (offset, run) in rest {
+        let offset: usize = offset
+            .parse()
+            .map_err(|_| format!("{}: invalid offset {:?}", REST_KEY, offset))?;
+        let mut data = Vec::new();
+        data.apply_json(run)
+            .map_err(|e| format!("{}.{}: {}", REST_KEY, offset, e))?;
+        runs.push((offset, data));
+    }
+    Ok(runs)
+}
+
+fn patch(bytes: &mut [u8], rest: &[(usize, Vec<u8>)]) -> Result<(), String> {
+    for (offset, data) in rest {
+        let target = bytes
+            .get_mut(*offset..*offset + data.len())
+            .ok_or_else(|| format!("{}.{}: out of range", REST_KEY, offset))?;
+        target.copy_from_slice(data);
+    }
+    Ok(())
+}
+
+macro_rules! serde_structures {
+    ($($ty:ident),* $(,)?) => {
+        $(
+            impl Serialize for $ty {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    to_json_value(self).serialize(serializer)
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $ty {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    let value = JsonValue::deserialize(deserializer)?;
+                    from_json_value(&value)
+                        .map_err(|e| de::Error::custom(format!("{}: {}", Self::TYPE_NAME, e)))
+                }
+            }
+        )*
+    };
+}
+
+serde_structures!(
+    DeviceInfo,
+    SystemInfo,
+    DeviceConfig,
+    RFConfig,
+    KeyData,
+    KeyInfo,
+    LEDInfo,
+    ColorTable,
+    TouchSensitivity,
+    AnalogKeyInfo,
+    AnalogKeyInfo2,
+    AdvancedKeyBinding,
+    TriggerKeyboardHid,
+    TriggerMouseHid,
+    TriggerMeidaHid,
+    LCDDrawData,
+    LedEffect,
+    GamePadCfg,
+    AmbientLED,
+    MonkeyGpios,
+    LedData,
+    SayoColorData,
+    SayoScriptContent,
+    DisplayAssets,
+    ScreenBuffer,
+    BroadCastData,
+    BroadCast,
+);
+
+const ENCODING_KEY: &str = "encoding";
+
+fn string_content(encoding: Option<u8>) -> impl Fn(RwBytes) -> StringContent {
+    move |bytes| {
+        let res = StringContent::new(bytes);
+        res.encoding_byte.set(encoding);
+        res
+    }
+}
+
+// 编码不在字节中：encoding 字段照常输出，反序列化时先按它构造再写入字符串，
+// 避免 StringContent::encoding 按新编码重写已有内容
+impl Serialize for StringContent {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let new = string_content(self.encoding_byte.get());
+        encode(self, new, &[ENCODING_KEY]).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for StringContent {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = JsonValue::deserialize(deserializer)?;
+        let decode_string = || {
+            let encoding = match value.get(ENCODING_KEY) {
+                Some(JsonValue::Null) => None,
+                Some(encoding) => {
+                    let mut encoding_byte = 0u8;
+                    encoding_byte
+                        .apply_json(encoding)
+                        .map_err(|e| format!("{}: {}", ENCODING_KEY, e))?;
+                    Some(encoding_byte)
+                }
+                None => StringContent::new(RwBytes::new(vec![])).encoding_byte.get(),
+            };
+            decode(&value, string_content(encoding), &[ENCODING_KEY])
+        };
+        decode_string().map_err(|e| de::Error::custom(format!("{}: {}", Self::TYPE_NAME, e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<T>(len: usize) -> serde_json::Value
+    where
+        T: CodecableHidPackage + Serialize + for<'de> Deserialize<'de>,
+    {
+        let bytes: Vec<u8> = (0..len).map(|i| (i * 37 + 11) as u8).collect();
+        let value = T::new(RwBytes::new(bytes.clone()));
+        let json = serde_json::to_value(&value).unwrap();
+        let copy: T = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(copy.into_vec(), bytes);
+        json
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let json = round_trip::<DeviceInfo>(20);
+        assert_eq!(json["model_code"], 0x300B);
+        assert_eq!(json["_len"], 20);
+        round_trip::<SystemInfo>(64);
+        round_trip::<DeviceConfig>(64);
+        round_trip::<RFConfig>(32);
+        round_trip::<KeyData>(8);
+        let json = round_trip::<KeyInfo>(48);
+        assert!(json["key_fn"].is_array() && json["key_fn"][0].is_object());
+        round_trip::<LEDInfo>(48);
+        // 尾部 2 字节不足一个颜色，放在 _rest
+        let json = round_trip::<ColorTable>(13);
+        assert_eq!(json["_rest"]["11"], "a2c7");
+        round_trip::<TouchSensitivity>(16);
+        round_trip::<AnalogKeyInfo>(64);
+        round_trip::<AnalogKeyInfo2>(104);
+        round_trip::<AdvancedKeyBinding>(40);
+        round_trip::<TriggerKeyboardHid>(8);
+        round_trip::<TriggerMouseHid>(8);
+        round_trip::<TriggerMeidaHid>(4);
+        round_trip::<LCDDrawData>(40);
+        round_trip::<LedEffect>(32);
+        round_trip::<GamePadCfg>(56);
+        round_trip::<AmbientLED>(36);
+        round_trip::<MonkeyGpios>(16);
+        round_trip::<LedData>(8);
+        round_trip::<SayoColorData>(3);
+        round_trip::<SayoScriptContent>(56);
+        round_trip::<DisplayAssets>(56);
+        round_trip::<ScreenBuffer>(56);
+        round_trip::<BroadCastData>(12);
+        round_trip::<BroadCast>(56);
+
+        // 字符串的编码随 encoding 字段往返
+        let string = StringContent::new(RwBytes::new(vec![0; 8]));
+        string.encoding_byte.set(Some(0x02));
+        string.str(Some("宏".to_string()));
+        let json = serde_json::to_value(&string).unwrap();
+        assert_eq!(json["encoding"], 0x02);
+        let copy: StringContent = serde_json::from_value(json).unwrap();
+        assert_eq!(copy.encoding_byte.get(), Some(0x02));
+        assert_eq!(copy.str(None).as_deref(), Some("宏"));
+        assert_eq!(copy.into_vec(), string.into_vec());
+        round_trip::<StringContent>(32);
+
+        let err = serde_json::from_str::<KeyData>(r#"{"key_mode": 1}"#).unwrap_err();
+        assert!(err.to_string().contains("missing _len"));
+    }
+}
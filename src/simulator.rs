@@ -0,0 +1,859 @@
+// 进程内的虚拟 Sayo 设备，按 report_codec 的分包协议应答命令，
+// 配合 VirtualBus 作为 Transport 使用，无需真实硬件即可跑通完整请求链路。
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use crate::byte_converter::{Encoding, RwBytes};
+use crate::device::ScreenLayer;
+use crate::device_constants::*;
+use crate::device_error_handling::{DeviceError, DeviceResult};
+use crate::report_codec::{encode_frames, get_crc16, max_package_len, ResponseStatus};
+use crate::structures::*;
+use crate::structures_codec::CodecableHidPackage;
+use crate::transport::{ConnectionListener, ReportListener, Transport, TransportFuture};
+use crate::utility::lock;
+
+const HEADER_SIZE: usize = 8;
+const REPORT_LEN_21: usize = 64;
+const REPORT_LEN_22: usize = 1024;
+
+const CMD_LOCK: u8 = 0x05;
+const CMD_UNLOCK: u8 = 0x06;
+const CMD_SCRIPT: u8 = 0x1A;
+const CMD_DISPLAY_ASSETS: u8 = 0x20;
+const CMD_LED_STATUS: u8 = 0x27;
+
+#[derive(Debug, Clone)]
+struct Entry {
+    bytes: Vec<u8>,
+    // End 表示普通结构体，其余为字符串的编码
+    status: ResponseStatus,
+}
+
+#[derive(Debug, Clone)]
+struct Region {
+    capacity: u32,
+    data: Vec<u8>,
+}
+
+struct DeviceState {
+    vid: u16,
+    pid: u16,
+    product_name: String,
+    report_ids: Vec<u8>,
+    entries: HashMap<(u8, u8), Entry>,
+    regions: HashMap<(u8, u8), Region>,
+    read_only: HashSet<u8>,
+    // 不应答的命令，用于模拟超时
+    silent: HashSet<u8>,
+    pending: HashMap<(u8, u8, u8, u8), Vec<u8>>,
+}
+
+#[derive(Clone)]
+pub struct VirtualDevice {
+    pub uuid: u128,
+    state: Arc<Mutex<DeviceState>>,
+}
+
+impl VirtualDevice {
+    pub const KEY_COUNT: u8 = 4;
+
+    pub fn new(uuid: u128) -> Self {
+        let device = VirtualDevice {
+            uuid,
+            state: Arc::new(Mutex::new(DeviceState {
+                vid: 0x8089,
+                pid: 0x0009,
+                product_name: "Sayo Virtual Device".to_string(),
+                report_ids: vec![REPORT_ID_BOOTUP, REPORT_ID_MAIN],
+                entries: HashMap::new(),
+                regions: HashMap::new(),
+                read_only: HashSet::new(),
+                silent: HashSet::new(),
+                pending: HashMap::new(),
+            })),
+        };
+        device.load_defaults();
+        device
+    }
+
+    fn load_defaults(&self) {
+        let device_info = DeviceInfo::new(RwBytes::new(vec![0; 20]));
+        device_info.model_code(Some(0x0106));
+        device_info.ver(Some(130));
+        device_info.batt_lv(Some(100));
+        self.set_entry(CMD_DEVICE_INFO, 0, device_info.into_vec());
+
+        let name = RwBytes::from_str(Encoding::UTF16LE, "Sayo Virtual").into_vec();
+        let mut name_bytes = vec![0; 64];
+        name_bytes[..name.len()].copy_from_slice(&name);
+        self.set_string(CMD_DEVICE_NAME, 0, Encoding::UTF16LE, name_bytes);
+
+        let system_info = SystemInfo::new(RwBytes::new(vec![0; 42]));
+        system_info.lcd_width(Some(240));
+        system_info.lcd_height(Some(135));
+        system_info.lcd_refresh_rate(Some(60));
+        system_info.vid(Some(0x8089));
+        system_info.pid(Some(0x0009));
+        self.set_entry(CMD_SYSTEM_INFO, 0, system_info.into_vec());
+
+        let device_config = DeviceConfig::new(RwBytes::new(vec![0; 38]));
+        device_config.display_width(Some(240));
+        device_config.display_height(Some(135));
+        self.set_entry(CMD_DEVICE_CONFIG, 0, device_config.into_vec());
+
+        let rf_config = RFConfig::new(RwBytes::new(vec![0; 16]));
+        rf_config.rf_addr(Some(0x12345678));
+        self.set_entry(CMD_RF_CONFIG, 0, rf_config.into_vec());
+
+        for index in 0..Self::KEY_COUNT {
+            let key_info = KeyInfo::new(RwBytes::new(vec![0; 48]));
+            key_info.valid(Some(0x01));
+            key_info.key_site_x(Some(index as u16 * 20));
+            key_info.key_width(Some(18));
+            key_info.key_height(Some(18));
+            if let Some(key_data) = key_info.key_data(0, None) {
+                key_data.key_mode(Some(0x01));
+                key_data.key_val(Some(vec![0x00, 0x00, 0x04 + index, 0x00]));
+            }
+            self.set_entry(CMD_KEY_INFO, index, key_info.into_vec());
+
+            let led_info = LEDInfo::new(RwBytes::new(vec![0; 48]));
+            led_info.valid(Some(0x01));
+            led_info.led_site_x(Some(index as u16 * 20));
+            self.set_entry(CMD_LED_INFO, index, led_info.into_vec());
+
+            let analog = AnalogKeyInfo2::new(RwBytes::new(vec![0; 104]));
+            analog.stroke(Some(40));
+            analog.rt_mode(Some(0x01));
+            analog.trigger_level(Some(1200));
+            analog.release_level(Some(1000));
+            self.set_entry(AnalogKeyInfo2::CMD.unwrap(), index, analog.into_vec());
+        }
+
+        let color_table = ColorTable::new(RwBytes::new(vec![0; 26]));
+        color_table.number_of_colors(Some(8));
+        self.set_entry(CMD_COLOR_TABLE, 0, color_table.into_vec());
+
+        for index in 0..2 {
+            let binding = AdvancedKeyBinding::new(RwBytes::new(vec![0; 48]));
+            binding.bind_key(Some(index));
+            self.set_entry(AdvancedKeyBinding::CMD.unwrap(), index, binding.into_vec());
+        }
+
+        let led_effect = LedEffect::new(RwBytes::new(vec![0; 48]));
+        led_effect.enabled(Some(0x01));
+        led_effect.brightness(Some(0x80));
+        self.set_entry(CMD_LED_EFFECT, 0, led_effect.into_vec());
+
+        self.set_entry(GamePadCfg::CMD.unwrap(), 0, vec![0; 56]);
+
+        let ambient = AmbientLED::new(RwBytes::new(vec![0; 36]));
+        ambient.led_count(Some(16));
+        self.set_entry(AmbientLED::CMD.unwrap(), 0, ambient.into_vec());
+
+        self.set_entry(CMD_HALL_50UM, 0, vec![0; 32]);
+        self.set_entry(CMD_HALL_50UM, 1, vec![0; 32]);
+        self.set_entry(CMD_KEY_PHYSICAL_STATUS, 0, vec![0; 4]);
+        self.set_entry(CMD_LED_STATUS, 0, vec![0; 16]);
+
+        // 字符串区各用一种编码，末尾补 0 到固定长度
+        for (cmd, index, encoding, text) in [
+            (CMD_STRING, 0, Encoding::UTF16LE, "Sayo"),
+            (CMD_STRING, 1, Encoding::GB18030, "宏"),
+            (CMD_SCRIPT_NAME, 0, Encoding::UTF16LE, "script"),
+        ] {
+            let mut bytes = RwBytes::from_str(encoding, text).into_vec();
+            bytes.resize(32, 0);
+            self.set_string(cmd, index, encoding, bytes);
+        }
+
+        // 三个屏幕图层各两条文字绘制数据
+        for layer in [ScreenLayer::Bootup, ScreenLayer::Main, ScreenLayer::Sleep] {
+            let cmd = layer as u8;
+            for index in 0..2 {
+                let draw = LCDDrawData::new(RwBytes::new(vec![0; 40]));
+                draw.data_type(Some(4));
+                draw.site_x(Some(index as i16 * 60));
+                draw.site_y(Some((cmd - 0x20) as i16 * 20));
+                draw.color(Some(0xFFFF));
+                draw.text(Some(format!("layer {:02X}", cmd)));
+                self.set_entry(cmd, index, draw.into_vec());
+            }
+        }
+
+        self.set_region(CMD_SCRIPT, 0, 4096, Vec::new());
+        self.set_region(CMD_DISPLAY_ASSETS, 0, 8192, Vec::new());
+    }
+
+    pub fn set_identity(&self, vid: u16, pid: u16, product_name: &str) {
+        let mut state = lock(&self.state);
+        state.vid = vid;
+        state.pid = pid;
+        state.product_name = product_name.to_string();
+    }
+
+    pub fn set_report_ids(&self, report_ids: Vec<u8>) {
+        lock(&self.state).report_ids = report_ids;
+    }
+
+    pub fn set_entry(&self, cmd: u8, index: u8, bytes: Vec<u8>) {
+        lock(&self.state)
+            .entries
+            .insert((cmd, index), Entry { bytes, status: ResponseStatus::End });
+    }
+
+    pub fn set_string(&self, cmd: u8, index: u8, encoding: Encoding, bytes: Vec<u8>) {
+        lock(&self.state).entries.insert(
+            (cmd, index),
+            Entry {
+                bytes,
+                status: ResponseStatus::from(encoding),
+            },
+        );
+    }
+
+    pub fn entry(&self, cmd: u8, index: u8) -> Option<Vec<u8>> {
+        lock(&self.state)
+            .entries
+            .get(&(cmd, index))
+            .map(|entry| entry.bytes.clone())
+    }
+
+    pub fn remove_entry(&self, cmd: u8, index: u8) {
+        lock(&self.state).entries.remove(&(cmd, index));
+    }
+
+    // 可寻址区域（脚本 0x1A、显示资源 0x20），data 不足 capacity 时补 0
+    pub fn set_region(&self, cmd: u8, index: u8, capacity: u32, mut data: Vec<u8>) {
+        data.resize(capacity as usize, 0x00);
+        lock(&self.state)
+            .regions
+            .insert((cmd, index), Region { capacity, data });
+    }
+
+    pub fn region(&self, cmd: u8, index: u8) -> Option<Vec<u8>> {
+        lock(&self.state)
+            .regions
+            .get(&(cmd, index))
+            .map(|region| region.data.clone())
+    }
+
+    // 对只读命令的写操作返回 0x3E
+    pub fn set_read_only(&self, cmd: u8, read_only: bool) {
+        let mut state = lock(&self.state);
+        if read_only {
+            state.read_only.insert(cmd);
+        } else {
+            state.read_only.remove(&cmd);
+        }
+    }
+
+    // 丢弃该命令的请求，不做任何应答
+    pub fn set_silent(&self, cmd: u8, silent: bool) {
+        let mut state = lock(&self.state);
+        if silent {
+            state.silent.insert(cmd);
+        } else {
+            state.silent.remove(&cmd);
+        }
+    }
+
+    pub fn has_report_id(&self, report_id: u8) -> bool {
+        lock(&self.state).report_ids.contains(&report_id)
+    }
+
+    fn broadcast_report_id(&self) -> u8 {
+        match self.has_report_id(REPORT_ID_MAIN) {
+            true => REPORT_ID_MAIN,
+            false => REPORT_ID_BOOTUP,
+        }
+    }
+
+    // 处理主机发来的一包报告，返回设备应答的报告（可能为空或多包）
+    pub fn handle_report(&self, mut report: Vec<u8>) -> Vec<Vec<u8>> {
+        if report.len() < HEADER_SIZE {
+            return Vec::new();
+        }
+        let header = HidReportHeader::new(RwBytes::new(report[0..HEADER_SIZE].to_vec()));
+        let (Some(report_id), Some(echo), Some(cmd), Some(index), Some(status), Some(len)) = (
+            header.report_id(None),
+            header.echo(None),
+            header.cmd(None),
+            header.index(None),
+            header.status(None),
+            header.len(None),
+        ) else {
+            return Vec::new();
+        };
+        if !self.has_report_id(report_id) || max_package_len(report_id).is_err() {
+            return Vec::new();
+        }
+        if lock(&self.state).silent.contains(&cmd) {
+            return Vec::new();
+        }
+        if len as usize + 4 > report.len() || (len as usize) < 4 {
+            return self.respond(report_id, echo, cmd, index, ResponseStatus::TooShort, &[]);
+        }
+
+        let packet_crc = report[2] as u16 | (report[3] as u16) << 8;
+        report[2] = 0;
+        report[3] = 0;
+        if get_crc16(&report) != packet_crc {
+            return self.respond(report_id, echo, cmd, index, ResponseStatus::CrcError, &[]);
+        }
+
+        let body = &report[HEADER_SIZE..len as usize + 4];
+        let handle = (report_id, echo, cmd, index);
+        let payload = {
+            let mut state = lock(&self.state);
+            if status == ResponseStatus::Continue {
+                state.pending.entry(handle).or_default().extend_from_slice(body);
+                return Vec::new();
+            }
+            let mut payload = state.pending.remove(&handle).unwrap_or_default();
+            payload.extend_from_slice(body);
+            payload
+        };
+
+        let (status, data) = self.execute(report_id, cmd, index, status, payload);
+        self.respond(report_id, echo, cmd, index, status, &data)
+    }
+
+    // 生成一条广播报告（echo 0x00，cmd 0xFF），records 为若干 BroadCastData 拼接
+    pub fn broadcast_report(&self, records: &[u8]) -> Vec<Vec<u8>> {
+        self.respond(self.broadcast_report_id(), 0x00, 0xFF, 0x00, ResponseStatus::End, records)
+    }
+
+    fn respond(
+        &self,
+        report_id: u8,
+        echo: u8,
+        cmd: u8,
+        index: u8,
+        status: ResponseStatus,
+        data: &[u8],
+    ) -> Vec<Vec<u8>> {
+        let report_len = match report_id {
+            REPORT_ID_BOOTUP => REPORT_LEN_21,
+            _ => REPORT_LEN_22,
+        };
+        match encode_frames(report_id, echo, cmd, index, status, data) {
+            Ok(mut frames) => {
+                for frame in frames.iter_mut() {
+                    frame.resize(report_len, 0x00);
+                }
+                frames
+            }
+            Err(_) => Vec::new(),
+        }
+    }
+
+    fn execute(
+        &self,
+        report_id: u8,
+        cmd: u8,
+        index: u8,
+        status: ResponseStatus,
+        payload: Vec<u8>,
+    ) -> (ResponseStatus, Vec<u8>) {
+        match cmd {
+            CMD_SAVE_ALL | CMD_REBOOT | CMD_LOCK | CMD_UNLOCK => (ResponseStatus::End, Vec::new()),
+            CMD_SCRIPT | CMD_DISPLAY_ASSETS => self.execute_region(report_id, cmd, index, payload),
+            // 带参数的查询，载荷不是写入内容
+            CMD_HALL_50UM | CMD_KEY_PHYSICAL_STATUS | CMD_LED_STATUS => {
+                self.execute_entry(cmd, index, status, Vec::new())
+            }
+            _ => self.execute_entry(cmd, index, status, payload),
+        }
+    }
+
+    fn execute_entry(
+        &self,
+        cmd: u8,
+        index: u8,
+        status: ResponseStatus,
+        payload: Vec<u8>,
+    ) -> (ResponseStatus, Vec<u8>) {
+        let mut state = lock(&self.state);
+        let read_only = state.read_only.contains(&cmd);
+        let Some(entry) = state.entries.get_mut(&(cmd, index)) else {
+            return match state.entries.keys().any(|(c, _)| *c == cmd) {
+                true => (ResponseStatus::IndexMissing, Vec::new()),
+                false => (ResponseStatus::UnknownCmd, Vec::new()),
+            };
+        };
+        if payload.is_empty() {
+            return (entry.status, entry.bytes.clone());
+        }
+        if read_only {
+            return (ResponseStatus::ReadOnly, Vec::new());
+        }
+        if entry.status != ResponseStatus::End {
+            // 字符串按主机给出的编码整体替换
+            if status.is_string_encoding() {
+                entry.status = status;
+            }
+            entry.bytes = payload;
+            return (entry.status, entry.bytes.clone());
+        }
+        if payload.len() > entry.bytes.len() {
+            return (ResponseStatus::TooLong, Vec::new());
+        }
+        if payload.len() < entry.bytes.len() {
+            return (ResponseStatus::TooShort, Vec::new());
+        }
+        entry.bytes = payload;
+        (ResponseStatus::End, entry.bytes.clone())
+    }
+
+    fn execute_region(
+        &self,
+        report_id: u8,
+        cmd: u8,
+        index: u8,
+        payload: Vec<u8>,
+    ) -> (ResponseStatus, Vec<u8>) {
+        if payload.len() < 4 {
+            return (ResponseStatus::TooShort, Vec::new());
+        }
+        let addr = u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]);
+        let chunk_len = max_package_len(report_id).unwrap_or(0).saturating_sub(4);
+
+        let mut state = lock(&self.state);
+        let read_only = state.read_only.contains(&cmd);
+        let Some(region) = state.regions.get_mut(&(cmd, index)) else {
+            return (ResponseStatus::IndexMissing, Vec::new());
+        };
+        // 越界地址返回区域大小，主机用 0xFFFFFFFF 查询长度
+        if addr >= region.capacity {
+            return (ResponseStatus::TooLong, region.capacity.to_le_bytes().to_vec());
+        }
+        if !addr.is_multiple_of(4) {
+            return (ResponseStatus::Alignment, Vec::new());
+        }
+
+        let addr_bytes = addr.to_le_bytes().to_vec();
+        let begin = addr as usize;
+        if payload.len() == 4 {
+            let end = std::cmp::min(begin + chunk_len, region.capacity as usize);
+            let mut res = addr_bytes;
+            res.extend_from_slice(&region.data[begin..end]);
+            return (ResponseStatus::End, res);
+        }
+        if read_only {
+            return (ResponseStatus::ReadOnly, Vec::new());
+        }
+        let data = &payload[4..];
+        if begin + data.len() > region.capacity as usize {
+            return (ResponseStatus::TooLong, Vec::new());
+        }
+        region.data[begin..begin + data.len()].copy_from_slice(data);
+        (ResponseStatus::End, addr_bytes)
+    }
+}
+
+// 虚拟 HID 总线：管理多个 VirtualDevice 并实现 Transport
+pub struct VirtualBus {
+    devices: Mutex<HashMap<u128, VirtualDevice>>,
+    report_listeners: Mutex<HashMap<u128, Vec<ReportListener>>>,
+    connection_listeners: Mutex<Vec<ConnectionListener>>,
+}
+
+impl VirtualBus {
+    pub fn new() -> Self {
+        VirtualBus {
+            devices: Mutex::new(HashMap::new()),
+            report_listeners: Mutex::new(HashMap::new()),
+            connection_listeners: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn device(&self, uuid: u128) -> Option<VirtualDevice> {
+        lock(&self.devices).get(&uuid).cloned()
+    }
+
+    // 插入设备并通知连接监听器
+    pub async fn attach(&self, device: VirtualDevice) {
+        let uuid = device.uuid;
+        lock(&self.devices).insert(uuid, device);
+        let listeners = lock(&self.connection_listeners).clone();
+        for listener in listeners {
+            listener.call(uuid, true).await;
+        }
+    }
+
+    pub async fn detach(&self, uuid: u128) {
+        lock(&self.devices).remove(&uuid);
+        let listeners = lock(&self.connection_listeners).clone();
+        for listener in listeners {
+            listener.call(uuid, false).await;
+        }
+    }
+
+    pub async fn emit_broadcast(&self, uuid: u128, records: &[u8]) -> DeviceResult<()> {
+        let device = self.device(uuid).ok_or(DeviceError::DeviceNotFound(uuid))?;
+        let reports = device.broadcast_report(records);
+        self.deliver(uuid, reports).await;
+        Ok(())
+    }
+
+    async fn deliver(&self, uuid: u128, reports: Vec<Vec<u8>>) {
+        let listeners = lock(&self.report_listeners)
+            .get(&uuid)
+            .cloned()
+            .unwrap_or_default();
+        for report in reports {
+            for listener in &listeners {
+                listener.call(uuid, report.clone()).await;
+            }
+        }
+    }
+}
+
+impl Default for VirtualBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Transport for VirtualBus {
+    fn init(&self) -> TransportFuture<'_, DeviceResult<()>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn request_device(&self, _filter: Vec<(u16, Option<u16>)>) -> TransportFuture<'_, DeviceResult<()>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn device_list(&self) -> DeviceResult<Vec<u128>> {
+        Ok(lock(&self.devices).keys().cloned().collect())
+    }
+
+    fn sub_connection_changed(&self, listener: ConnectionListener) -> TransportFuture<'_, DeviceResult<()>> {
+        lock(&self.connection_listeners).push(listener);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn unsub_connection_changed(&self, _listener: ConnectionListener) -> TransportFuture<'_, DeviceResult<()>> {
+        // SafeCallback2 无法比较，直接清空
+        lock(&self.connection_listeners).clear();
+        Box::pin(async { Ok(()) })
+    }
+
+    fn send_report(&self, uuid: u128, report: Vec<u8>) -> TransportFuture<'_, DeviceResult<()>> {
+        Box::pin(async move {
+            let device = self.device(uuid).ok_or(DeviceError::DeviceNotFound(uuid))?;
+            let responses = device.handle_report(report);
+            self.deliver(uuid, responses).await;
+            Ok(())
+        })
+    }
+
+    fn add_report_listener(&self, uuid: u128, listener: &ReportListener) -> TransportFuture<'_, DeviceResult<()>> {
+        lock(&self.report_listeners)
+            .entry(uuid)
+            .or_default()
+            .push(listener.clone());
+        Box::pin(async { Ok(()) })
+    }
+
+    fn remove_report_listener(&self, uuid: u128, _listener: &ReportListener) -> TransportFuture<'_, DeviceResult<()>> {
+        // 每个设备只注册一个监听器，移除时整体清理
+        lock(&self.report_listeners).remove(&uuid);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn has_report_id(&self, uuid: u128, report_id: u8) -> bool {
+        match self.device(uuid) {
+            Some(device) => device.has_report_id(report_id),
+            None => false,
+        }
+    }
+
+    fn vid(&self, uuid: u128) -> Option<u16> {
+        self.device(uuid).map(|device| lock(&device.state).vid)
+    }
+
+    fn pid(&self, uuid: u128) -> Option<u16> {
+        self.device(uuid).map(|device| lock(&device.state).pid)
+    }
+
+    fn product_name(&self, uuid: u128) -> Option<String> {
+        self.device(uuid)
+            .map(|device| lock(&device.state).product_name.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cancellation::CancellationToken;
+    use crate::capabilities::Capabilities;
+    use crate::context::SayoContext;
+    use crate::report_codec::{encode_report, RequestOptions};
+    use pollster::block_on;
+
+    fn header_of(report: &[u8]) -> HidReportHeader {
+        HidReportHeader::new(RwBytes::new(report[0..HEADER_SIZE].to_vec()))
+    }
+
+    #[test]
+    fn test_status_codes() {
+        let device = VirtualDevice::new(1);
+
+        let request = encode_report(0x22, 0x13, 0x10, 0x00, &KeyInfo::empty()).unwrap();
+        let response = device.handle_report(request[0].clone());
+        assert_eq!(response.len(), 1);
+        assert_eq!(header_of(&response[0]).status(None), Some(ResponseStatus::End));
+
+        let request = encode_report(0x22, 0x13, 0x10, 0x40, &KeyInfo::empty()).unwrap();
+        let response = device.handle_report(request[0].clone());
+        assert_eq!(header_of(&response[0]).status(None), Some(ResponseStatus::IndexMissing));
+
+        let request = encode_report(0x22, 0x13, 0x09, 0x00, &ByteArray::empty()).unwrap();
+        let response = device.handle_report(request[0].clone());
+        assert_eq!(header_of(&response[0]).status(None), Some(ResponseStatus::UnknownCmd));
+
+        let mut request = encode_report(0x22, 0x13, 0x10, 0x00, &KeyInfo::empty()).unwrap();
+        request[0][2] ^= 0xFF;
+        let response = device.handle_report(request[0].clone());
+        assert_eq!(header_of(&response[0]).status(None), Some(ResponseStatus::CrcError));
+
+        let len_query = SayoScriptPacket::new(RwBytes::new(vec![0xFF; 4]));
+        let request = encode_report(0x22, 0x13, CMD_SCRIPT, 0x00, &len_query).unwrap();
+        let response = device.handle_report(request[0].clone());
+        assert_eq!(header_of(&response[0]).status(None), Some(ResponseStatus::TooLong));
+        assert_eq!(&response[0][8..12], &4096u32.to_le_bytes());
+    }
+
+    #[test]
+    fn test_multi_packet_request() {
+        let device = VirtualDevice::new(2);
+        device.set_report_ids(vec![REPORT_ID_BOOTUP]);
+        let analog = AnalogKeyInfo2::new(RwBytes::new(vec![0; 104]));
+        analog.trigger_level(Some(1500));
+        let request = encode_report(0x21, 0x13, 0x1C, 0x01, &analog).unwrap();
+        assert!(request.len() > 1);
+
+        let mut responses = Vec::new();
+        for report in request {
+            responses.extend(device.handle_report(report));
+        }
+        assert_eq!(responses.len(), 2);
+        assert_eq!(header_of(&responses[0]).status(None), Some(ResponseStatus::Continue));
+        let stored = AnalogKeyInfo2::new(RwBytes::new(device.entry(0x1C, 0x01).unwrap()));
+        assert_eq!(stored.trigger_level(None), Some(1500));
+    }
+
+    #[test]
+    fn test_analog_key_versions() {
+        let uuid = 0x5A10_0000_0000_0000_0000_0000_0000_0021;
+        let bus = Arc::new(VirtualBus::new());
+        let context = SayoContext::new(bus.clone());
+        block_on(context.init()).expect("init");
+        let device = VirtualDevice::new(uuid);
+        let info = DeviceInfo::new(RwBytes::new(device.entry(CMD_DEVICE_INFO, 0).unwrap()));
+        info.ver(Some(110));
+        device.set_entry(CMD_DEVICE_INFO, 0, info.into_vec());
+        let v1 = AnalogKeyInfo::new(RwBytes::new(vec![0; 96]));
+        // 旧固件以 50 微米为一档
+        v1.bytes.u8(2, Some(24));
+        v1.bytes.u8(3, Some(20));
+        for index in 0..VirtualDevice::KEY_COUNT {
+            device.remove_entry(AnalogKeyInfo2::CMD.unwrap(), index);
+            device.set_entry(AnalogKeyInfo::CMD.unwrap(), index, v1.into_vec());
+        }
+        block_on(bus.attach(device.clone()));
+        let api = context.device(uuid);
+
+        let keys = block_on(api.get_analog_keys()).expect("analog keys");
+        assert_eq!(keys.len(), VirtualDevice::KEY_COUNT as usize);
+        assert_eq!(keys[0].trigger_level(None), Some(1200));
+        assert_eq!(keys[0].release_level(None), Some(1000));
+        keys[1].trigger_level(Some(1500));
+        block_on(api.set_analog_key(1, &keys[1])).expect("set analog key");
+        assert_eq!(device.entry(AnalogKeyInfo::CMD.unwrap(), 1).unwrap()[2], 30);
+        // 写回时取最近的一档
+        keys[2].trigger_level(Some(1230));
+        block_on(api.set_analog_key(2, &keys[2])).expect("set analog key");
+        assert_eq!(device.entry(AnalogKeyInfo::CMD.unwrap(), 2).unwrap()[2], 25);
+
+        // 120 起 v1 为 0.01mm；api_list 未列出 0x1C 时仍使用 0x14
+        info.ver(Some(130));
+        info.api_list(Some(vec![AnalogKeyInfo::CMD.unwrap(), 0, 0, 0, 0, 0, 0, 0]));
+        device.set_entry(CMD_DEVICE_INFO, 0, info.into_vec());
+        block_on(api.get_device_info()).expect("device info");
+        let key = block_on(api.get_analog_key(1)).expect("analog key");
+        assert_eq!(key.trigger_level(None), Some(300));
+        key.trigger_level(Some(1500));
+        let key = block_on(api.set_analog_key(1, &key)).expect("set analog key");
+        assert_eq!(device.entry(AnalogKeyInfo::CMD.unwrap(), 1).unwrap()[2], 125);
+        assert_eq!(key.trigger_level(None), Some(1500));
+        // 写回时取最近的一档：1mm 以内 10 微米，以上 20 微米
+        for (um, stored) in [(567, 570), (1234, 1240), (1229, 1220)] {
+            key.trigger_level(Some(um));
+            let key = block_on(api.set_analog_key(1, &key)).expect("set analog key");
+            assert_eq!(key.trigger_level(None), Some(stored));
+        }
+    }
+
+    #[test]
+    fn test_capability_probe_timeout() {
+        let uuid = 0x5A10_0000_0000_0000_0000_0000_0000_0020;
+        let bus = Arc::new(VirtualBus::new());
+        let context = SayoContext::new(bus.clone());
+        block_on(context.init()).expect("init");
+        let device = VirtualDevice::new(uuid);
+        device.set_silent(CMD_DEVICE_INFO, true);
+        block_on(bus.attach(device));
+        let options = RequestOptions {
+            timeout_ms: 200,
+            ..Default::default()
+        };
+        let api = context.device(uuid).with_options(options);
+
+        // 第一个请求探测一次能力，超时后照常发出
+        assert!(block_on(api.get_device_name()).is_ok());
+        assert_eq!(api.cached_capabilities(), None);
+        // 同一连接内不再为能力检查等待超时
+        let started = std::time::Instant::now();
+        assert!(block_on(api.get_system_info()).is_ok());
+        assert!(started.elapsed() < std::time::Duration::from_millis(150));
+
+        // 连接时的预读仍在等待应答，shutdown 取消它而不是等到超时
+        let started = std::time::Instant::now();
+        block_on(context.shutdown());
+        assert!(started.elapsed() < std::time::Duration::from_millis(150));
+    }
+
+    #[test]
+    fn test_device_api_end_to_end() {
+        let uuid = 0x5A10_0000_0000_0000_0000_0000_0000_0001;
+        let bus = Arc::new(VirtualBus::new());
+        let context = SayoContext::new(bus.clone());
+        block_on(context.init()).expect("init");
+        let device = VirtualDevice::new(uuid);
+        block_on(bus.attach(device.clone()));
+        let api = context.device(uuid);
+        block_on(api.active_mode()).expect("active mode");
+
+        let info = block_on(api.get_device_info()).expect("device info");
+        assert_eq!(info.model_code(None), Some(0x0106));
+
+        let keys = block_on(api.get_key_infos()).expect("key infos");
+        assert_eq!(keys.len(), VirtualDevice::KEY_COUNT as usize);
+
+        let (name, _) = block_on(api.get_device_name()).expect("device name");
+        assert_eq!(name, "Sayo Virtual");
+
+        let script = SayoScriptContent::new(RwBytes::new((0..200).map(|i| i as u8).collect()));
+        block_on(api.set_script(0, &script, 0, |_| Box::pin(async { true }))).expect("set script");
+        let (len, read_back) = block_on(api.get_script(0)).expect("script");
+        assert_eq!(len, 4096);
+        assert_eq!(read_back.bytes.into_vec()[..200], script.bytes.into_vec()[..]);
+
+        let options = RequestOptions {
+            timeout_ms: 200,
+            report_id: Some(REPORT_ID_BOOTUP),
+            echo: Some(0x31),
+            ..Default::default()
+        };
+        let analog = block_on(api.with_options(options).get_analog_key_info2(1)).expect("analog");
+        assert_eq!(analog.trigger_level(None), Some(1200));
+
+        let session = api.session().expect("session");
+        let echo = session.echo();
+        assert_ne!(echo, api.echo());
+        let info = block_on(session.get_device_info()).expect("session device info");
+        assert_eq!(info.model_code(None), Some(0x0106));
+        assert_ne!(api.session().expect("second session").echo(), echo);
+
+        device.set_report_ids(vec![REPORT_ID_MAIN]);
+        let started = std::time::Instant::now();
+        let err = block_on(api.with_options(options).get_device_info()).unwrap_err();
+        assert!(matches!(err, DeviceError::ReceiveTimeout));
+        assert!(started.elapsed() < std::time::Duration::from_secs(2));
+        // 枚举不再叠加额外的重试，retries 为 0 时一个超时即失败
+        let started = std::time::Instant::now();
+        let err = block_on(api.with_options(options).get_key_infos()).unwrap_err();
+        assert!(matches!(err, DeviceError::ReceiveTimeout));
+        assert!(started.elapsed() < std::time::Duration::from_millis(600));
+        device.set_report_ids(vec![REPORT_ID_BOOTUP, REPORT_ID_MAIN]);
+
+        let forced = RequestOptions {
+            report_id: Some(0x30),
+            ..options
+        };
+        let script = SayoScriptContent::new(RwBytes::new(vec![0; 8]));
+        let err = block_on(api.with_options(forced).set_script(0, &script, 0, |_| {
+            Box::pin(async { true })
+        }))
+        .unwrap_err();
+        assert!(matches!(err, DeviceError::InvalidData(_)));
+
+        // 窗口传输只按 transfer_retransmits 重传，不再乘上 retries
+        device.set_silent(CMD_SCRIPT, true);
+        let lossy = RequestOptions {
+            timeout_ms: 100,
+            retries: 3,
+            transfer_window: 128,
+            transfer_retransmits: 1,
+            ..options
+        };
+        let started = std::time::Instant::now();
+        let err = block_on(api.with_options(lossy).set_addressable_data::<SayoScriptPacket>(
+            0,
+            RwBytes::new(vec![0; 8]),
+            0,
+            |_| Box::pin(async { true }),
+        ))
+        .unwrap_err();
+        assert!(matches!(err, DeviceError::TransferIncomplete(ref report) if report.retransmitted == report.packets));
+        assert!(started.elapsed() < std::time::Duration::from_millis(600));
+        device.set_silent(CMD_SCRIPT, false);
+
+        // 进度回调中止时带回已传输的部分
+        let err = block_on(api.set_addressable_data::<SayoScriptPacket>(
+            0,
+            RwBytes::new(vec![0; 8]),
+            0,
+            |_| Box::pin(async { false }),
+        ))
+        .unwrap_err();
+        let DeviceError::TransferCancelled(report) = err else {
+            panic!("expected TransferCancelled, got {:?}", err);
+        };
+        assert!(!report.is_complete());
+        assert!(report.unconfirmed.iter().map(|range| range.len()).sum::<usize>() < report.total_bytes);
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let err = block_on(api.with_cancellation(token).get_key_infos()).unwrap_err();
+        assert!(matches!(err, DeviceError::Cancelled));
+
+        device.remove_entry(GamePadCfg::CMD.unwrap(), 0);
+        let err = block_on(api.get_gamepad_cfg()).unwrap_err();
+        assert!(err.is_unsupported());
+        assert_eq!(err.status(), Some(ResponseStatus::UnknownCmd));
+        block_on(api.reboot()).expect("reboot");
+
+        // api_list 声明后未列出的命令不再发出
+        let info = DeviceInfo::new(RwBytes::new(device.entry(CMD_DEVICE_INFO, 0).unwrap()));
+        info.api_list(Some(vec![CMD_KEY_INFO, CMD_DEVICE_NAME, 0, 0, 0, 0, 0, 0]));
+        device.set_entry(CMD_DEVICE_INFO, 0, info.into_vec());
+        let capabilities = Capabilities::from_device_info(&block_on(api.get_device_info()).unwrap());
+        assert_eq!(api.cached_capabilities(), Some(capabilities));
+        assert_eq!(block_on(api.get_key_infos()).expect("key infos").len(), keys.len());
+        let started = std::time::Instant::now();
+        let err = block_on(api.with_options(options).get_gamepad_cfg()).unwrap_err();
+        assert!(matches!(err, DeviceError::Unsupported { cmd } if Some(cmd) == GamePadCfg::CMD));
+        assert!(err.is_unsupported());
+        assert!(started.elapsed() < std::time::Duration::from_millis(100));
+
+        block_on(bus.detach(uuid));
+        let err = block_on(api.get_device_info()).unwrap_err();
+        assert!(err.is_disconnected());
+    }
+}
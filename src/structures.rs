@@ -2,9 +2,11 @@ use encoding_rs::GB18030;
 use std::cell::Cell;
 
 use super::byte_converter::{Encoding, RwBytes};
+use super::device_constants::ANALOG_KEY_V2_VERSION;
+use super::report_codec::ResponseStatus;
 
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 
 pub struct ByteArray {
     pub bytes: RwBytes,
@@ -16,7 +18,7 @@ impl ByteArray {
 }
 
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 
 pub struct HidReportHeader {
     pub bytes: RwBytes,
@@ -71,20 +73,20 @@ impl HidReportHeader {
         }
     }
 
-    pub fn status(&self, value: Option<u8>) -> Option<u8> {
+    pub fn status(&self, value: Option<ResponseStatus>) -> Option<ResponseStatus> {
         if let Some(value) = value {
             // write
             let (_, len) = self
                 .sta_len(None)
                 .expect("sta_len not found in HidReportHeader");
-            self.sta_len(Some((value, len)));
+            self.sta_len(Some((u8::from(value), len)));
             return Some(value);
         } else {
             //read
             let (sta, _) = self
                 .sta_len(None)
                 .expect("sta_len not found in HidReportHeader");
-            return Some(sta);
+            return Some(ResponseStatus::from(sta));
         }
     }
 
@@ -98,7 +100,7 @@ impl HidReportHeader {
 }
 
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 
 pub struct StringContent {
     pub encoding_byte: Cell<Option<u8>>,
@@ -218,7 +220,7 @@ impl StringContent {
 }
 
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 
 pub struct DeviceInfo {
     pub bytes: RwBytes,
@@ -280,7 +282,7 @@ impl DeviceInfo {
 }
 
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 
 pub struct SystemInfo {
     pub bytes: RwBytes,
@@ -299,10 +301,7 @@ impl SystemInfo {
     }
 
     pub fn cfg_selection(&self, value: Option<u8>) -> Option<u8> {
-        let byte = self
-            .bytes
-            .u8(5, None)
-            .expect("cfg_selection not found in SystemInfo");
+        let byte = self.bytes.u8(5, None)?;
         match value {
             Some(value) => {
                 //write
@@ -367,7 +366,7 @@ impl SystemInfo {
 }
 
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 
 pub struct DeviceConfig {
     pub bytes: RwBytes,
@@ -503,7 +502,7 @@ impl DeviceConfig {
 }
 
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 
 pub struct RFConfig {
     pub bytes: RwBytes,
@@ -563,7 +562,7 @@ impl RFConfig {
 }
 
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 
 pub struct MonkeyGpios {
     pub bytes: RwBytes,
@@ -615,7 +614,7 @@ impl MonkeyGpios {
 }
 
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 
 pub struct KeyData {
     pub bytes: RwBytes,
@@ -645,7 +644,7 @@ impl KeyData {
 }
 
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 
 pub struct KeyInfo {
     pub bytes: RwBytes,
@@ -722,7 +721,7 @@ impl KeyInfo {
 }
 
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 
 pub struct LedData {
     pub bytes: RwBytes,
@@ -862,7 +861,7 @@ impl LedData {
 }
 
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 
 pub struct LEDInfo {
     pub bytes: RwBytes,
@@ -920,7 +919,7 @@ impl LEDInfo {
 }
 
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 
 pub struct SayoColorData {
     pub bytes: RwBytes,
@@ -942,7 +941,7 @@ impl SayoColorData {
 }
 
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 
 pub struct ColorTable {
     pub bytes: RwBytes,
@@ -972,7 +971,7 @@ impl ColorTable {
 }
 
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 
 pub struct TouchSensitivity {
     pub bytes: RwBytes,
@@ -996,7 +995,7 @@ impl TouchSensitivity {
 }
 
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 
 pub struct AnalogKeyInfo {
     pub bytes: RwBytes,
@@ -1089,7 +1088,7 @@ impl AnalogKeyInfo {
 }
 
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 
 pub struct SayoScriptContent {
     pub bytes: RwBytes,
@@ -1101,24 +1100,39 @@ impl SayoScriptContent {
 }
 
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 
 pub struct SayoScriptPacket {
     pub bytes: RwBytes,
 }
 
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 
 pub struct AnalogKeyInfo2 {
     pub bytes: RwBytes,
 }
+
+// 微米换算为档位数，取最近的一档
+fn um_to_steps(um: u16, step: u16) -> u16 {
+    ((um as u32 + step as u32 / 2) / step as u32) as u16
+}
+
+// 120 起 v1 以 0.01mm 计，超过 1mm 的部分按 0.02mm 一档存储（见 AnalogKeyInfo::_codecode_level）
+fn um_to_v1_level(um: u16) -> u16 {
+    match um > 1000 {
+        true => 100 + 2 * um_to_steps(um - 1000, 20),
+        false => um_to_steps(um, 10),
+    }
+}
+
 impl AnalogKeyInfo2 {
+    // 行程单位为微米，v1 的单位随固件版本变化，见 ANALOG_KEY_V2_VERSION
     pub fn from_v1(v1: &mut AnalogKeyInfo, firmware_version: u16) -> Self {
         let bytes = RwBytes::new(vec![0; 104]);
         let res = AnalogKeyInfo2 { bytes };
         res.raw_data(v1.raw_data(None));
-        res.raw_um(if firmware_version < 120 {
+        res.raw_um(if firmware_version < ANALOG_KEY_V2_VERSION {
             Some(
                 (v1.raw_level(None)
                     .expect("Can not get raw_level in AnalogKeyInfo2::from_v1")
@@ -1139,53 +1153,53 @@ impl AnalogKeyInfo2 {
         res.stroke(Some(80));
         res.rt_mode(Some(0x01));
         res.switch_type(Some(0x00));
-        res.trigger_level(if firmware_version < 120 {
+        res.trigger_level(if firmware_version < ANALOG_KEY_V2_VERSION {
             match v1.bytes.u8(2, None) {
                 Some(value) => Some((value as u16) * 50),
                 None => None,
             }
         } else {
-            v1.trigger_level(None)
+            v1.trigger_level(None).map(|value| value * 10)
         });
-        res.release_level(if firmware_version < 120 {
+        res.release_level(if firmware_version < ANALOG_KEY_V2_VERSION {
             match v1.bytes.u8(3, None) {
                 Some(value) => Some((value as u16) * 50),
                 None => None,
             }
         } else {
-            v1.release_level(None)
+            v1.release_level(None).map(|value| value * 10)
         });
-        res.rapid_trigger_top(if firmware_version < 120 {
+        res.rapid_trigger_top(if firmware_version < ANALOG_KEY_V2_VERSION {
             match v1.bytes.u8(4, None) {
                 Some(value) => Some((value as u16) * 50),
                 None => None,
             }
         } else {
-            v1.rapid_trigger_top(None)
+            v1.rapid_trigger_top(None).map(|value| value * 10)
         });
-        res.rapid_trigger_area(if firmware_version < 120 {
+        res.rapid_trigger_area(if firmware_version < ANALOG_KEY_V2_VERSION {
             match v1.bytes.u8(5, None) {
                 Some(value) => Some((value as u16) * 50),
                 None => None,
             }
         } else {
-            v1.rapid_trigger_area(None)
+            v1.rapid_trigger_area(None).map(|value| value * 10)
         });
-        res.rapid_trigger_level(if firmware_version < 120 {
+        res.rapid_trigger_level(if firmware_version < ANALOG_KEY_V2_VERSION {
             match v1.bytes.u8(6, None) {
                 Some(value) => Some((value as u16) * 50),
                 None => None,
             }
         } else {
-            v1.rapid_trigger_level(None)
+            v1.rapid_trigger_level(None).map(|value| value * 10)
         });
-        res.rapid_release_level(if firmware_version < 120 {
+        res.rapid_release_level(if firmware_version < ANALOG_KEY_V2_VERSION {
             match v1.bytes.u8(7, None) {
                 Some(value) => Some((value as u16) * 50),
                 None => None,
             }
         } else {
-            v1.rapid_release_level(None)
+            v1.rapid_release_level(None).map(|value| value * 10)
         });
         res.bytes
             .vec(24, Some(80), v1.bytes.vec(16, Some(80), None));
@@ -1196,7 +1210,7 @@ impl AnalogKeyInfo2 {
             bytes: RwBytes::new(vec![0; 96]),
         };
         res.raw_data(self.raw_data(None));
-        res.raw_um(if firmware_version < 120 {
+        res.raw_um(if firmware_version < ANALOG_KEY_V2_VERSION {
             Some(
                 (self
                     .raw_um(None)
@@ -1214,56 +1228,56 @@ impl AnalogKeyInfo2 {
             },
             None => None,
         });
-        if firmware_version < 120 {
+        if firmware_version < ANALOG_KEY_V2_VERSION {
             res.bytes.u8(
                 2,
                 match self.trigger_level(None) {
-                    Some(value) => Some((value / 50) as u8),
+                    Some(value) => Some(um_to_steps(value, 50) as u8),
                     None => None,
                 },
             );
             res.bytes.u8(
                 3,
                 match self.release_level(None) {
-                    Some(value) => Some((value / 50) as u8),
+                    Some(value) => Some(um_to_steps(value, 50) as u8),
                     None => None,
                 },
             );
             res.bytes.u8(
                 4,
                 match self.rapid_trigger_top(None) {
-                    Some(value) => Some((value / 50) as u8),
+                    Some(value) => Some(um_to_steps(value, 50) as u8),
                     None => None,
                 },
             );
             res.bytes.u8(
                 5,
                 match self.rapid_trigger_area(None) {
-                    Some(value) => Some((value / 50) as u8),
+                    Some(value) => Some(um_to_steps(value, 50) as u8),
                     None => None,
                 },
             );
             res.bytes.u8(
                 6,
                 match self.rapid_trigger_level(None) {
-                    Some(value) => Some((value / 50) as u8),
+                    Some(value) => Some(um_to_steps(value, 50) as u8),
                     None => None,
                 },
             );
             res.bytes.u8(
                 7,
                 match self.rapid_release_level(None) {
-                    Some(value) => Some((value / 50) as u8),
+                    Some(value) => Some(um_to_steps(value, 50) as u8),
                     None => None,
                 },
             );
         } else {
-            res.trigger_level(self.trigger_level(None));
-            res.release_level(self.release_level(None));
-            res.rapid_trigger_top(self.rapid_trigger_top(None));
-            res.rapid_trigger_area(self.rapid_trigger_area(None));
-            res.rapid_trigger_level(self.rapid_trigger_level(None));
-            res.rapid_release_level(self.rapid_release_level(None));
+            res.trigger_level(self.trigger_level(None).map(um_to_v1_level));
+            res.release_level(self.release_level(None).map(um_to_v1_level));
+            res.rapid_trigger_top(self.rapid_trigger_top(None).map(um_to_v1_level));
+            res.rapid_trigger_area(self.rapid_trigger_area(None).map(um_to_v1_level));
+            res.rapid_trigger_level(self.rapid_trigger_level(None).map(um_to_v1_level));
+            res.rapid_release_level(self.rapid_release_level(None).map(um_to_v1_level));
         }
         if self.bytes.len() >= 104 {
             res.bytes
@@ -1349,7 +1363,7 @@ impl AnalogKeyInfo2 {
 
 
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct AdvancedKeyBinding {
     pub bytes: RwBytes,
 }
@@ -1479,7 +1493,7 @@ impl AdvancedKeyBinding {
 // }
 
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 
 pub struct TriggerKeyboardHid {
     pub bytes: RwBytes,
@@ -1499,7 +1513,7 @@ impl TriggerKeyboardHid {
 }
 
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 
 pub struct TriggerMouseHid {
     pub bytes: RwBytes,
@@ -1523,7 +1537,7 @@ impl TriggerMouseHid {
 }
 
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 
 pub struct TriggerMeidaHid {
     pub bytes: RwBytes,
@@ -1535,7 +1549,7 @@ impl TriggerMeidaHid {
 }
 
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 
 pub struct DisplayData {
     pub bytes: RwBytes, //4 bytes alignment
@@ -1623,7 +1637,7 @@ impl DisplayData {
 }
 
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 
 pub struct DisplayAssets {
     pub bytes: RwBytes,
@@ -1649,14 +1663,14 @@ impl DisplayAssets {
             let packet_len = match DisplayData::packet_len(&self.bytes, len as u32) {
                 Some(packet_len) => packet_len,
                 None => {
-                    println!("DisplayAssets::datas: packet_len is None");
+                    log::trace!("DisplayAssets::datas: packet_len is None");
                     break;
                 }
             };
             let bytes = match self.bytes.ref_at(len, packet_len as usize) {
                 Some(bytes) => bytes,
                 None => {
-                    println!("DisplayAssets::datas: ref bytes is None");
+                    log::trace!("DisplayAssets::datas: ref bytes is None");
                     break;
                 }
             };
@@ -1684,7 +1698,7 @@ impl DisplayAssets {
 }
 
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 
 pub struct DisplayAssetsPacket {
     pub bytes: RwBytes,
@@ -1809,7 +1823,7 @@ impl LCDInfo {
 }
 
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 
 pub struct LCDDrawData {
     pub bytes: RwBytes,
@@ -1870,7 +1884,7 @@ impl LCDDrawData {
 }
 
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 
 pub struct ScreenBuffer {
     pub bytes: RwBytes,
@@ -1886,7 +1900,7 @@ impl ScreenBuffer {
 }
 
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 
 pub struct LedEffect {
     pub bytes: RwBytes,
@@ -2080,7 +2094,7 @@ impl LedEffect {
 }
 
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 
 pub struct GamePadCfg {
     pub bytes: RwBytes,
@@ -2274,7 +2288,7 @@ impl GamePadCfg {
 // }
 
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct AmbientLED {
     pub bytes: RwBytes,
 }
@@ -2481,6 +2495,12 @@ impl BroadCastData {
             2
         } else if tp >= 0xC0 && tp < 0xE0 {
             4
+        } else if tp == 0xE1 {
+            // 行程广播的长度字节是按键数，每键 2 字节
+            match self.bytes.u8(1, None) {
+                Some(keys) => keys.checked_mul(2)?.checked_add(1)?,
+                None => return None,
+            }
         } else {
             match self.bytes.u8(1, None) {
                 Some(len) => len,
@@ -2507,7 +2527,7 @@ impl BroadCastData {
         let mut len = match self.data_len() {
             Some(len) => Some(len as usize),
             None => {
-                println!("BroadCastData::data: len is None");
+                log::trace!("BroadCastData::data: len is None");
                 return None;
             }
         };
@@ -2518,7 +2538,7 @@ impl BroadCastData {
             }
             Some(false) => 1,
             None => {
-                println!("BroadCastData::data: should_skip_first_byte is None");
+                log::trace!("BroadCastData::data: should_skip_first_byte is None");
                 return None;
             }
         };
@@ -2615,7 +2635,7 @@ impl std::fmt::Debug for BroadCastData {
     }
 }
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 
 pub struct BroadCast {
     pub bytes: RwBytes,
@@ -2628,7 +2648,7 @@ impl BroadCast {
             let bytes = match self.bytes.ref_at(i, self.bytes.len() - i) {
                 Some(bytes) => bytes,
                 None => {
-                    println!("BroadCast::data: ref bytes is None");
+                    log::trace!("BroadCast::data: ref bytes is None");
                     break;
                 }
             };
@@ -2636,29 +2656,24 @@ impl BroadCast {
             let data_len = match data.len() {
                 Some(len) => len as usize,
                 None => {
-                    println!("BroadCast::data: len is None");
+                    log::trace!("BroadCast::data: len is None");
                     break;
                 }
             };
             let bytes = match self.bytes.ref_at(i, data_len) {
                 Some(bytes) => bytes,
                 None => {
-                    println!("BroadCast::data: ref bytes is None");
+                    log::trace!("BroadCast::data: ref bytes is None");
                     break;
                 }
             };
             let data = BroadCastData { bytes };
             i += data_len;
             if data.data_type(None) == Some(0x00) {
-                println!("BroadCast::data: end");
+                log::trace!("BroadCast::data: end");
                 break;
             }
-            let tp = data.data_type(None);
             res.push(data);
-            if tp == Some(0xE1) {
-                // my boss said, just skip the rest of data
-                break;
-            }
         }
         Some(res)
     }
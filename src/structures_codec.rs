@@ -4,32 +4,7 @@ use crate::structures::*;
 use std::cell::Cell;
 //use crate::debug_log;
 
-// pub(in crate::api) fn debug_decode(header: &mut HidReportHeader, bytes: &[u8]) {
-//     let bytes = RwBytes::new(bytes.to_vec());
-//     match header.cmd(None).expect("Bad Report Header") {
-//         0x00 => debug_log!("{:?}", DeviceInfo::new(bytes)),
-//         0x01 => debug_log!("{:?}", StringContent::new(bytes)),
-//         0x02 => debug_log!("{:?}", SystemInfo::new(bytes)),
-//         0x03 => debug_log!("{:?}", OptionalBytes::new(bytes)),
-//         0x04 => debug_log!("{:?}", WirelessConfig::new(bytes)),
-//         0x0E => debug_log!("{:?}", AdvancedSystemConfig::new(bytes)),
-//         0x10 => debug_log!("{:?}", KeyInfo::new(bytes)),
-//         0x11 => debug_log!("{:?}", LEDInfo::new(bytes)),
-//         0x12 => debug_log!("{:?}", ColorTable::new(bytes)),
-//         0x13 => debug_log!("{:?}", TouchSensitivity::new(bytes)),
-//         0x14 => debug_log!("{:?}", MagAxisInfo::new(bytes)),
-//         0x1F => match header.index(None).expect("Bad Report Header") {
-//             0x00 => debug_log!("{:?}", TriggerKeyboardHid::new(bytes)),
-//             0x01 => debug_log!("{:?}", TriggerMouseHid::new(bytes)),
-//             0x02 => debug_log!("{:?}", TriggerMeidaHid::new(bytes)),
-//             _ => (),
-//         },
-//         0x20 => debug_log!("{:?}", DisplayAssets::new(bytes)),
-//         0x21 => debug_log!("{:?}", LcdDrawData::new(bytes)),
-//         0xFF => debug_log!("{:?}", BroadCast::new(bytes)),
-//         _ => debug_log!("Unknown command: {:02X?}", header.cmd(None)),
-//     }
-// }
+// 按 cmd 解码报告见 dissector::dissect_payload
 
 pub trait CodecableHidPackage {
     const CMD: Option<u8> = None;
@@ -275,6 +250,28 @@ impl CodecableHidPackage for KeyData {
     }
 }
 
+impl CodecableHidPackage for LedData {
+    const CMD: Option<u8> = None;
+
+    fn new(bytes: RwBytes) -> Self {
+        LedData { bytes }
+    }
+
+    fn into_vec(&self) -> Vec<u8> {
+        self.bytes.clone().into_vec()
+    }
+    fn empty() -> Self {
+        LedData {
+            bytes: RwBytes::new(vec![]),
+        }
+    }
+
+    fn deep_clone(&self) -> Self {
+        let bytes = self.bytes.deep_clone();
+        Self { bytes }
+    }
+}
+
 impl CodecableHidPackage for LEDInfo {
     const CMD: Option<u8> = Some(0x11);
 
@@ -297,6 +294,28 @@ impl CodecableHidPackage for LEDInfo {
     }
 }
 
+impl CodecableHidPackage for SayoColorData {
+    const CMD: Option<u8> = None;
+
+    fn new(bytes: RwBytes) -> Self {
+        SayoColorData { bytes }
+    }
+
+    fn into_vec(&self) -> Vec<u8> {
+        self.bytes.clone().into_vec()
+    }
+    fn empty() -> Self {
+        SayoColorData {
+            bytes: RwBytes::new(vec![]),
+        }
+    }
+
+    fn deep_clone(&self) -> Self {
+        let bytes = self.bytes.deep_clone();
+        Self { bytes }
+    }
+}
+
 impl CodecableHidPackage for ColorTable {
     const CMD: Option<u8> = Some(0x12);
 
@@ -711,6 +730,28 @@ impl CodecableHidPackage for AmbientLED {
     }
 }
 
+impl CodecableHidPackage for BroadCastData {
+    const CMD: Option<u8> = None;
+
+    fn new(bytes: RwBytes) -> Self {
+        BroadCastData { bytes }
+    }
+
+    fn into_vec(&self) -> Vec<u8> {
+        self.bytes.clone().into_vec()
+    }
+    fn empty() -> Self {
+        BroadCastData {
+            bytes: RwBytes::new(vec![]),
+        }
+    }
+
+    fn deep_clone(&self) -> Self {
+        let bytes = self.bytes.deep_clone();
+        Self { bytes }
+    }
+}
+
 impl CodecableHidPackage for BroadCast {
     const CMD: Option<u8> = Some(0xFF);
 
@@ -0,0 +1,203 @@
+// 可寻址数据（脚本 0x1A、显示资源 0x20）的分包传输：
+// 同时在途的包数受窗口限制，失败的包按轮次单独重传，最后报告未确认的地址范围。
+
+use std::ops::Range;
+
+use futures::{Future, StreamExt, stream};
+
+use crate::cancellation::CancellationToken;
+use crate::device_error_handling::{DeviceError, DeviceResult};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransferChunk {
+    pub addr: u32,
+    pub data: Vec<u8>,
+}
+
+impl TransferChunk {
+    pub fn range(&self) -> Range<u32> {
+        self.addr..self.addr + self.data.len() as u32
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TransferReport {
+    pub total_bytes: usize,
+    pub packets: usize,
+    // 重传的包数（同一包多次重传累计）
+    pub retransmitted: usize,
+    // 未被设备确认的地址范围，已合并相邻范围
+    pub unconfirmed: Vec<Range<u32>>,
+    pub last_error: Option<Box<DeviceError>>,
+}
+
+impl TransferReport {
+    pub fn is_complete(&self) -> bool {
+        self.unconfirmed.is_empty()
+    }
+
+    pub fn unconfirmed_bytes(&self) -> usize {
+        self.unconfirmed
+            .iter()
+            .map(|range| (range.end - range.start) as usize)
+            .sum()
+    }
+}
+
+// 按窗口发送所有包，失败的包最多重传 retransmits 轮；
+// 令牌取消或 on_progress 返回 false 后不再发出新包，剩余包计入未确认范围
+pub(crate) async fn run_windowed<S, SF, P, PF>(
+    chunks: Vec<TransferChunk>,
+    window: usize,
+    retransmits: u8,
+    cancel: &CancellationToken,
+    send: S,
+    on_progress: P,
+) -> TransferReport
+where
+    S: Fn(TransferChunk) -> SF,
+    SF: Future<Output = DeviceResult<()>>,
+    P: Fn(f32) -> PF,
+    PF: Future<Output = bool>,
+{
+    let mut report = TransferReport {
+        total_bytes: chunks.iter().map(|chunk| chunk.data.len()).sum(),
+        packets: chunks.len(),
+        ..Default::default()
+    };
+    let mut pending = chunks;
+    let mut confirmed = 0;
+    let mut round = 0;
+
+    loop {
+        let mut failed = Vec::new();
+        let mut in_flight = stream::iter(pending.into_iter().map(|chunk| {
+            // buffer_unordered 按需取包，此处检查即为包间检查
+            let response = match cancel.is_cancelled() {
+                true => None,
+                false => Some(send(chunk.clone())),
+            };
+            async move {
+                match response {
+                    Some(response) => (chunk, response.await),
+                    None => (chunk, Err(DeviceError::Cancelled)),
+                }
+            }
+        }))
+        .buffer_unordered(window.max(1));
+
+        while let Some((chunk, res)) = in_flight.next().await {
+            match res {
+                Ok(_) => {
+                    confirmed += 1;
+                    if !on_progress(confirmed as f32 / report.packets as f32).await {
+                        cancel.cancel();
+                    }
+                }
+                Err(e) => {
+                    report.last_error = Some(Box::new(e));
+                    failed.push(chunk);
+                }
+            }
+        }
+
+        if failed.is_empty() || round >= retransmits || cancel.is_cancelled() {
+            report.unconfirmed = merge_ranges(failed.iter().map(|chunk| chunk.range()).collect());
+            return report;
+        }
+        round += 1;
+        report.retransmitted += failed.len();
+        pending = failed;
+    }
+}
+
+fn merge_ranges(mut ranges: Vec<Range<u32>>) -> Vec<Range<u32>> {
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<Range<u32>> = Vec::new();
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pollster::block_on;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_retransmit_and_unconfirmed_ranges() {
+        let chunks: Vec<TransferChunk> = (0..6)
+            .map(|i| TransferChunk {
+                addr: i * 52,
+                data: vec![i as u8; 52],
+            })
+            .collect();
+        let attempts = Mutex::new(Vec::new());
+        let report = block_on(run_windowed(
+            chunks,
+            3,
+            2,
+            &CancellationToken::new(),
+            |chunk| {
+                attempts.lock().unwrap().push(chunk.addr);
+                let tries = attempts
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .filter(|addr| **addr == chunk.addr)
+                    .count();
+                async move {
+                    match chunk.addr {
+                        // 第一次失败，重传成功
+                        52 if tries == 1 => Err(DeviceError::ReceiveTimeout),
+                        // 始终失败
+                        156 | 208 => Err(DeviceError::ReceiveTimeout),
+                        _ => Ok(()),
+                    }
+                }
+            },
+            |_| async { true },
+        ));
+
+        assert_eq!(report.packets, 6);
+        assert_eq!(report.total_bytes, 312);
+        assert_eq!(report.retransmitted, 3 + 2);
+        assert_eq!(report.unconfirmed, vec![156..260]);
+        assert_eq!(report.unconfirmed_bytes(), 104);
+        assert!(!report.is_complete());
+        assert_eq!(attempts.lock().unwrap().len(), 6 + 3 + 2);
+    }
+
+    #[test]
+    fn test_progress_false_cancels_remaining() {
+        let chunks: Vec<TransferChunk> = (0..10)
+            .map(|i| TransferChunk {
+                addr: i * 4,
+                data: vec![0; 4],
+            })
+            .collect();
+        let token = CancellationToken::new();
+        let sent = Mutex::new(0);
+        let report = block_on(run_windowed(
+            chunks,
+            1,
+            3,
+            &token,
+            |_| {
+                *sent.lock().unwrap() += 1;
+                async { Ok(()) }
+            },
+            |progress| async move { progress < 0.2 },
+        ));
+
+        assert!(token.is_cancelled());
+        assert_eq!(*sent.lock().unwrap(), 2);
+        assert_eq!(report.unconfirmed, vec![8..40]);
+        assert_eq!(report.retransmitted, 0);
+    }
+}
@@ -0,0 +1,209 @@
+// 传输层抽象：SayoDeviceApi 只通过 Transport 收发报告，
+// hid_rs 只是其中一种实现，测试时可以替换为内存或回放实现。
+
+use futures::Future;
+use once_cell::sync::Lazy;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+
+use hid_rs::{HidDevice, SafeCallback2};
+
+use crate::device_error_handling::{DeviceError, DeviceResult};
+
+// wasm 上 hid_rs 的 future 依赖 JS 对象，不满足 Send
+#[cfg(not(target_arch = "wasm32"))]
+pub type TransportFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+#[cfg(target_arch = "wasm32")]
+pub type TransportFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+/// 收到原始报告时调用 (uuid, report)，report 以 report id 开头
+pub type ReportListener = SafeCallback2<u128, Vec<u8>, ()>;
+/// 设备插拔时调用 (uuid, connected)
+pub type ConnectionListener = SafeCallback2<u128, bool, ()>;
+
+pub trait Transport: Send + Sync {
+    fn init(&self) -> TransportFuture<'_, DeviceResult<()>>;
+
+    fn request_device(&self, filter: Vec<(u16, Option<u16>)>) -> TransportFuture<'_, DeviceResult<()>>;
+
+    fn device_list(&self) -> DeviceResult<Vec<u128>>;
+
+    fn sub_connection_changed(&self, listener: ConnectionListener) -> TransportFuture<'_, DeviceResult<()>>;
+
+    fn unsub_connection_changed(&self, listener: ConnectionListener) -> TransportFuture<'_, DeviceResult<()>>;
+
+    fn send_report(&self, uuid: u128, report: Vec<u8>) -> TransportFuture<'_, DeviceResult<()>>;
+
+    fn add_report_listener(&self, uuid: u128, listener: &ReportListener) -> TransportFuture<'_, DeviceResult<()>>;
+
+    fn remove_report_listener(&self, uuid: u128, listener: &ReportListener) -> TransportFuture<'_, DeviceResult<()>>;
+
+    fn has_report_id(&self, uuid: u128, report_id: u8) -> bool;
+
+    fn vid(&self, uuid: u128) -> Option<u16>;
+
+    fn pid(&self, uuid: u128) -> Option<u16>;
+
+    fn product_name(&self, uuid: u128) -> Option<String>;
+}
+
+static TRANSPORT: Lazy<RwLock<Arc<dyn Transport>>> =
+    Lazy::new(|| RwLock::new(Arc::new(HidTransport)));
+
+// 替换全局传输层，需在 init_sayo_device 之前调用
+pub fn set_transport(transport: Arc<dyn Transport>) {
+    let mut guard = match TRANSPORT.write() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    *guard = transport;
+}
+
+pub fn transport() -> Arc<dyn Transport> {
+    match TRANSPORT.read() {
+        Ok(guard) => guard.clone(),
+        Err(poisoned) => poisoned.into_inner().clone(),
+    }
+}
+
+// 基于 hid_rs 的默认实现
+pub struct HidTransport;
+
+impl Transport for HidTransport {
+    fn init(&self) -> TransportFuture<'_, DeviceResult<()>> {
+        Box::pin(async move {
+            hid_rs::Hid::init_hid()
+                .await
+                .map(|_| ())
+                .map_err(|e| DeviceError::ConnectionFailed(format!("{:?}", e)))
+        })
+    }
+
+    fn request_device(&self, filter: Vec<(u16, Option<u16>)>) -> TransportFuture<'_, DeviceResult<()>> {
+        Box::pin(async move {
+            hid_rs::Hid::request_device(filter)
+                .await
+                .map(|_| ())
+                .map_err(|e| DeviceError::ConnectionFailed(format!("{:?}", e)))
+        })
+    }
+
+    fn device_list(&self) -> DeviceResult<Vec<u128>> {
+        hid_rs::Hid::get_device_list()
+            .map(|devices| devices.into_iter().map(|device| device.uuid).collect())
+            .map_err(|e| DeviceError::ConnectionFailed(format!("{:?}", e)))
+    }
+
+    fn sub_connection_changed(&self, listener: ConnectionListener) -> TransportFuture<'_, DeviceResult<()>> {
+        Box::pin(async move {
+            hid_rs::Hid::sub_connection_changed(listener)
+                .await
+                .map(|_| ())
+                .map_err(|e| DeviceError::ConnectionFailed(format!("{:?}", e)))
+        })
+    }
+
+    fn unsub_connection_changed(&self, listener: ConnectionListener) -> TransportFuture<'_, DeviceResult<()>> {
+        Box::pin(async move {
+            hid_rs::Hid::unsub_connection_changed(listener)
+                .await
+                .map(|_| ())
+                .map_err(|e| DeviceError::ConnectionFailed(format!("{:?}", e)))
+        })
+    }
+
+    fn send_report(&self, uuid: u128, report: Vec<u8>) -> TransportFuture<'_, DeviceResult<()>> {
+        Box::pin(async move {
+            let hid = HidDevice::from(uuid);
+            hid.send_report(report)
+                .await
+                .map(|_| ())
+                .map_err(|e| DeviceError::SendReportFailed(format!("{:?}", e)))
+        })
+    }
+
+    fn add_report_listener(&self, uuid: u128, listener: &ReportListener) -> TransportFuture<'_, DeviceResult<()>> {
+        let listener = listener.clone();
+        Box::pin(async move {
+            let hid = HidDevice::from(uuid);
+            hid.add_report_listener(&listener)
+                .await
+                .map(|_| ())
+                .map_err(|e| DeviceError::ConnectionFailed(format!("{:?}", e)))
+        })
+    }
+
+    fn remove_report_listener(&self, uuid: u128, listener: &ReportListener) -> TransportFuture<'_, DeviceResult<()>> {
+        let listener = listener.clone();
+        Box::pin(async move {
+            let hid = HidDevice::from(uuid);
+            hid.remove_report_listener(&listener)
+                .await
+                .map(|_| ())
+                .map_err(|e| DeviceError::ConnectionFailed(format!("{:?}", e)))
+        })
+    }
+
+    fn has_report_id(&self, uuid: u128, report_id: u8) -> bool {
+        HidDevice::from(uuid).has_report_id(report_id)
+    }
+
+    fn vid(&self, uuid: u128) -> Option<u16> {
+        HidDevice::from(uuid).vid().ok()
+    }
+
+    fn pid(&self, uuid: u128) -> Option<u16> {
+        HidDevice::from(uuid).pid().ok()
+    }
+
+    fn product_name(&self, uuid: u128) -> Option<String> {
+        match HidDevice::from(uuid).get_product_name() {
+            Ok(name) => name.clone(),
+            Err(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::SayoContext;
+    use crate::report_codec::encode_report;
+    use crate::simulator::{VirtualBus, VirtualDevice};
+    use crate::structures::KeyInfo;
+    use crate::structures_codec::CodecableHidPackage;
+    use pollster::block_on;
+    use std::sync::Mutex;
+
+    // 只有这个测试替换全局传输层，其他测试各自用 SayoContext::new 持有传输层
+    #[test]
+    fn test_global_transport() {
+        let uuid = 0x5A10_0000_0000_0000_0000_0000_0000_0101;
+        let bus = Arc::new(VirtualBus::new());
+        block_on(bus.attach(VirtualDevice::new(uuid)));
+        set_transport(bus.clone());
+        let transport = transport();
+        assert_eq!(transport.device_list().unwrap(), vec![uuid]);
+        assert!(transport.has_report_id(uuid, 0x22));
+        let devices = block_on(SayoContext::global().device_list()).unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].get_uuid(), uuid);
+
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let listener: ReportListener = SafeCallback2::new({
+            let received = received.clone();
+            move |_, report| {
+                received.lock().unwrap().push(report);
+                Box::pin(async {}) as Pin<Box<dyn Future<Output = ()> + Send + 'static>>
+            }
+        });
+        let request = encode_report(0x22, 0x13, 0x10, 0x00, &KeyInfo::empty()).unwrap();
+        block_on(transport.add_report_listener(uuid, &listener)).unwrap();
+        block_on(transport.send_report(uuid, request[0].clone())).unwrap();
+        assert_eq!(received.lock().unwrap().len(), 1);
+        block_on(transport.remove_report_listener(uuid, &listener)).unwrap();
+        block_on(transport.send_report(uuid, request[0].clone())).unwrap();
+        assert_eq!(received.lock().unwrap().len(), 1);
+        assert!(block_on(transport.send_report(uuid + 1, request[0].clone())).is_err());
+    }
+}
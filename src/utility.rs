@@ -1,3 +1,5 @@
+use std::sync::{Mutex, MutexGuard};
+
 #[cfg(all(not(target_arch = "wasm32"), not(target_os = "android")))]
 pub fn future_delay(milliseconds: u32) -> impl Future<Output = ()> {
     use futures_timer::Delay;
@@ -16,3 +18,53 @@ pub fn future_delay(milliseconds: u32) -> impl Future<Output = ()> {
     use std::time::Duration;
     Delay::new(Duration::from_millis(milliseconds.into()))
 }
+
+// 锁被毒化时照常取出数据：某个持锁方 panic 不应让之后的每次调用都跟着 panic
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+// 小写 hex，sep 为字节之间的分隔符
+pub(crate) fn hex(bytes: &[u8], sep: &str) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(sep)
+}
+
+// 与 hex 相反，长度为奇数或含非 hex 字符（包括多字节 UTF-8）时返回 None
+pub(crate) fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+pub(crate) fn invalid_data(msg: impl Into<String>) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg.into())
+}
+
+pub(crate) fn le_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        bytes.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+pub(crate) fn le_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+pub(crate) fn le_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        bytes.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}